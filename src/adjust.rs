@@ -0,0 +1,298 @@
+//! Brightness, contrast, gamma, saturation and hue adjustments.
+//!
+//! Tone adjustments go through a lookup table with one entry per channel
+//! value, so channels must be `u8` or `u16` (`TableChannel`).  They change the luma of YUV
+//! formats and the colour channels of RGB formats; alpha is left alone.
+//!
+//! Saturation and hue work on the chroma of YUV formats directly, rotating
+//! and scaling it about its neutral value.  RGB formats go through HSV.
+//! `Luma` has no colour and is left unchanged; other formats are rejected.
+
+use std::any::TypeId;
+use std::ops::{Deref, DerefMut};
+
+use super::{Channel, ChannelAccess, ColorHSV, ColorRGB, Format, Surface, TableChannel, ToneFormat};
+use super::{Luma, Yuv420p, Yuv422, Yuv422p, Yuv444};
+use super::tone::{apply_luts, is_rgb};
+
+/// A tone curve.  Values are normalised to `0.0..=1.0` and adjusted in
+/// the order of the fields.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Levels {
+    /// Slope of the curve about mid-grey; 1.0 leaves contrast unchanged.
+    pub contrast: f64,
+    /// Offset added after contrast, as a fraction of the channel range.
+    pub brightness: f64,
+    /// Exponent applied last as `value.powf(1.0 / gamma)`, so values
+    /// above 1.0 brighten mid-tones.
+    pub gamma: f64,
+}
+
+impl Default for Levels {
+    fn default() -> Levels {
+        Levels { contrast: 1.0, brightness: 0.0, gamma: 1.0 }
+    }
+}
+
+impl Levels {
+    /// The curve applied to one normalised value.
+    pub fn apply(&self, value: f64) -> f64 {
+        let value = (value - 0.5) * self.contrast + 0.5 + self.brightness;
+        value.clamp(0.0, 1.0).powf(1.0 / self.gamma)
+    }
+
+    /// The curve as a lookup table indexed by channel value.
+    pub fn lut<C: TableChannel>(&self) -> Vec<C> {
+        let max = C::max_value().to_f64().unwrap();
+        (0..C::LEVELS)
+            .map(|value| C::from_f64(self.apply(value as f64 / max) * max))
+            .collect()
+    }
+}
+
+/// Applies `levels` to every tone channel of `surface`.
+pub fn adjust_levels<M, C, S>(surface: &mut Surface<M, C, S>, levels: Levels)
+    where
+        M: ToneFormat<C>,
+        M::Pixel: ChannelAccess,
+        C: TableChannel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    let lut = levels.lut::<C>();
+    let luts = vec![lut; <M as ToneFormat<C>>::TONE_CHANNELS];
+    apply_luts(surface, &luts);
+}
+
+/// Applies a separate curve to each tone channel, e.g. R, G and B.  There
+/// must be one curve per tone channel: one for `Luma` and YUV formats,
+/// three for RGB formats.
+pub fn adjust_channel_levels<M, C, S>(surface: &mut Surface<M, C, S>, levels: &[Levels])
+    where
+        M: ToneFormat<C>,
+        M::Pixel: ChannelAccess,
+        C: TableChannel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    assert_eq!(levels.len(), <M as ToneFormat<C>>::TONE_CHANNELS, "one curve per tone channel is needed");
+    let luts: Vec<Vec<C>> = levels.iter().map(Levels::lut).collect();
+    apply_luts(surface, &luts);
+}
+
+/// Brightness offset as a fraction of the channel range.
+pub fn adjust_brightness<M, C, S>(surface: &mut Surface<M, C, S>, brightness: f64)
+    where
+        M: ToneFormat<C>,
+        M::Pixel: ChannelAccess,
+        C: TableChannel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    adjust_levels(surface, Levels { brightness, ..Levels::default() });
+}
+
+/// Contrast scaling about mid-grey.
+pub fn adjust_contrast<M, C, S>(surface: &mut Surface<M, C, S>, contrast: f64)
+    where
+        M: ToneFormat<C>,
+        M::Pixel: ChannelAccess,
+        C: TableChannel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    adjust_levels(surface, Levels { contrast, ..Levels::default() });
+}
+
+/// Gamma correction; values above 1.0 brighten mid-tones.
+pub fn adjust_gamma<M, C, S>(surface: &mut Surface<M, C, S>, gamma: f64)
+    where
+        M: ToneFormat<C>,
+        M::Pixel: ChannelAccess,
+        C: TableChannel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    adjust_levels(surface, Levels { gamma, ..Levels::default() });
+}
+
+/// The neutral chroma value and the channel maximum.
+fn chroma_range<C: Channel>() -> (f64, f64) {
+    match C::max_depth() {
+        Some(max) => ((max as f64 + 1.0) / 2.0, max as f64),
+        None => (0.5, 1.0),
+    }
+}
+
+/// Rotates and scales a chroma pair about neutral.
+struct ChromaTransform {
+    cos: f64,
+    sin: f64,
+    neutral: f64,
+    max: f64,
+}
+
+impl ChromaTransform {
+    fn new<C: Channel>(hue_degrees: f64, saturation: f64) -> ChromaTransform {
+        let (sin, cos) = hue_degrees.to_radians().sin_cos();
+        let (neutral, max) = chroma_range::<C>();
+        ChromaTransform { cos: cos * saturation, sin: sin * saturation, neutral, max }
+    }
+
+    #[inline]
+    fn apply<C: Channel>(&self, u: &mut C, v: &mut C) {
+        let cb = u.to_f64().unwrap() - self.neutral;
+        let cr = v.to_f64().unwrap() - self.neutral;
+        let clamp = |val: f64| C::from_f64((val + self.neutral).max(0.0).min(self.max));
+        *u = clamp(cb * self.cos - cr * self.sin);
+        *v = clamp(cb * self.sin + cr * self.cos);
+    }
+}
+
+/// Rotates hue by `hue_degrees` and scales saturation by `saturation`,
+/// where 0.0 removes all colour and 1.0 keeps it.  Positive rotations take
+/// red towards green.
+pub fn adjust_hue_saturation<M, C, S>(surface: &mut Surface<M, C, S>, hue_degrees: f64, saturation: f64)
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    let format = TypeId::of::<M>();
+    let (width, height) = (surface.width(), surface.height());
+    let pixels = width as usize * height as usize;
+
+    if format == TypeId::of::<Luma>() {
+        return;
+    }
+    if format == TypeId::of::<Yuv420p>() || format == TypeId::of::<Yuv422p>() {
+        let transform = ChromaTransform::new::<C>(hue_degrees, saturation);
+        let len = <M as Format<C>>::channel_data_size(width, height);
+        let chroma = &mut surface.as_storage_mut()[pixels..len];
+        let (us, vs) = chroma.split_at_mut(chroma.len() / 2);
+        for (u, v) in us.iter_mut().zip(vs.iter_mut()) {
+            transform.apply(u, v);
+        }
+        return;
+    }
+    if format == TypeId::of::<Yuv444>() || format == TypeId::of::<Yuv422>() {
+        // Yuv422 shares chroma between pixel pairs, so it is visited once
+        // per group rather than once per pixel.
+        let transform = ChromaTransform::new::<C>(hue_degrees, saturation);
+        let len = <M as Format<C>>::channel_data_size(width, height);
+        let (group, u, v) = if format == TypeId::of::<Yuv444>() { (3, 1, 2) } else { (4, 1, 3) };
+        for chunk in surface.as_storage_mut()[..len].chunks_mut(group) {
+            let (head, tail) = chunk.split_at_mut(v);
+            transform.apply(&mut head[u], &mut tail[0]);
+        }
+        return;
+    }
+
+    assert!(is_rgb::<M>(), "hue and saturation apply to Luma, YUV and RGB formats");
+    let (_, max) = chroma_range::<C>();
+    surface.map_in_place(|_, _, px| {
+        let norm = |index: usize| px.channel(index).to_f64().unwrap() / max;
+        let hsv = ColorHSV::from(ColorRGB::new_rgb(norm(0), norm(1), norm(2)));
+        let rgb = ColorRGB::from(ColorHSV::new_hsv(
+            (hsv.h + hue_degrees / 360.0).rem_euclid(1.0),
+            (hsv.s * saturation).min(1.0),
+            hsv.v));
+        px.map_channels(|index, channel| match index {
+            0 => C::from_f64(rgb.r * max),
+            1 => C::from_f64(rgb.g * max),
+            2 => C::from_f64(rgb.b * max),
+            _ => channel,
+        })
+    });
+}
+
+/// Scales colourfulness; 0.0 gives grey, 1.0 leaves the image unchanged.
+pub fn adjust_saturation<M, C, S>(surface: &mut Surface<M, C, S>, saturation: f64)
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    adjust_hue_saturation(surface, 0.0, saturation);
+}
+
+/// Rotates hue by `degrees`.
+pub fn rotate_hue<M, C, S>(surface: &mut Surface<M, C, S>, degrees: f64)
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    adjust_hue_saturation(surface, degrees, 1.0);
+}
+
+#[test]
+fn levels_lut() {
+    assert_eq!(Levels::default().lut::<u8>(), (0..=255).collect::<Vec<u8>>());
+
+    let brighter = Levels { brightness: 0.2, ..Levels::default() }.lut::<u8>();
+    assert_eq!((brighter[0], brighter[100], brighter[250]), (51, 151, 255));
+
+    let contrast = Levels { contrast: 2.0, ..Levels::default() }.lut::<u8>();
+    assert_eq!((contrast[0], contrast[60], contrast[192], contrast[255]), (0, 0, 255, 255));
+
+    let gamma = Levels { gamma: 2.0, ..Levels::default() }.lut::<u16>();
+    assert_eq!(gamma.len(), 65536);
+    assert_eq!(gamma[16384], 32768);
+}
+
+#[test]
+fn levels_touch_tone_channels_only() {
+    use super::{ColorRGBA, Rgb, Rgba};
+
+    let mut rgba: Surface<Rgba, u8, Vec<u8>> = Surface::new(1, 1, vec![10, 20, 30, 40]);
+    adjust_brightness(&mut rgba, 0.2);
+    let px: ColorRGBA<u8> = rgba.get_pixel(0, 0);
+    assert_eq!((px.r, px.g, px.b, px.a), (61, 71, 81, 40));
+
+    let mut yuv: Surface<Yuv420p, u8, Vec<u8>> = Surface::new(2, 2, vec![0, 60, 192, 255, 100, 200]);
+    adjust_contrast(&mut yuv, 2.0);
+    assert_eq!(&yuv.as_storage()[..], &[0, 0, 255, 255, 100, 200][..]);
+
+    let mut packed: Surface<Yuv422, u8, Vec<u8>> = Surface::new(2, 1, vec![100, 50, 200, 60]);
+    adjust_brightness(&mut packed, 0.2);
+    assert_eq!(&packed.as_storage()[..], &[151, 50, 251, 60][..]);
+
+    let mut rgb: Surface<Rgb, u8, Vec<u8>> = Surface::new(1, 1, vec![100, 100, 100]);
+    let identity = Levels::default();
+    adjust_channel_levels(&mut rgb, &[Levels { brightness: 0.2, ..identity }, identity, identity]);
+    assert_eq!(&rgb.as_storage()[..], &[151, 100, 100][..]);
+}
+
+#[test]
+fn hue_and_saturation() {
+    use super::Rgb;
+
+    let mut rgb: Surface<Rgb, u8, Vec<u8>> = Surface::new(2, 1, vec![255, 0, 0, 40, 80, 120]);
+    rotate_hue(&mut rgb, 120.0);
+    assert_eq!(&rgb.as_storage()[..3], &[0, 255, 0][..]);
+    adjust_saturation(&mut rgb, 0.0);
+    assert_eq!(&rgb.as_storage()[..], &[255, 255, 255, 120, 120, 120][..]);
+
+    let mut planar: Surface<Yuv420p, u8, Vec<u8>> = Surface::new(2, 2, vec![50, 50, 50, 50, 128 + 40, 128 - 20]);
+    adjust_saturation(&mut planar, 0.5);
+    assert_eq!(&planar.as_storage()[4..], &[148, 118][..]);
+    rotate_hue(&mut planar, 90.0);
+    assert_eq!(&planar.as_storage()[4..], &[138, 148][..]);
+
+    // Both pixels of a Yuv422 pair share one chroma sample, adjusted once.
+    let mut packed: Surface<Yuv422, u8, Vec<u8>> = Surface::new(2, 1, vec![50, 168, 60, 128]);
+    adjust_saturation(&mut packed, 0.5);
+    assert_eq!(&packed.as_storage()[..], &[50, 148, 60, 128][..]);
+
+    let mut yuv444: Surface<Yuv444, u8, Vec<u8>> = Surface::new(1, 1, vec![50, 168, 128]);
+    rotate_hue(&mut yuv444, 180.0);
+    assert_eq!(&yuv444.as_storage()[..], &[50, 88, 128][..]);
+}
+
+#[test]
+#[should_panic(expected = "hue and saturation apply to Luma, YUV and RGB formats")]
+fn hue_rejects_other_formats() {
+    use super::Hsv;
+
+    let mut hsv: Surface<Hsv, f64, Vec<f64>> = Surface::new_black(1, 1);
+    rotate_hue(&mut hsv, 90.0);
+}
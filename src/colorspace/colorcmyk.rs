@@ -0,0 +1,153 @@
+use super::{Channel, ChannelAccess, Pixel, ColorL, ColorRGB};
+use super::{normalised_rgb, rec601_luma, scale_channel};
+
+/// Cyan, magenta, yellow and black ink coverage.  Zero is bare paper and
+/// the channel maximum is full coverage, as in PAM's `CMYK` tuple type and
+/// TIFF's separated photometric interpretation.
+#[derive(Debug, Copy)]
+#[repr(C)]
+pub struct ColorCMYK<T> {
+    pub c: T,
+    pub m: T,
+    pub y: T,
+    pub k: T,
+}
+
+impl<T: Clone> Clone for ColorCMYK<T> {
+    fn clone(&self) -> ColorCMYK<T> {
+        ColorCMYK {
+            c: self.c.clone(),
+            m: self.m.clone(),
+            y: self.y.clone(),
+            k: self.k.clone(),
+        }
+    }
+}
+
+impl<T: Channel> ColorCMYK<T> {
+    pub fn new_cmyk(c: T, m: T, y: T, k: T) -> ColorCMYK<T> {
+        ColorCMYK { c, m, y, k }
+    }
+
+    /// Converts `rgb` using `separation`.
+    pub fn from_rgb(rgb: ColorRGB<T>, separation: Separation) -> ColorCMYK<T> {
+        let (r, g, b) = normalised_rgb(&rgb);
+        let (c, m, y, k) = rgb_to_cmyk(r, g, b, separation);
+        ColorCMYK::new_cmyk(scale_channel(c), scale_channel(m), scale_channel(y), scale_channel(k))
+    }
+}
+
+/// How RGB is separated into inks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Separation {
+    /// Cyan, magenta and yellow are the complements of red, green and
+    /// blue, and no black ink is used.
+    Naive,
+    /// Under-colour removal: this fraction of the grey component shared by
+    /// cyan, magenta and yellow is printed with black ink instead.  1.0
+    /// prints neutral greys with black alone.
+    UnderColorRemoval(f64),
+}
+
+/// Normalised RGB to normalised CMYK.
+pub fn rgb_to_cmyk(r: f64, g: f64, b: f64, separation: Separation) -> (f64, f64, f64, f64) {
+    let (c, m, y) = (1.0 - r, 1.0 - g, 1.0 - b);
+    let k = match separation {
+        Separation::Naive => 0.0,
+        Separation::UnderColorRemoval(amount) => amount.clamp(0.0, 1.0) * c.min(m).min(y),
+    };
+    (c - k, m - k, y - k, k)
+}
+
+/// Normalised CMYK to normalised RGB.  Black ink adds to the other inks,
+/// so this inverts `rgb_to_cmyk` for any separation; total coverage past
+/// 100% is clipped.
+pub fn cmyk_to_rgb(c: f64, m: f64, y: f64, k: f64) -> (f64, f64, f64) {
+    let channel = |ink: f64| 1.0 - (ink + k).min(1.0);
+    (channel(c), channel(m), channel(y))
+}
+
+/// Separates with full under-colour removal.
+impl<T: Channel> From<ColorRGB<T>> for ColorCMYK<T> {
+    fn from(rgb: ColorRGB<T>) -> ColorCMYK<T> {
+        ColorCMYK::from_rgb(rgb, Separation::UnderColorRemoval(1.0))
+    }
+}
+
+impl<T: Channel> From<ColorCMYK<T>> for ColorRGB<T> {
+    fn from(cmyk: ColorCMYK<T>) -> ColorRGB<T> {
+        let max = T::max_value().to_f64().unwrap();
+        let norm = |c: T| c.to_f64().unwrap() / max;
+        let (r, g, b) = cmyk_to_rgb(norm(cmyk.c), norm(cmyk.m), norm(cmyk.y), norm(cmyk.k));
+        ColorRGB::new_rgb(scale_channel(r), scale_channel(g), scale_channel(b))
+    }
+}
+
+impl<T> Pixel for ColorCMYK<T> where T: Channel+Copy {
+    type Channel = T;
+
+    fn white() -> Self {
+        ColorCMYK::new_cmyk(
+            Channel::min_value(),
+            Channel::min_value(),
+            Channel::min_value(),
+            Channel::min_value())
+    }
+
+    fn black() -> Self {
+        ColorCMYK::new_cmyk(
+            Channel::min_value(),
+            Channel::min_value(),
+            Channel::min_value(),
+            Channel::max_value())
+    }
+
+    fn luma(&self) -> ColorL<Self::Channel> {
+        rec601_luma(ColorRGB::from(*self))
+    }
+}
+
+impl<T> ChannelAccess for ColorCMYK<T> where T: Channel+Copy {
+    fn channel_count() -> usize { 4 }
+
+    fn channel(&self, index: usize) -> T {
+        match index {
+            0 => self.c,
+            1 => self.m,
+            2 => self.y,
+            3 => self.k,
+            _ => panic!("channel index {} out of range", index),
+        }
+    }
+
+    fn map_channels<F: FnMut(usize, T) -> T>(self, mut f: F) -> Self {
+        ColorCMYK {
+            c: f(0, self.c),
+            m: f(1, self.m),
+            y: f(2, self.y),
+            k: f(3, self.k),
+        }
+    }
+}
+
+#[test]
+fn cmyk_separations() {
+    assert_eq!(rgb_to_cmyk(1.0, 0.0, 0.5, Separation::Naive), (0.0, 1.0, 0.5, 0.0));
+    assert_eq!(rgb_to_cmyk(0.25, 0.25, 0.25, Separation::Naive), (0.75, 0.75, 0.75, 0.0));
+    assert_eq!(rgb_to_cmyk(0.25, 0.25, 0.25, Separation::UnderColorRemoval(1.0)), (0.0, 0.0, 0.0, 0.75));
+    assert_eq!(rgb_to_cmyk(0.5, 0.75, 1.0, Separation::UnderColorRemoval(0.5)), (0.5, 0.25, 0.0, 0.0));
+    assert_eq!(rgb_to_cmyk(0.0, 0.5, 0.5, Separation::UnderColorRemoval(0.5)), (0.75, 0.25, 0.25, 0.25));
+
+    for &separation in &[Separation::Naive, Separation::UnderColorRemoval(0.4), Separation::UnderColorRemoval(1.0)] {
+        for &(r, g, b) in &[(12u8, 200u8, 99u8), (255, 0, 128), (0, 0, 0), (255, 255, 255), (77, 77, 77)] {
+            let cmyk = ColorCMYK::from_rgb(ColorRGB::new_rgb(r, g, b), separation);
+            let back = ColorRGB::from(cmyk);
+            assert_eq!((back.r, back.g, back.b), (r, g, b));
+        }
+    }
+
+    let grey = ColorCMYK::from(ColorRGB::new_rgb(64u8, 64, 64));
+    assert_eq!((grey.c, grey.m, grey.y, grey.k), (0, 0, 0, 191));
+    assert_eq!(ColorCMYK::<u8>::black().luma().l, 0);
+    assert_eq!(ColorCMYK::<u8>::white().luma().l, 255);
+}
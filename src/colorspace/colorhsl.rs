@@ -0,0 +1,118 @@
+use super::{Channel, ChannelAccess, Pixel, ColorL, ColorRGB};
+use super::{normalised_rgb, rec601_luma, scale_channel};
+use super::colorhsv::{hue_of, from_hue_chroma};
+
+/// Hue, saturation and lightness, scaled like `ColorHSV`.
+#[derive(Debug, Copy)]
+#[repr(C)]
+pub struct ColorHSL<T> {
+    pub h: T,
+    pub s: T,
+    pub l: T,
+}
+
+impl<T: Clone> Clone for ColorHSL<T> {
+    fn clone(&self) -> ColorHSL<T> {
+        ColorHSL {
+            h: self.h.clone(),
+            s: self.s.clone(),
+            l: self.l.clone(),
+        }
+    }
+}
+
+impl<T: Channel> ColorHSL<T> {
+    pub fn new_hsl(h: T, s: T, l: T) -> ColorHSL<T> {
+        ColorHSL { h, s, l }
+    }
+}
+
+/// Normalised RGB to hue in turns, saturation and lightness.
+pub fn rgb_to_hsl(r: f64, g: f64, b: f64) -> (f64, f64, f64) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let lightness = (max + min) / 2.0;
+    let saturation = if delta == 0.0 { 0.0 } else { delta / (1.0 - (2.0 * lightness - 1.0).abs()) };
+    (hue_of(r, g, b, max, delta), saturation, lightness)
+}
+
+/// Hue in turns, saturation and lightness to normalised RGB.
+pub fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> (f64, f64, f64) {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    from_hue_chroma(hue, chroma, lightness - chroma / 2.0)
+}
+
+impl<T: Channel> From<ColorRGB<T>> for ColorHSL<T> {
+    fn from(rgb: ColorRGB<T>) -> ColorHSL<T> {
+        let (r, g, b) = normalised_rgb(&rgb);
+        let (h, s, l) = rgb_to_hsl(r, g, b);
+        ColorHSL::new_hsl(scale_channel(h), scale_channel(s), scale_channel(l))
+    }
+}
+
+impl<T: Channel> From<ColorHSL<T>> for ColorRGB<T> {
+    fn from(hsl: ColorHSL<T>) -> ColorRGB<T> {
+        let max = T::max_value().to_f64().unwrap();
+        let norm = |c: T| c.to_f64().unwrap() / max;
+        let (r, g, b) = hsl_to_rgb(norm(hsl.h), norm(hsl.s), norm(hsl.l));
+        ColorRGB::new_rgb(scale_channel(r), scale_channel(g), scale_channel(b))
+    }
+}
+
+impl<T> Pixel for ColorHSL<T> where T: Channel+Copy {
+    type Channel = T;
+
+    fn white() -> Self {
+        ColorHSL::new_hsl(
+            Channel::min_value(),
+            Channel::min_value(),
+            Channel::max_value())
+    }
+
+    fn black() -> Self {
+        ColorHSL::new_hsl(
+            Channel::min_value(),
+            Channel::min_value(),
+            Channel::min_value())
+    }
+
+    fn luma(&self) -> ColorL<Self::Channel> {
+        rec601_luma(ColorRGB::from(*self))
+    }
+}
+
+impl<T> ChannelAccess for ColorHSL<T> where T: Channel+Copy {
+    fn channel_count() -> usize { 3 }
+
+    fn channel(&self, index: usize) -> T {
+        match index {
+            0 => self.h,
+            1 => self.s,
+            2 => self.l,
+            _ => panic!("channel index {} out of range", index),
+        }
+    }
+
+    fn map_channels<F: FnMut(usize, T) -> T>(self, mut f: F) -> Self {
+        ColorHSL {
+            h: f(0, self.h),
+            s: f(1, self.s),
+            l: f(2, self.l),
+        }
+    }
+}
+
+#[test]
+fn hsl_round_trip() {
+    for &(r, g, b) in &[(1.0, 0.0, 0.0), (0.2, 0.6, 0.4), (0.9, 0.9, 0.1), (0.3, 0.3, 0.3), (0.1, 0.2, 0.8)] {
+        let (h, s, l) = rgb_to_hsl(r, g, b);
+        let (r2, g2, b2) = hsl_to_rgb(h, s, l);
+        assert!((r - r2).abs() < 1e-12 && (g - g2).abs() < 1e-12 && (b - b2).abs() < 1e-12);
+    }
+    assert_eq!(rgb_to_hsl(1.0, 0.0, 0.0), (0.0, 1.0, 0.5));
+    assert_eq!(rgb_to_hsl(1.0, 1.0, 1.0), (0.0, 0.0, 1.0));
+
+    let hsl = ColorHSL::from(ColorRGB::new_rgb(255u8, 127, 127));
+    assert_eq!((hsl.h, hsl.s, hsl.l), (0, 255, 191));
+}
@@ -0,0 +1,148 @@
+use super::{Channel, ChannelAccess, Pixel, ColorL, ColorRGB};
+use super::{normalised_rgb, rec601_luma, scale_channel};
+
+/// Hue, saturation and value.  All three channels span the channel range:
+/// hue is a fraction of a full turn starting at red, so 1/3 is green.
+#[derive(Debug, Copy)]
+#[repr(C)]
+pub struct ColorHSV<T> {
+    pub h: T,
+    pub s: T,
+    pub v: T,
+}
+
+impl<T: Clone> Clone for ColorHSV<T> {
+    fn clone(&self) -> ColorHSV<T> {
+        ColorHSV {
+            h: self.h.clone(),
+            s: self.s.clone(),
+            v: self.v.clone(),
+        }
+    }
+}
+
+impl<T: Channel> ColorHSV<T> {
+    pub fn new_hsv(h: T, s: T, v: T) -> ColorHSV<T> {
+        ColorHSV { h, s, v }
+    }
+}
+
+/// Normalised RGB to hue in turns, saturation and value.
+pub fn rgb_to_hsv(r: f64, g: f64, b: f64) -> (f64, f64, f64) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    (hue_of(r, g, b, max, delta), saturation, max)
+}
+
+/// The hue in turns shared by HSV and HSL.
+pub fn hue_of(r: f64, g: f64, b: f64, max: f64, delta: f64) -> f64 {
+    let sector = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    sector / 6.0
+}
+
+/// Normalised RGB from a hue in turns, chroma and the offset added to
+/// every channel.
+pub fn from_hue_chroma(hue: f64, chroma: f64, offset: f64) -> (f64, f64, f64) {
+    let sector = hue.rem_euclid(1.0) * 6.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    (r + offset, g + offset, b + offset)
+}
+
+/// Hue in turns, saturation and value to normalised RGB.
+pub fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> (f64, f64, f64) {
+    let chroma = value * saturation;
+    from_hue_chroma(hue, chroma, value - chroma)
+}
+
+impl<T: Channel> From<ColorRGB<T>> for ColorHSV<T> {
+    fn from(rgb: ColorRGB<T>) -> ColorHSV<T> {
+        let (r, g, b) = normalised_rgb(&rgb);
+        let (h, s, v) = rgb_to_hsv(r, g, b);
+        ColorHSV::new_hsv(scale_channel(h), scale_channel(s), scale_channel(v))
+    }
+}
+
+impl<T: Channel> From<ColorHSV<T>> for ColorRGB<T> {
+    fn from(hsv: ColorHSV<T>) -> ColorRGB<T> {
+        let max = T::max_value().to_f64().unwrap();
+        let norm = |c: T| c.to_f64().unwrap() / max;
+        let (r, g, b) = hsv_to_rgb(norm(hsv.h), norm(hsv.s), norm(hsv.v));
+        ColorRGB::new_rgb(scale_channel(r), scale_channel(g), scale_channel(b))
+    }
+}
+
+impl<T> Pixel for ColorHSV<T> where T: Channel+Copy {
+    type Channel = T;
+
+    fn white() -> Self {
+        ColorHSV::new_hsv(
+            Channel::min_value(),
+            Channel::min_value(),
+            Channel::max_value())
+    }
+
+    fn black() -> Self {
+        ColorHSV::new_hsv(
+            Channel::min_value(),
+            Channel::min_value(),
+            Channel::min_value())
+    }
+
+    fn luma(&self) -> ColorL<Self::Channel> {
+        rec601_luma(ColorRGB::from(*self))
+    }
+}
+
+impl<T> ChannelAccess for ColorHSV<T> where T: Channel+Copy {
+    fn channel_count() -> usize { 3 }
+
+    fn channel(&self, index: usize) -> T {
+        match index {
+            0 => self.h,
+            1 => self.s,
+            2 => self.v,
+            _ => panic!("channel index {} out of range", index),
+        }
+    }
+
+    fn map_channels<F: FnMut(usize, T) -> T>(self, mut f: F) -> Self {
+        ColorHSV {
+            h: f(0, self.h),
+            s: f(1, self.s),
+            v: f(2, self.v),
+        }
+    }
+}
+
+#[test]
+fn hsv_round_trip() {
+    for &(r, g, b) in &[(1.0, 0.0, 0.0), (0.2, 0.6, 0.4), (0.9, 0.9, 0.1), (0.3, 0.3, 0.3), (0.1, 0.2, 0.8)] {
+        let (h, s, v) = rgb_to_hsv(r, g, b);
+        let (r2, g2, b2) = hsv_to_rgb(h, s, v);
+        assert!((r - r2).abs() < 1e-12 && (g - g2).abs() < 1e-12 && (b - b2).abs() < 1e-12);
+    }
+    assert_eq!(rgb_to_hsv(0.0, 1.0, 0.0), (1.0 / 3.0, 1.0, 1.0));
+
+    let hsv = ColorHSV::from(ColorRGB::new_rgb(0u8, 0, 255));
+    assert_eq!((hsv.h, hsv.s, hsv.v), (170, 255, 255));
+    let rgb = ColorRGB::from(ColorHSV::new_hsv(0.5f64, 0.5, 0.8));
+    assert_eq!((rgb.r, rgb.g, rgb.b), (0.4, 0.8, 0.8));
+}
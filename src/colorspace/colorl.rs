@@ -1,6 +1,7 @@
-use super::{Channel, Pixel};
+use super::{Channel, ChannelAccess, Pixel};
 
 #[derive(Debug, Copy)]
+#[repr(C)]
 pub struct ColorL<T> {
     pub l: T,
 }
@@ -31,4 +32,21 @@ impl<T> Pixel for ColorL<T> where T: Channel+Copy {
     fn luma(&self) -> ColorL<Self::Channel> {
         ColorL::new_l(self.l)
     }
+}
+
+impl<T> ChannelAccess for ColorL<T> where T: Channel+Copy {
+    fn channel_count() -> usize { 1 }
+
+    fn channel(&self, index: usize) -> T {
+        match index {
+            0 => self.l,
+            _ => panic!("channel index {} out of range", index),
+        }
+    }
+
+    fn map_channels<F: FnMut(usize, T) -> T>(self, mut f: F) -> Self {
+        ColorL {
+            l: f(0, self.l),
+        }
+    }
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
-use super::{Channel, Pixel, ColorL};
+use super::{Channel, ChannelAccess, Pixel, ColorL};
 
 #[derive(Debug, Copy)]
+#[repr(C)]
 pub struct ColorLA<T> {
     pub l: T,
     pub a: T,
@@ -45,4 +46,23 @@ impl<T> Pixel for ColorLA<T> where T: Channel+Copy {
     fn luma(&self) -> ColorL<Self::Channel> {
         ColorL::new_l(self.l)
     }
+}
+
+impl<T> ChannelAccess for ColorLA<T> where T: Channel+Copy {
+    fn channel_count() -> usize { 2 }
+
+    fn channel(&self, index: usize) -> T {
+        match index {
+            0 => self.l,
+            1 => self.a,
+            _ => panic!("channel index {} out of range", index),
+        }
+    }
+
+    fn map_channels<F: FnMut(usize, T) -> T>(self, mut f: F) -> Self {
+        ColorLA {
+            l: f(0, self.l),
+            a: f(1, self.a),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,158 @@
+use super::{Channel, ChannelAccess, Pixel, ColorL, ColorRGB};
+use super::normalised_rgb;
+use super::colorxyz::{D65, rgb_to_xyz, xyz_to_rgb, linear_to_srgb};
+
+/// CIE L*a*b* relative to the D65 white point.  L runs from 0 to 100 and
+/// a and b are signed, so `f64` is the useful channel type.
+#[derive(Debug, Copy)]
+#[repr(C)]
+pub struct ColorLab<T> {
+    pub l: T,
+    pub a: T,
+    pub b: T,
+}
+
+impl<T: Clone> Clone for ColorLab<T> {
+    fn clone(&self) -> ColorLab<T> {
+        ColorLab {
+            l: self.l.clone(),
+            a: self.a.clone(),
+            b: self.b.clone(),
+        }
+    }
+}
+
+impl<T: Channel> ColorLab<T> {
+    pub fn new_lab(l: T, a: T, b: T) -> ColorLab<T> {
+        ColorLab { l, a, b }
+    }
+}
+
+impl ColorLab<f64> {
+    /// The CIE 1976 colour difference, the Euclidean distance in Lab.  A
+    /// difference of about 2.3 is just noticeable.
+    pub fn delta_e(&self, other: &ColorLab<f64>) -> f64 {
+        let (dl, da, db) = (self.l - other.l, self.a - other.a, self.b - other.b);
+        (dl * dl + da * da + db * db).sqrt()
+    }
+}
+
+const EPSILON: f64 = 6.0 / 29.0;
+
+fn lab_f(t: f64) -> f64 {
+    if t > EPSILON * EPSILON * EPSILON {
+        t.cbrt()
+    } else {
+        t / (3.0 * EPSILON * EPSILON) + 4.0 / 29.0
+    }
+}
+
+fn lab_f_inv(t: f64) -> f64 {
+    if t > EPSILON {
+        t * t * t
+    } else {
+        3.0 * EPSILON * EPSILON * (t - 4.0 / 29.0)
+    }
+}
+
+/// XYZ to L*a*b*.
+pub fn xyz_to_lab(xyz: [f64; 3]) -> [f64; 3] {
+    let (fx, fy, fz) = (lab_f(xyz[0] / D65[0]), lab_f(xyz[1] / D65[1]), lab_f(xyz[2] / D65[2]));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// L*a*b* to XYZ.
+pub fn lab_to_xyz(lab: [f64; 3]) -> [f64; 3] {
+    let fy = (lab[0] + 16.0) / 116.0;
+    let (fx, fz) = (fy + lab[1] / 500.0, fy - lab[2] / 200.0);
+    [lab_f_inv(fx) * D65[0], lab_f_inv(fy) * D65[1], lab_f_inv(fz) * D65[2]]
+}
+
+impl<T: Channel> From<ColorRGB<T>> for ColorLab<f64> {
+    fn from(rgb: ColorRGB<T>) -> ColorLab<f64> {
+        let (r, g, b) = normalised_rgb(&rgb);
+        let [l, a, b] = xyz_to_lab(rgb_to_xyz(r, g, b));
+        ColorLab::new_lab(l, a, b)
+    }
+}
+
+impl<T: Channel> From<ColorLab<f64>> for ColorRGB<T> {
+    fn from(lab: ColorLab<f64>) -> ColorRGB<T> {
+        let max = T::max_value().to_f64().unwrap();
+        let [r, g, b] = xyz_to_rgb(lab_to_xyz([lab.l, lab.a, lab.b]));
+        ColorRGB::new_rgb(T::from_f64(r * max), T::from_f64(g * max), T::from_f64(b * max))
+    }
+}
+
+impl<T> Pixel for ColorLab<T> where T: Channel+Copy {
+    type Channel = T;
+
+    fn white() -> Self {
+        ColorLab::new_lab(
+            T::from_f64(100.0),
+            T::from_f64(0.0),
+            T::from_f64(0.0))
+    }
+
+    fn black() -> Self {
+        ColorLab::new_lab(
+            T::from_f64(0.0),
+            T::from_f64(0.0),
+            T::from_f64(0.0))
+    }
+
+    /// The sRGB encoding of the luminance L describes.
+    fn luma(&self) -> ColorL<Self::Channel> {
+        let y = lab_f_inv((self.l.to_f64().unwrap() + 16.0) / 116.0).clamp(0.0, 1.0);
+        let max = T::max_value().to_f64().unwrap();
+        ColorL::new_l(T::from_f64(linear_to_srgb(y) * max))
+    }
+}
+
+impl<T> ChannelAccess for ColorLab<T> where T: Channel+Copy {
+    fn channel_count() -> usize { 3 }
+
+    fn channel(&self, index: usize) -> T {
+        match index {
+            0 => self.l,
+            1 => self.a,
+            2 => self.b,
+            _ => panic!("channel index {} out of range", index),
+        }
+    }
+
+    fn map_channels<F: FnMut(usize, T) -> T>(self, mut f: F) -> Self {
+        ColorLab {
+            l: f(0, self.l),
+            a: f(1, self.a),
+            b: f(2, self.b),
+        }
+    }
+}
+
+#[test]
+fn lab_reference_values() {
+    let close = |lab: ColorLab<f64>, expected: [f64; 3]| {
+        (lab.l - expected[0]).abs() < 0.01 && (lab.a - expected[1]).abs() < 0.01 && (lab.b - expected[2]).abs() < 0.01
+    };
+    assert!(close(ColorLab::from(ColorRGB::new_rgb(255u8, 255, 255)), [100.0, 0.0, 0.0]));
+    assert!(close(ColorLab::from(ColorRGB::new_rgb(0u8, 0, 0)), [0.0, 0.0, 0.0]));
+    assert!(close(ColorLab::from(ColorRGB::new_rgb(255u8, 0, 0)), [53.24, 80.09, 67.20]));
+    assert!(close(ColorLab::from(ColorRGB::new_rgb(0.0f64, 0.0, 1.0)), [32.30, 79.19, -107.86]));
+
+    for &(r, g, b) in &[(12u8, 200u8, 99u8), (255, 0, 128), (1, 2, 3), (128, 128, 128)] {
+        let back: ColorRGB<u8> = ColorRGB::from(ColorLab::from(ColorRGB::new_rgb(r, g, b)));
+        assert_eq!((back.r, back.g, back.b), (r, g, b));
+    }
+}
+
+#[test]
+fn lab_distance_and_luma() {
+    let grey = ColorLab::from(ColorRGB::new_rgb(119u8, 119, 119));
+    let nearby = ColorLab::from(ColorRGB::new_rgb(120u8, 119, 119));
+    let red = ColorLab::from(ColorRGB::new_rgb(200u8, 30, 30));
+    assert!(grey.delta_e(&nearby) < 1.0);
+    assert!(grey.delta_e(&red) > 50.0);
+    assert_eq!(grey.delta_e(&grey), 0.0);
+    assert!((grey.luma().l - 119.0 / 255.0).abs() < 1e-6);
+}
@@ -1,6 +1,7 @@
-use super::{Pixel, Channel, ColorL};
+use super::{ChannelAccess, Pixel, Channel, ColorL};
 
 #[derive(Debug, Copy)]
+#[repr(C)]
 pub struct ColorRGB<T> {
     pub r: T,
     pub g: T,
@@ -51,4 +52,25 @@ impl<T> Pixel for ColorRGB<T> where T: Channel+Copy {
 
         ColorL::new_l(Channel::from_i32(luma_val, 0, 0xFF))
     }
+}
+
+impl<T> ChannelAccess for ColorRGB<T> where T: Channel+Copy {
+    fn channel_count() -> usize { 3 }
+
+    fn channel(&self, index: usize) -> T {
+        match index {
+            0 => self.r,
+            1 => self.g,
+            2 => self.b,
+            _ => panic!("channel index {} out of range", index),
+        }
+    }
+
+    fn map_channels<F: FnMut(usize, T) -> T>(self, mut f: F) -> Self {
+        ColorRGB {
+            r: f(0, self.r),
+            g: f(1, self.g),
+            b: f(2, self.b),
+        }
+    }
 }
\ No newline at end of file
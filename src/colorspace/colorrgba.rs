@@ -1,8 +1,9 @@
 use std::ops::{Add, Mul, Sub};
 use num::traits::{Float, ToPrimitive};
-use super::{Channel, Pixel, clamp, ColorL};
+use super::{Channel, ChannelAccess, Pixel, clamp, ColorL};
 
 #[derive(Debug, Copy)]
+#[repr(C)]
 pub struct ColorRGBA<T> {
     pub r: T,
     pub g: T,
@@ -150,6 +151,29 @@ impl<T> Pixel for ColorRGBA<T> where T: Channel + Copy {
     }
 }
 
+impl<T> ChannelAccess for ColorRGBA<T> where T: Channel + Copy {
+    fn channel_count() -> usize { 4 }
+
+    fn channel(&self, index: usize) -> T {
+        match index {
+            0 => self.r,
+            1 => self.g,
+            2 => self.b,
+            3 => self.a,
+            _ => panic!("channel index {} out of range", index),
+        }
+    }
+
+    fn map_channels<F: FnMut(usize, T) -> T>(self, mut f: F) -> Self {
+        ColorRGBA {
+            r: f(0, self.r),
+            g: f(1, self.g),
+            b: f(2, self.b),
+            a: f(3, self.a),
+        }
+    }
+}
+
 #[test]
 fn color_add() {
     let foo_color: ColorRGBA<u8> = ColorRGBA::new_rgba(1, 1, 1, 1) +
@@ -0,0 +1,157 @@
+use super::{Channel, ChannelAccess, Pixel, ColorL, ColorRGB};
+use super::normalised_rgb;
+
+/// CIE 1931 XYZ tristimulus values relative to the D65 white point, with
+/// Y = 1.0 for white.  Channels hold the values themselves, so `f64` is
+/// the useful channel type.
+#[derive(Debug, Copy)]
+#[repr(C)]
+pub struct ColorXYZ<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+impl<T: Clone> Clone for ColorXYZ<T> {
+    fn clone(&self) -> ColorXYZ<T> {
+        ColorXYZ {
+            x: self.x.clone(),
+            y: self.y.clone(),
+            z: self.z.clone(),
+        }
+    }
+}
+
+impl<T: Channel> ColorXYZ<T> {
+    pub fn new_xyz(x: T, y: T, z: T) -> ColorXYZ<T> {
+        ColorXYZ { x, y, z }
+    }
+}
+
+/// The D65 white point.
+pub const D65: [f64; 3] = [0.95047, 1.0, 1.08883];
+
+const RGB_TO_XYZ: [[f64; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041],
+];
+
+const XYZ_TO_RGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+/// The sRGB transfer function, from encoded to linear light.
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// The inverse sRGB transfer function, from linear light to encoded.
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[inline]
+fn mul(m: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    let row = |r: &[f64; 3]| r[0] * v[0] + r[1] * v[1] + r[2] * v[2];
+    [row(&m[0]), row(&m[1]), row(&m[2])]
+}
+
+/// Normalised, encoded sRGB to XYZ.
+pub fn rgb_to_xyz(r: f64, g: f64, b: f64) -> [f64; 3] {
+    mul(&RGB_TO_XYZ, [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)])
+}
+
+/// XYZ to normalised, encoded sRGB.  Colours outside the sRGB gamut are
+/// clipped.
+pub fn xyz_to_rgb(xyz: [f64; 3]) -> [f64; 3] {
+    let linear = mul(&XYZ_TO_RGB, xyz);
+    let encode = |v: f64| linear_to_srgb(v.clamp(0.0, 1.0));
+    [encode(linear[0]), encode(linear[1]), encode(linear[2])]
+}
+
+impl<T: Channel> From<ColorRGB<T>> for ColorXYZ<f64> {
+    fn from(rgb: ColorRGB<T>) -> ColorXYZ<f64> {
+        let (r, g, b) = normalised_rgb(&rgb);
+        let [x, y, z] = rgb_to_xyz(r, g, b);
+        ColorXYZ::new_xyz(x, y, z)
+    }
+}
+
+impl<T: Channel> From<ColorXYZ<f64>> for ColorRGB<T> {
+    fn from(xyz: ColorXYZ<f64>) -> ColorRGB<T> {
+        let max = T::max_value().to_f64().unwrap();
+        let [r, g, b] = xyz_to_rgb([xyz.x, xyz.y, xyz.z]);
+        ColorRGB::new_rgb(T::from_f64(r * max), T::from_f64(g * max), T::from_f64(b * max))
+    }
+}
+
+impl<T> Pixel for ColorXYZ<T> where T: Channel+Copy {
+    type Channel = T;
+
+    fn white() -> Self {
+        ColorXYZ::new_xyz(
+            T::from_f64(D65[0]),
+            T::from_f64(D65[1]),
+            T::from_f64(D65[2]))
+    }
+
+    fn black() -> Self {
+        ColorXYZ::new_xyz(
+            T::from_f64(0.0),
+            T::from_f64(0.0),
+            T::from_f64(0.0))
+    }
+
+    /// Y is relative luminance; luma is its sRGB encoding.
+    fn luma(&self) -> ColorL<Self::Channel> {
+        let y = self.y.to_f64().unwrap().clamp(0.0, 1.0);
+        let max = T::max_value().to_f64().unwrap();
+        ColorL::new_l(T::from_f64(linear_to_srgb(y) * max))
+    }
+}
+
+impl<T> ChannelAccess for ColorXYZ<T> where T: Channel+Copy {
+    fn channel_count() -> usize { 3 }
+
+    fn channel(&self, index: usize) -> T {
+        match index {
+            0 => self.x,
+            1 => self.y,
+            2 => self.z,
+            _ => panic!("channel index {} out of range", index),
+        }
+    }
+
+    fn map_channels<F: FnMut(usize, T) -> T>(self, mut f: F) -> Self {
+        ColorXYZ {
+            x: f(0, self.x),
+            y: f(1, self.y),
+            z: f(2, self.z),
+        }
+    }
+}
+
+#[test]
+fn xyz_of_primaries() {
+    let white = ColorXYZ::from(ColorRGB::new_rgb(255u8, 255, 255));
+    assert!((white.x - D65[0]).abs() < 1e-4 && (white.y - 1.0).abs() < 1e-6 && (white.z - D65[2]).abs() < 1e-4);
+
+    let red = ColorXYZ::from(ColorRGB::new_rgb(1.0f64, 0.0, 0.0));
+    assert_eq!((red.x, red.y, red.z), (RGB_TO_XYZ[0][0], RGB_TO_XYZ[1][0], RGB_TO_XYZ[2][0]));
+
+    for &(r, g, b) in &[(12u8, 200u8, 99u8), (255, 0, 128), (1, 2, 3)] {
+        let back: ColorRGB<u8> = ColorRGB::from(ColorXYZ::from(ColorRGB::new_rgb(r, g, b)));
+        assert_eq!((back.r, back.g, back.b), (r, g, b));
+    }
+}
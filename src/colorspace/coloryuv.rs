@@ -1,6 +1,7 @@
-use super::{Channel, Pixel, ColorL};
+use super::{Channel, ChannelAccess, Pixel, ColorL};
 
 #[derive(Debug, Copy)]
+#[repr(C)]
 pub struct ColorYUV<T> {
     pub y: T,
     pub u: T,
@@ -45,3 +46,24 @@ impl<T> Pixel for ColorYUV<T> where T: Channel+Copy {
         ColorL::new_l(self.y)
     }
 }
+
+impl<T> ChannelAccess for ColorYUV<T> where T: Channel+Copy {
+    fn channel_count() -> usize { 3 }
+
+    fn channel(&self, index: usize) -> T {
+        match index {
+            0 => self.y,
+            1 => self.u,
+            2 => self.v,
+            _ => panic!("channel index {} out of range", index),
+        }
+    }
+
+    fn map_channels<F: FnMut(usize, T) -> T>(self, mut f: F) -> Self {
+        ColorYUV {
+            y: f(0, self.y),
+            u: f(1, self.u),
+            v: f(2, self.v),
+        }
+    }
+}
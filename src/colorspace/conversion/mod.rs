@@ -1,3 +1,5 @@
 mod yuv2rgb;
 
-pub use self::yuv2rgb::yuv444_to_rgb888;
\ No newline at end of file
+pub use self::yuv2rgb::yuv444_to_rgb888;
+#[cfg(feature = "parallel")]
+pub use self::yuv2rgb::yuv444_to_rgb888_par;
\ No newline at end of file
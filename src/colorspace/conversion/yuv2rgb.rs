@@ -6,6 +6,8 @@ use super::super::super::Channel;
 use super::super::{ColorYUV, ColorRGB, Pixel};
 use super::super::super::unified::{Yuv444, Rgb, Format};
 use ::unsafe_impl::chunks3_mut;
+#[cfg(feature = "parallel")]
+use ::parallel::{self, Partition};
 
 fn clamp<T: Ord>(value: T, min_value: T, max_value: T) -> T {
     max(min(value, max_value), min_value)
@@ -50,3 +52,17 @@ pub fn yuv444_to_rgb888<S>(surf: &Surface<Yuv444, u8, S>)
 
     Surface::new(surf.width(), surf.height(), storage)
 }
+
+/// Parallel counterpart of `yuv444_to_rgb888`, producing identical output.
+#[cfg(feature = "parallel")]
+pub fn yuv444_to_rgb888_par<S>(surf: &Surface<Yuv444, u8, S>, partition: Partition)
+    -> Surface<Rgb, u8, Box<[u8]>>
+    where
+        S: Deref<Target=[u8]> + Sync,
+{
+    let mut out = Surface::new_black(surf.width(), surf.height());
+    parallel::fill_with(&mut out, partition, |x, y| {
+        pixel_yuv444_to_rgb888(surf.get_pixel(x, y))
+    });
+    out
+}
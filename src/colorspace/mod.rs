@@ -1,4 +1,4 @@
-use num::traits::{Float, ToPrimitive};
+use num::traits::{Float, NumCast, ToPrimitive};
 use std::cmp::{min, max, Ord};
 use std::ops::{Add, Sub, Mul, Div};
 
@@ -7,6 +7,11 @@ mod colorla;
 mod colorrgb;
 mod colorrgba;
 mod coloryuv;
+mod colorhsv;
+mod colorhsl;
+mod colorxyz;
+mod colorlab;
+mod colorcmyk;
 pub mod conversion;
 
 pub use self::colorl::ColorL;
@@ -14,8 +19,13 @@ pub use self::colorla::ColorLA;
 pub use self::colorrgb::ColorRGB;
 pub use self::colorrgba::ColorRGBA;
 pub use self::coloryuv::ColorYUV;
+pub use self::colorhsv::ColorHSV;
+pub use self::colorhsl::ColorHSL;
+pub use self::colorxyz::ColorXYZ;
+pub use self::colorlab::ColorLab;
+pub use self::colorcmyk::{ColorCMYK, Separation};
 
-pub trait Channel: ToPrimitive + Clone + Copy +
+pub trait Channel: ToPrimitive + NumCast + Clone + Copy +
         Add<Output=Self> + Sub<Output=Self> +
         Mul<Output=Self> + Div<Output=Self>
 {
@@ -31,6 +41,17 @@ pub trait Channel: ToPrimitive + Clone + Copy +
     fn min_value() -> Self;
     fn max_value() -> Self;
 
+    /// Converts an intermediate result back to a channel value.  Integer
+    /// channels round to nearest and saturate at their range.
+    ///
+    /// The default rounds and clamps to `min_value()..=max_value()`, with
+    /// NaN giving `min_value()`.
+    fn from_f64(val: f64) -> Self {
+        let lo = Self::min_value().to_f64().unwrap();
+        let hi = Self::max_value().to_f64().unwrap();
+        NumCast::from(val.round().max(lo).min(hi)).unwrap()
+    }
+
     fn add(a: Self, b: Self) -> Self;
     fn sub(a: Self, b: Self) -> Self;
 }
@@ -62,6 +83,9 @@ impl Channel for u8 {
     #[inline]
     fn max_value() -> u8 { u8::max_value() }
 
+    #[inline]
+    fn from_f64(val: f64) -> u8 { val.round() as u8 }
+
     #[inline]
     fn add(a: u8, b: u8) -> u8 { a.saturating_add(b) }
 
@@ -89,6 +113,9 @@ impl Channel for u16 {
     #[inline]
     fn max_value() -> u16 { u16::max_value() }
 
+    #[inline]
+    fn from_f64(val: f64) -> u16 { val.round() as u16 }
+
     #[inline]
     fn add(a: u16, b: u16) -> u16 { a.saturating_add(b) }
 
@@ -116,6 +143,9 @@ impl Channel for u32 {
     #[inline]
     fn max_value() -> u32 { u32::max_value() }
 
+    #[inline]
+    fn from_f64(val: f64) -> u32 { val.round() as u32 }
+
     #[inline]
     fn add(a: u32, b: u32) -> u32 { a.saturating_add(b) }
 
@@ -141,6 +171,9 @@ impl Channel for f64 {
     #[inline]
     fn max_value() -> f64 { 1.0 }
 
+    #[inline]
+    fn from_f64(val: f64) -> f64 { val }
+
     #[inline]
     fn add(a: f64, b: f64) -> f64 { a + b }
 
@@ -148,6 +181,37 @@ impl Channel for f64 {
     fn sub(a: f64, b: f64) -> f64 { a - b }
 }
 
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+/// The integer channels, `u8`, `u16` and `u32`, whose values are whole
+/// numbers from zero to `max_depth()`.
+pub trait IntegerChannel: Channel + sealed::Sealed {}
+
+impl IntegerChannel for u8 {}
+impl IntegerChannel for u16 {}
+impl IntegerChannel for u32 {}
+
+/// Channels narrow enough for a table entry per value: `u8` and `u16`.
+/// Histograms and lookup tables are indexed by the value itself.
+pub trait TableChannel: IntegerChannel {
+    /// The number of distinct values, `max_depth() + 1`.
+    const LEVELS: usize;
+}
+
+impl TableChannel for u8 {
+    const LEVELS: usize = 0x100;
+}
+
+impl TableChannel for u16 {
+    const LEVELS: usize = 0x10000;
+}
+
 pub trait Pixel: Copy + Sized {
     type Channel: Channel;
 
@@ -161,6 +225,36 @@ pub trait Pixel: Copy + Sized {
     fn luma(&self) -> ColorL<Self::Channel>;
 }
 
+/// Pixels whose channels can be read and rewritten by index.
+pub trait ChannelAccess: Pixel {
+    /// The number of channels, e.g. 3 for `ColorRGB`.
+    fn channel_count() -> usize;
+
+    /// Channel `index`, counting in field order.
+    fn channel(&self, index: usize) -> Self::Channel;
+
+    /// Applies `f(index, value)` to every channel.
+    fn map_channels<F: FnMut(usize, Self::Channel) -> Self::Channel>(self, f: F) -> Self;
+}
+
+
+/// The channels of `rgb` scaled to `0.0..=1.0`.
+fn normalised_rgb<T: Channel>(rgb: &ColorRGB<T>) -> (f64, f64, f64) {
+    let max = T::max_value().to_f64().unwrap();
+    let norm = |c: T| c.to_f64().unwrap() / max;
+    (norm(rgb.r), norm(rgb.g), norm(rgb.b))
+}
+
+/// A normalised value scaled to the channel range.
+fn scale_channel<T: Channel>(value: f64) -> T {
+    T::from_f64(value.clamp(0.0, 1.0) * T::max_value().to_f64().unwrap())
+}
+
+/// Rec. 601 luma, for pixel types without a luma channel of their own.
+fn rec601_luma<T: Channel>(rgb: ColorRGB<T>) -> ColorL<T> {
+    let (r, g, b) = normalised_rgb(&rgb);
+    ColorL::new_l(scale_channel(0.299 * r + 0.587 * g + 0.114 * b))
+}
 
 fn clamp<T: Ord>(value: T, min_value: T, max_value: T) -> T {
     max(min(value, max_value), min_value)
@@ -0,0 +1,301 @@
+//! Dithering for bit-depth reduction.
+//!
+//! Converting to fewer levels by rounding turns smooth gradients into
+//! bands.  Dithering trades the bands for fine noise: ordered dithering
+//! adds a fixed Bayer pattern before rounding, and error diffusion carries
+//! each pixel's rounding error on to its unvisited neighbours.
+//!
+//! `reduce_depth` converts between channel types of the same format, e.g.
+//! `u16` or `f64` to `u8`.  `dither_to_bilevel` reduces a grey surface to
+//! black and white for e-ink displays, and `pack_bilevel` packs the result
+//! to one bit per pixel.
+
+use std::ops::Deref;
+
+use super::{Channel, ChannelAccess, ColorL, Format, Luma, Surface};
+use super::kernels::{MASK_OFF, MASK_ON};
+
+/// How values are rounded to the levels of the output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dither {
+    /// Rounds every value to the nearest level.
+    Round,
+    /// Ordered dithering with a `size`x`size` Bayer matrix, where `size` is
+    /// 2, 4, 8 or 16.  Every pixel is independent, so the pattern is
+    /// stable between frames.
+    Bayer(u32),
+    /// Error diffusion to four neighbours with Floyd–Steinberg weights.
+    FloydSteinberg,
+    /// Atkinson's error diffusion, which spreads three quarters of the
+    /// error over six neighbours for lighter, higher-contrast output.
+    Atkinson,
+}
+
+/// Error diffusion taps as `(dx, dy, weight)`.
+const FLOYD_STEINBERG: &[(isize, usize, f64)] = &[
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0), (0, 1, 5.0 / 16.0), (1, 1, 1.0 / 16.0),
+];
+
+const ATKINSON: &[(isize, usize, f64)] = &[
+    (1, 0, 1.0 / 8.0), (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0), (0, 1, 1.0 / 8.0), (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+/// Columns of padding either side of the error rows, enough for every
+/// kernel's reach.
+const PAD: usize = 2;
+
+/// The thresholds of a `size`x`size` Bayer matrix, in `-0.5..0.5`.
+fn bayer_thresholds(size: u32) -> Vec<f64> {
+    assert!(size.is_power_of_two() && (2..=16).contains(&size), "Bayer matrices are 2, 4, 8 or 16 wide");
+    let mut matrix = vec![0u32];
+    let mut side = 1;
+    while side < size as usize {
+        let next = side * 2;
+        let mut grown = vec![0; next * next];
+        for y in 0..side {
+            for x in 0..side {
+                let m = 4 * matrix[x + side * y];
+                grown[x + next * y] = m;
+                grown[x + side + next * y] = m + 2;
+                grown[x + next * (y + side)] = m + 3;
+                grown[x + side + next * (y + side)] = m + 1;
+            }
+        }
+        matrix = grown;
+        side = next;
+    }
+    let cells = (size * size) as f64;
+    matrix.iter().map(|&m| (m as f64 + 0.5) / cells - 0.5).collect()
+}
+
+/// `value` rounded to a level in `0..=max`, with NaN taken as 0.
+fn round_level(value: f64, max: f64) -> f64 {
+    if value.is_nan() {
+        0.0
+    } else {
+        value.round().clamp(0.0, max)
+    }
+}
+
+/// Rounds values to integer levels, visited in raster order.  Also used by
+/// `quantize::map_to_palette`, which picks palette entries itself and only
+/// needs the error rows.
+pub(crate) struct Ditherer {
+    dither: Dither,
+    thresholds: Vec<f64>,
+    channels: usize,
+    row_len: usize,
+    /// Error for the current row and the two below it.
+    errors: [Vec<f64>; 3],
+    y: u32,
+}
+
+impl Ditherer {
+    pub(crate) fn new(dither: Dither, width: u32, channels: usize) -> Ditherer {
+        let thresholds = match dither {
+            Dither::Bayer(size) => bayer_thresholds(size),
+            _ => Vec::new(),
+        };
+        let row_len = (width as usize + 2 * PAD) * channels;
+        Ditherer {
+            dither,
+            thresholds,
+            channels,
+            row_len,
+            errors: [vec![0.0; row_len], vec![0.0; row_len], vec![0.0; row_len]],
+            y: 0,
+        }
+    }
+
+    /// The level in `0..=max` for `value`, which is in output units.
+    fn level(&mut self, x: u32, y: u32, channel: usize, value: f64, max: f64) -> f64 {
+        match self.dither {
+            Dither::Round => return round_level(value, max),
+            Dither::Bayer(size) => {
+                let threshold = self.thresholds[(x % size + size * (y % size)) as usize];
+                return round_level(value + threshold, max);
+            }
+            Dither::FloydSteinberg | Dither::Atkinson => {}
+        }
+        let wanted = self.carried(x, y, channel, value);
+        let level = round_level(wanted, max);
+        self.diffuse(x, channel, wanted - level);
+        level
+    }
+
+    /// `value` plus the error diffused onto it so far.  Pixels must be
+    /// visited in raster order.
+    pub(crate) fn carried(&mut self, x: u32, y: u32, channel: usize, value: f64) -> f64 {
+        while self.y < y {
+            self.errors.rotate_left(1);
+            self.errors[2] = vec![0.0; self.row_len];
+            self.y += 1;
+        }
+        value + self.errors[0][(x as usize + PAD) * self.channels + channel]
+    }
+
+    /// Spreads the rounding `error` of pixel `x` in the current row over
+    /// its unvisited neighbours.
+    pub(crate) fn diffuse(&mut self, x: u32, channel: usize, error: f64) {
+        let taps = match self.dither {
+            Dither::FloydSteinberg => FLOYD_STEINBERG,
+            Dither::Atkinson => ATKINSON,
+            Dither::Round | Dither::Bayer(_) => return,
+        };
+        // An infinite input would otherwise poison every later pixel.
+        if !error.is_finite() {
+            return;
+        }
+        let column = x as usize + PAD;
+        for &(dx, dy, weight) in taps {
+            let target = (column as isize + dx) as usize;
+            self.errors[dy][target * self.channels + channel] += error * weight;
+        }
+    }
+}
+
+/// Converts `input` to another channel type of the same format, dithering
+/// to the output's levels.  Values are scaled between the channel ranges,
+/// so `u16` 65535 and `f64` 1.0 both become `u8` 255.  Float outputs have
+/// no levels and are only rescaled.
+pub fn reduce_depth<M, CI, CO, S>(input: &Surface<M, CI, S>, dither: Dither) -> Surface<M, CO, Box<[CO]>>
+    where
+        M: Format<CI> + Format<CO>,
+        <M as Format<CI>>::Pixel: ChannelAccess,
+        <M as Format<CO>>::Pixel: ChannelAccess,
+        CI: Channel,
+        CO: Channel,
+        S: Deref<Target=[CI]>,
+{
+    let channels = <<M as Format<CI>>::Pixel as ChannelAccess>::channel_count();
+    let in_max = CI::max_value().to_f64().unwrap();
+    let out_max = CO::max_value().to_f64().unwrap();
+    let mut ditherer = Ditherer::new(dither, input.width(), channels);
+
+    let mut out: Surface<M, CO, Box<[CO]>> = Surface::new_black(input.width(), input.height());
+    out.map_in_place(|x, y, px| {
+        let source = input.get_pixel(x, y);
+        px.map_channels(|index, _| {
+            let value = source.channel(index).to_f64().unwrap() / in_max * out_max;
+            match CO::max_depth() {
+                Some(_) => CO::from_f64(ditherer.level(x, y, index, value, out_max)),
+                None => CO::from_f64(value),
+            }
+        })
+    });
+    out
+}
+
+/// Reduces a grey surface to a `MASK_OFF`/`MASK_ON` mask, as for a
+/// black-and-white e-ink panel.  With `Dither::Round`, values from half
+/// the channel range up are on.
+pub fn dither_to_bilevel<C, S>(input: &Surface<Luma, C, S>, dither: Dither) -> Surface<Luma, u8, Box<[u8]>>
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    let max = C::max_value().to_f64().unwrap();
+    let mut ditherer = Ditherer::new(dither, input.width(), 1);
+
+    let mut out: Surface<Luma, u8, Box<[u8]>> = Surface::new_black(input.width(), input.height());
+    out.map_in_place(|x, y, _| {
+        let value = input.get_pixel(x, y).l.to_f64().unwrap() / max;
+        let on = ditherer.level(x, y, 0, value, 1.0) > 0.0;
+        ColorL::new_l(if on { MASK_ON } else { MASK_OFF })
+    });
+    out
+}
+
+/// Packs a mask to one bit per pixel, most significant bit first, with
+/// each row padded to a whole byte.  `MASK_ON` pixels are set bits;
+/// formats such as PBM, where a set bit is black, need the bits inverted.
+pub fn pack_bilevel<S>(mask: &Surface<Luma, u8, S>) -> Vec<u8>
+    where
+        S: Deref<Target=[u8]>,
+{
+    let width = mask.width() as usize;
+    let stride = width.div_ceil(8);
+    let mut packed = vec![0u8; stride * mask.height() as usize];
+    for (x, y, px) in mask.enumerate_pixels() {
+        if px.l == MASK_ON {
+            packed[y as usize * stride + x as usize / 8] |= 0x80 >> (x % 8);
+        }
+    }
+    packed
+}
+
+#[test]
+fn bayer_matrices() {
+    let two = bayer_thresholds(2);
+    assert_eq!(two, vec![-0.375, 0.125, 0.375, -0.125]);
+    for &size in &[4, 8, 16] {
+        let mut ranks: Vec<i64> = bayer_thresholds(size).iter()
+            .map(|t| ((t + 0.5) * (size * size) as f64 - 0.5).round() as i64)
+            .collect();
+        ranks.sort();
+        assert_eq!(ranks, (0..(size * size) as i64).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn reduce_depth_preserves_mean() {
+    use super::Rgba;
+
+    // 25764 / 257 is about 100.25, a quarter of the way to the next level.
+    let flat: Surface<Luma, u16, Vec<u16>> = Surface::new(16, 16, vec![25764; 256]);
+    let count_up = |dither: Dither| {
+        let out: Surface<Luma, u8, _> = reduce_depth(&flat, dither);
+        assert!(out.as_storage().iter().all(|&v| v == 100 || v == 101));
+        out.as_storage().iter().filter(|&&v| v == 101).count()
+    };
+    assert_eq!(count_up(Dither::Round), 0);
+    assert_eq!(count_up(Dither::Bayer(4)), 64);
+    // Error diffused past the right and bottom edges is lost.
+    let diffused = count_up(Dither::FloydSteinberg);
+    assert!((56..=68).contains(&diffused), "{} raised", diffused);
+    let atkinson = count_up(Dither::Atkinson);
+    assert!(atkinson > 0 && atkinson <= diffused, "{} raised", atkinson);
+
+    let float: Surface<Rgba, f64, Vec<f64>> = Surface::new(1, 1, vec![0.0, 0.5, 1.0, 1.0]);
+    let out: Surface<Rgba, u8, _> = reduce_depth(&float, Dither::FloydSteinberg);
+    assert_eq!(&out.as_storage()[..], &[0, 128, 255, 255]);
+    let back: Surface<Rgba, f64, _> = reduce_depth(&out, Dither::Round);
+    assert_eq!(back.as_storage()[2], 1.0);
+}
+
+#[test]
+fn bilevel_output() {
+    let grey: Surface<Luma, f64, Vec<f64>> = Surface::new(10, 2, vec![0.5; 20]);
+    let mask = dither_to_bilevel(&grey, Dither::Bayer(2));
+    assert_eq!(&mask.as_storage()[..4], &[MASK_OFF, MASK_ON, MASK_OFF, MASK_ON]);
+    assert_eq!(&mask.as_storage()[10..14], &[MASK_ON, MASK_OFF, MASK_ON, MASK_OFF]);
+    assert_eq!(pack_bilevel(&mask), vec![0x55, 0x40, 0xAA, 0x80]);
+
+    for &dither in &[Dither::FloydSteinberg, Dither::Atkinson] {
+        let mask = dither_to_bilevel(&grey, dither);
+        let on = mask.as_storage().iter().filter(|&&v| v == MASK_ON).count();
+        assert!((8..=12).contains(&on), "{:?} turned on {}", dither, on);
+    }
+
+    let dark: Surface<Luma, u8, Vec<u8>> = Surface::new(4, 1, vec![0, 10, 20, 30]);
+    let mask = dither_to_bilevel(&dark, Dither::Round);
+    assert_eq!(pack_bilevel(&mask), vec![0x00]);
+}
+
+#[test]
+fn non_finite_values_are_not_diffused() {
+    let mut values = vec![0.5; 16];
+    values[5] = f64::NAN;
+    values[10] = f64::INFINITY;
+    let grey: Surface<Luma, f64, Vec<f64>> = Surface::new(4, 4, values);
+    for &dither in &[Dither::Round, Dither::Bayer(2), Dither::FloydSteinberg, Dither::Atkinson] {
+        let out: Surface<Luma, u8, _> = reduce_depth(&grey, dither);
+        assert_eq!(out.as_storage()[5], 0, "{:?}", dither);
+        assert_eq!(out.as_storage()[10], 255, "{:?}", dither);
+        let mut rest = out.as_storage().iter().enumerate().filter(|&(i, _)| i != 5 && i != 10);
+        assert!(rest.all(|(_, &v)| v == 127 || v == 128), "{:?}", dither);
+    }
+}
@@ -0,0 +1,603 @@
+//! Drawing primitives for any surface format.
+//!
+//! Everything is drawn through `get_pixel`/`put_pixel`, so subsampled
+//! formats such as `Yuv420p` are drawn on directly: luma is written per
+//! pixel and the shared chroma sample takes the colour of the last pixel
+//! drawn in its block.  Shapes are clipped to the surface.
+//!
+//! Colours are combined with what is already on the surface by `Blend`.
+//! Colours with an alpha channel are composited by their alpha, and the
+//! anti-aliased line blends by coverage; every other colour replaces the
+//! pixel outright.
+//!
+//! Text is set in a built-in 8x16 bitmap font with `draw_text`.
+
+use std::ops::{Deref, DerefMut};
+
+use super::{Channel, Format, Pixel, Surface};
+use super::{ColorL, ColorLA, ColorRGB, ColorRGBA, ColorYUV};
+
+mod font;
+mod text;
+
+pub use self::font::{GLYPH_WIDTH, GLYPH_HEIGHT};
+pub use self::text::{draw_text, text_size};
+
+/// Pixels that can be composited onto another pixel.
+pub trait Blend: Pixel {
+    /// `self` drawn over `dst` with the given coverage, from 0 to 1.
+    fn blend(self, dst: Self, coverage: f64) -> Self;
+}
+
+#[inline]
+fn lerp<C: Channel>(from: C, to: C, t: f64) -> C {
+    let from_f = from.to_f64().unwrap();
+    C::from_f64(from_f + (to.to_f64().unwrap() - from_f) * t)
+}
+
+#[inline]
+fn opacity<C: Channel>(alpha: C) -> f64 {
+    alpha.to_f64().unwrap() / C::max_value().to_f64().unwrap()
+}
+
+impl<C: Channel> Blend for ColorL<C> {
+    fn blend(self, dst: Self, coverage: f64) -> Self {
+        ColorL::new_l(lerp(dst.l, self.l, coverage))
+    }
+}
+
+impl<C: Channel> Blend for ColorLA<C> {
+    fn blend(self, dst: Self, coverage: f64) -> Self {
+        let t = coverage * opacity(self.a);
+        ColorLA::new_la(lerp(dst.l, self.l, t), lerp(dst.a, C::max_value(), t))
+    }
+}
+
+impl<C: Channel> Blend for ColorRGB<C> {
+    fn blend(self, dst: Self, coverage: f64) -> Self {
+        ColorRGB::new_rgb(
+            lerp(dst.r, self.r, coverage),
+            lerp(dst.g, self.g, coverage),
+            lerp(dst.b, self.b, coverage))
+    }
+}
+
+impl<C: Channel> Blend for ColorRGBA<C> {
+    fn blend(self, dst: Self, coverage: f64) -> Self {
+        let t = coverage * opacity(self.a);
+        ColorRGBA::new_rgba(
+            lerp(dst.r, self.r, t),
+            lerp(dst.g, self.g, t),
+            lerp(dst.b, self.b, t),
+            lerp(dst.a, C::max_value(), t))
+    }
+}
+
+impl<C: Channel> Blend for ColorYUV<C> {
+    fn blend(self, dst: Self, coverage: f64) -> Self {
+        ColorYUV::new_yuv(
+            lerp(dst.y, self.y, coverage),
+            lerp(dst.u, self.u, coverage),
+            lerp(dst.v, self.v, coverage))
+    }
+}
+
+/// An axis-aligned rectangle whose top-left corner may lie off the surface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect { x, y, width, height }
+    }
+}
+
+#[inline]
+fn plot<M, C, S>(surface: &mut Surface<M, C, S>, x: i64, y: i64, colour: M::Pixel, coverage: f64)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    if x < 0 || y < 0 || x >= surface.width() as i64 || y >= surface.height() as i64 {
+        return;
+    }
+    let (x, y) = (x as u32, y as u32);
+    let dst = surface.get_pixel(x, y);
+    surface.put_pixel(x, y, colour.blend(dst, coverage));
+}
+
+/// Draws pixels `x0..=x1` of row `y`.
+fn span<M, C, S>(surface: &mut Surface<M, C, S>, x0: i64, x1: i64, y: i64, colour: M::Pixel)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    if y < 0 || y >= surface.height() as i64 {
+        return;
+    }
+    let x0 = x0.max(0);
+    let x1 = x1.min(surface.width() as i64 - 1);
+    for x in x0..(x1 + 1) {
+        plot(surface, x, y, colour, 1.0);
+    }
+}
+
+/// The steps `i` with `lo <= floor((2 * i * minor + major) / (2 * major)) <= hi`,
+/// i.e. those at which a Bresenham line from offset 0 along its minor axis
+/// is at an offset in `lo..=hi`.
+fn minor_steps(major: i128, minor: i128, lo: i128, hi: i128) -> (i128, i128) {
+    if minor == 0 {
+        return if lo <= 0 && 0 <= hi { (0, major) } else { (1, 0) };
+    }
+    let first = -(-(2 * lo * major - major)).div_euclid(2 * minor);
+    let last = (2 * (hi + 1) * major - major - 1).div_euclid(2 * minor);
+    (first, last)
+}
+
+/// The offsets along an axis from `start`, stepping by `sign`, that are in
+/// `0..limit`.
+fn offsets_inside(start: i64, sign: i64, limit: u32) -> (i128, i128) {
+    let (start, limit) = (start as i128, limit as i128);
+    if sign > 0 { (-start, limit - 1 - start) } else { (start - (limit - 1), start) }
+}
+
+fn bresenham<M, C, S>(surface: &mut Surface<M, C, S>, from: (i32, i32), to: (i32, i32), colour: M::Pixel, include_end: bool)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    let (x0, y0) = (from.0 as i64, from.1 as i64);
+    let (x1, y1) = (to.0 as i64, to.1 as i64);
+    let (sx, sy) = (if x0 < x1 { 1 } else { -1 }, if y0 < y1 { 1 } else { -1 });
+    let (dx, dy) = ((x1 - x0).abs(), (y1 - y0).abs());
+
+    // Step along the major axis, rounding the minor axis to the nearest
+    // pixel with halves going towards the end point.  Only the steps that
+    // land on the surface are visited.
+    let x_major = dx >= dy;
+    let (major, minor) = if x_major { (dx as i128, dy as i128) } else { (dy as i128, dx as i128) };
+    let (x_lo, x_hi) = offsets_inside(x0, sx, surface.width());
+    let (y_lo, y_hi) = offsets_inside(y0, sy, surface.height());
+    let ((major_lo, major_hi), (minor_lo, minor_hi)) = if x_major {
+        ((x_lo, x_hi), (y_lo, y_hi))
+    } else {
+        ((y_lo, y_hi), (x_lo, x_hi))
+    };
+    let (minor_first, minor_last) = minor_steps(major, minor, minor_lo, minor_hi);
+    let last = if include_end { major } else { major - 1 };
+    let first = major_lo.max(minor_first).max(0);
+    let last = major_hi.min(minor_last).min(last);
+
+    let mut i = first;
+    while i <= last {
+        let m = if major == 0 { 0 } else { (2 * i * minor + major).div_euclid(2 * major) };
+        let (ox, oy) = if x_major { (i, m) } else { (m, i) };
+        plot(surface, x0 + sx * ox as i64, y0 + sy * oy as i64, colour, 1.0);
+        i += 1;
+    }
+}
+
+/// Draws a one pixel wide aliased line, including both end points.
+pub fn draw_line<M, C, S>(surface: &mut Surface<M, C, S>, from: (i32, i32), to: (i32, i32), colour: M::Pixel)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    bresenham(surface, from, to, colour, true);
+}
+
+/// Draws an anti-aliased line between pixel centres using Xiaolin Wu's
+/// algorithm.
+pub fn draw_line_aa<M, C, S>(surface: &mut Surface<M, C, S>, from: (f64, f64), to: (f64, f64), colour: M::Pixel)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    let ((mut x0, mut y0), (mut x1, mut y1)) = (from, to);
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    if steep {
+        ::std::mem::swap(&mut x0, &mut y0);
+        ::std::mem::swap(&mut x1, &mut y1);
+    }
+    if x0 > x1 {
+        ::std::mem::swap(&mut x0, &mut x1);
+        ::std::mem::swap(&mut y0, &mut y1);
+    }
+    let gradient = if x1 == x0 { 1.0 } else { (y1 - y0) / (x1 - x0) };
+
+    let put = |surface: &mut Surface<M, C, S>, x: i64, y: i64, coverage: f64| {
+        if steep {
+            plot(surface, y, x, colour, coverage);
+        } else {
+            plot(surface, x, y, colour, coverage);
+        }
+    };
+
+    // End points cover their pixel in proportion to the part of it the
+    // line spans horizontally.
+    let endpoint = |surface: &mut Surface<M, C, S>, x: f64, y: f64, is_start: bool| {
+        let xe = x.round();
+        let ye = y + gradient * (xe - x);
+        let overhang = x + 0.5 - xe;
+        let xgap = if is_start { 1.0 - overhang } else { overhang };
+        let (yi, fy) = (ye.floor(), ye - ye.floor());
+        put(surface, xe as i64, yi as i64, (1.0 - fy) * xgap);
+        put(surface, xe as i64, yi as i64 + 1, fy * xgap);
+        (xe as i64, ye)
+    };
+    let (xs, ys) = endpoint(surface, x0, y0, true);
+    let (xe, _) = endpoint(surface, x1, y1, false);
+
+    // Only columns on the surface are walked.
+    let limit = if steep { surface.height() } else { surface.width() } as i64;
+    let first = (xs + 1).max(0);
+    let mut intery = ys + gradient * (first - xs) as f64;
+    for x in first..xe.min(limit) {
+        let (yi, fy) = (intery.floor(), intery - intery.floor());
+        put(surface, x, yi as i64, 1.0 - fy);
+        put(surface, x, yi as i64 + 1, fy);
+        intery += gradient;
+    }
+}
+
+/// Draws the one pixel wide border just inside `rect`.
+pub fn draw_rect<M, C, S>(surface: &mut Surface<M, C, S>, rect: Rect, colour: M::Pixel)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    if rect.width == 0 || rect.height == 0 {
+        return;
+    }
+    let (x0, y0) = (rect.x as i64, rect.y as i64);
+    let (x1, y1) = (x0 + rect.width as i64 - 1, y0 + rect.height as i64 - 1);
+    span(surface, x0, x1, y0, colour);
+    if y1 > y0 {
+        span(surface, x0, x1, y1, colour);
+    }
+    for y in (y0 + 1).max(0)..y1.min(surface.height() as i64) {
+        plot(surface, x0, y, colour, 1.0);
+        if x1 > x0 {
+            plot(surface, x1, y, colour, 1.0);
+        }
+    }
+}
+
+pub fn fill_rect<M, C, S>(surface: &mut Surface<M, C, S>, rect: Rect, colour: M::Pixel)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    let (x0, y0) = (rect.x as i64, rect.y as i64);
+    let x1 = x0 + rect.width as i64 - 1;
+    let y_end = (y0 + rect.height as i64).min(surface.height() as i64);
+    for y in y0.max(0)..y_end {
+        span(surface, x0, x1, y, colour);
+    }
+}
+
+/// Half-width of row `dy` of an ellipse: the largest `dx` with
+/// `(dx/rx)^2 + (dy/ry)^2 <= 1`, or -1 outside it.
+fn ellipse_half_width(rx: u32, ry: u32, dy: i64) -> i64 {
+    if dy.unsigned_abs() > ry as u64 {
+        return -1;
+    }
+    if ry == 0 {
+        return rx as i64;
+    }
+    // In u128, dx^2 * ry^2 <= rx^2 * (ry^2 - dy^2) cannot overflow.
+    let (rx, ry, dy) = (rx as u128, ry as u128, dy.unsigned_abs() as u128);
+    let (a2, b2) = (rx * rx, ry * ry);
+    let bound = a2 * (b2 - dy * dy);
+    let inside = |dx: u128| dx * dx * b2 <= bound;
+    let mut dx = (rx as f64 * (1.0 - (dy * dy) as f64 / b2 as f64).sqrt()) as u128;
+    while dx < rx && inside(dx + 1) {
+        dx += 1;
+    }
+    while dx > 0 && !inside(dx) {
+        dx -= 1;
+    }
+    dx as i64
+}
+
+/// The rows `dy` of an ellipse centred on row `cy` that lie on a surface
+/// `height` pixels high.
+fn ellipse_rows(height: u32, cy: i64, ry: u32) -> ::std::ops::Range<i64> {
+    let ry = ry as i64;
+    (-ry).max(-cy)..(ry + 1).min(height as i64 - cy)
+}
+
+/// Draws the outline of the ellipse centred on `centre` with radii `rx`
+/// and `ry`: the pixels of `fill_ellipse` that have a neighbour outside it.
+pub fn draw_ellipse<M, C, S>(surface: &mut Surface<M, C, S>, centre: (i32, i32), rx: u32, ry: u32, colour: M::Pixel)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    let (cx, cy) = (centre.0 as i64, centre.1 as i64);
+    for dy in ellipse_rows(surface.height(), cy, ry) {
+        let half = ellipse_half_width(rx, ry, dy);
+        let inner = ellipse_half_width(rx, ry, dy - 1).min(ellipse_half_width(rx, ry, dy + 1));
+        let start = half.min(inner + 1);
+        if start == 0 {
+            span(surface, cx - half, cx + half, cy + dy, colour);
+        } else {
+            span(surface, cx - half, cx - start, cy + dy, colour);
+            span(surface, cx + start, cx + half, cy + dy, colour);
+        }
+    }
+}
+
+pub fn fill_ellipse<M, C, S>(surface: &mut Surface<M, C, S>, centre: (i32, i32), rx: u32, ry: u32, colour: M::Pixel)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    let (cx, cy) = (centre.0 as i64, centre.1 as i64);
+    for dy in ellipse_rows(surface.height(), cy, ry) {
+        let half = ellipse_half_width(rx, ry, dy);
+        span(surface, cx - half, cx + half, cy + dy, colour);
+    }
+}
+
+pub fn draw_circle<M, C, S>(surface: &mut Surface<M, C, S>, centre: (i32, i32), radius: u32, colour: M::Pixel)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    draw_ellipse(surface, centre, radius, radius, colour);
+}
+
+pub fn fill_circle<M, C, S>(surface: &mut Surface<M, C, S>, centre: (i32, i32), radius: u32, colour: M::Pixel)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    fill_ellipse(surface, centre, radius, radius, colour);
+}
+
+/// Draws the closed outline through `points`.  Each pixel is drawn once,
+/// so translucent outlines have no darker vertices.
+pub fn draw_polygon<M, C, S>(surface: &mut Surface<M, C, S>, points: &[(i32, i32)], colour: M::Pixel)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    match points.len() {
+        0 => {}
+        1 => draw_line(surface, points[0], points[0], colour),
+        n => {
+            for i in 0..n {
+                bresenham(surface, points[i], points[(i + 1) % n], colour, false);
+            }
+        }
+    }
+}
+
+/// Fills the polygon through `points` by the even-odd rule.  A pixel is
+/// inside when its centre is, with centres on the right or bottom edge
+/// counted as outside so that adjacent polygons do not overlap.
+pub fn fill_polygon<M, C, S>(surface: &mut Surface<M, C, S>, points: &[(i32, i32)], colour: M::Pixel)
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    if points.len() < 3 {
+        return;
+    }
+    let y_min = points.iter().map(|p| p.1).min().unwrap().max(0) as i64;
+    let y_max = points.iter().map(|p| p.1).max().unwrap() as i64;
+    let y_max = y_max.min(surface.height() as i64 - 1);
+
+    let mut crossings = Vec::new();
+    for y in y_min..(y_max + 1) {
+        crossings.clear();
+        for i in 0..points.len() {
+            let (x0, y0) = (points[i].0 as f64, points[i].1 as i64);
+            let next = points[(i + 1) % points.len()];
+            let (x1, y1) = (next.0 as f64, next.1 as i64);
+            if (y0 <= y && y < y1) || (y1 <= y && y < y0) {
+                let t = (y - y0) as f64 / (y1 - y0) as f64;
+                crossings.push(x0 + t * (x1 - x0));
+            }
+        }
+        crossings.sort_by(f64::total_cmp);
+        for pair in crossings.chunks(2) {
+            if let [xa, xb] = *pair {
+                span(surface, xa.ceil() as i64, xb.ceil() as i64 - 1, y, colour);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn lit(surface: &Surface<super::Luma, u8, Vec<u8>>, value: u8) -> Vec<(u32, u32)> {
+    surface.enumerate_pixels()
+        .filter(|&(_, _, px)| px.l == value)
+        .map(|(x, y, _)| (x, y))
+        .collect()
+}
+
+#[test]
+fn lines_and_rects_on_luma() {
+    use super::Luma;
+
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new_black(8, 6);
+    let white = ColorL::new_l(255);
+    draw_line(&mut surf, (0, 0), (7, 3), white);
+    assert_eq!(lit(&surf, 255), vec![(0, 0), (1, 0), (2, 1), (3, 1), (4, 2), (5, 2), (6, 3), (7, 3)]);
+
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new_black(6, 5);
+    draw_rect(&mut surf, Rect::new(1, 1, 4, 3), white);
+    assert_eq!(surf.as_storage(), &[
+        0, 0, 0, 0, 0, 0,
+        0, 255, 255, 255, 255, 0,
+        0, 255, 0, 0, 255, 0,
+        0, 255, 255, 255, 255, 0,
+        0, 0, 0, 0, 0, 0,
+    ][..]);
+
+    fill_rect(&mut surf, Rect::new(-3, 3, 5, 9), ColorL::new_l(7));
+    assert_eq!(&surf.as_storage()[18..], &[
+        7, 7, 255, 255, 255, 0,
+        7, 7, 0, 0, 0, 0,
+    ][..]);
+}
+
+#[test]
+fn circles_are_symmetric_and_clipped() {
+    use super::Luma;
+
+    let white = ColorL::new_l(255);
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new_black(7, 7);
+    draw_circle(&mut surf, (3, 3), 3, white);
+    assert_eq!(surf.as_storage(), &[
+        0, 0, 0, 255, 0, 0, 0,
+        0, 255, 255, 0, 255, 255, 0,
+        0, 255, 0, 0, 0, 255, 0,
+        255, 0, 0, 0, 0, 0, 255,
+        0, 255, 0, 0, 0, 255, 0,
+        0, 255, 255, 0, 255, 255, 0,
+        0, 0, 0, 255, 0, 0, 0,
+    ][..]);
+
+    let mut filled: Surface<Luma, u8, Vec<u8>> = Surface::new_black(7, 7);
+    fill_circle(&mut filled, (3, 3), 3, white);
+    for (x, y, px) in surf.enumerate_pixels() {
+        if px.l == 255 {
+            assert_eq!(filled.get_pixel(x, y).l, 255);
+        }
+    }
+    assert_eq!(lit(&filled, 255).len(), 29);
+
+    fill_ellipse(&mut filled, (-20, 50), 30, 60, ColorL::new_l(1));
+    assert_eq!(filled.get_pixel(0, 6).l, 1);
+    assert_eq!(filled.get_pixel(3, 6).l, 255);
+
+    // Huge radii neither overflow nor walk rows off the surface.
+    let mut small: Surface<Luma, u8, Vec<u8>> = Surface::new_black(4, 4);
+    fill_circle(&mut small, (0, 0), 60_000, white);
+    assert!(small.as_storage().iter().all(|&v| v == 255));
+    let mut small: Surface<Luma, u8, Vec<u8>> = Surface::new_black(4, 4);
+    draw_ellipse(&mut small, (2, 2), u32::MAX, u32::MAX, white);
+    assert_eq!(lit(&small, 255), vec![]);
+    assert_eq!(ellipse_half_width(u32::MAX, u32::MAX, 0), u32::MAX as i64);
+}
+
+#[test]
+fn long_lines_are_clipped() {
+    use super::Luma;
+
+    let white = ColorL::new_l(255);
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new_black(4, 3);
+    draw_line(&mut surf, (i32::MIN, 1), (i32::MAX, 1), white);
+    assert_eq!(lit(&surf, 255), vec![(0, 1), (1, 1), (2, 1), (3, 1)]);
+    draw_line(&mut surf, (i32::MIN, i32::MIN), (i32::MAX, i32::MAX), ColorL::new_l(9));
+    assert_eq!(lit(&surf, 9), vec![(0, 0), (1, 1), (2, 2)]);
+
+    let mut aa: Surface<Luma, u8, Vec<u8>> = Surface::new_black(4, 3);
+    draw_line_aa(&mut aa, (-1e12, 1.0), (1e12, 1.0), white);
+    assert_eq!(lit(&aa, 255), vec![(0, 1), (1, 1), (2, 1), (3, 1)]);
+}
+
+#[test]
+fn polygons() {
+    use super::Luma;
+
+    let white = ColorL::new_l(255);
+    let triangle = [(0, 0), (6, 0), (0, 6)];
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new_black(7, 7);
+    fill_polygon(&mut surf, &triangle, white);
+    let rows: Vec<usize> = surf.rows().map(|r| r.iter().filter(|&&v| v == 255).count()).collect();
+    assert_eq!(rows, vec![6, 5, 4, 3, 2, 1, 0]);
+
+    let mut outline: Surface<Luma, u8, Vec<u8>> = Surface::new_black(7, 7);
+    draw_polygon(&mut outline, &triangle, white);
+    let outline = lit(&outline, 255);
+    assert_eq!(outline.len(), 18);
+    assert!(outline.contains(&(0, 0)) && outline.contains(&(6, 0)) && outline.contains(&(0, 6)));
+}
+
+#[test]
+fn rgba_blends_by_alpha() {
+    use super::Rgba;
+
+    let mut surf: Surface<Rgba, u8, Vec<u8>> = Surface::new_black(4, 1);
+    fill_rect(&mut surf, Rect::new(0, 0, 4, 1), ColorRGBA::new_rgba(0, 0, 200, 255));
+    fill_rect(&mut surf, Rect::new(1, 0, 2, 1), ColorRGBA::new_rgba(255, 0, 0, 128));
+    assert_eq!(surf.as_storage(), &[
+        0, 0, 200, 255,
+        128, 0, 100, 255,
+        128, 0, 100, 255,
+        0, 0, 200, 255,
+    ][..]);
+}
+
+#[test]
+fn antialiased_line_coverage() {
+    use super::Luma;
+
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new_black(5, 3);
+    draw_line_aa(&mut surf, (0.0, 1.0), (4.0, 1.0), ColorL::new_l(200));
+    assert_eq!(surf.as_storage(), &[
+        0, 0, 0, 0, 0,
+        100, 200, 200, 200, 100,
+        0, 0, 0, 0, 0,
+    ][..]);
+
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new_black(5, 3);
+    draw_line_aa(&mut surf, (1.0, 0.5), (3.0, 0.5), ColorL::new_l(200));
+    assert_eq!(&surf.as_storage()[..10], &[
+        0, 50, 100, 50, 0,
+        0, 50, 100, 50, 0,
+    ][..]);
+}
+
+#[test]
+fn boxes_on_yuv420p() {
+    use super::Yuv420p;
+
+    let mut surf: Surface<Yuv420p, u8, Vec<u8>> = Surface::new_black(6, 4);
+    let green = ColorYUV::new_yuv(150, 40, 30);
+    fill_rect(&mut surf, Rect::new(2, 0, 2, 2), green);
+    let (y, u, v) = surf.get_planes();
+    assert_eq!(y, &[
+        0, 0, 150, 150, 0, 0,
+        0, 0, 150, 150, 0, 0,
+        0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0,
+    ][..]);
+    assert_eq!(u, &[128, 40, 128, 128, 128, 128][..]);
+    assert_eq!(v, &[128, 30, 128, 128, 128, 128][..]);
+}
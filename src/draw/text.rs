@@ -0,0 +1,113 @@
+use std::ops::{Deref, DerefMut};
+
+use super::super::{Channel, Format, Surface};
+use super::font::{glyph, GLYPH_WIDTH, GLYPH_HEIGHT};
+use super::{plot, fill_rect, Blend, Rect};
+
+/// The size in pixels of `text` set in the built-in 8x16 font.  Lines are
+/// separated by `'\n'`.
+pub fn text_size(text: &str) -> (u32, u32) {
+    let lines = text.split('\n');
+    let (mut columns, mut rows) = (0, 0);
+    for line in lines {
+        columns = columns.max(line.chars().count() as u32);
+        rows += 1;
+    }
+    (columns * GLYPH_WIDTH, rows * GLYPH_HEIGHT)
+}
+
+/// Draws `text` in the built-in 8x16 font with its top-left corner at
+/// `origin`, returning the box it occupies.  With a `background`, that box
+/// is filled first.  Characters outside printable ASCII are drawn as `?`.
+pub fn draw_text<M, C, S>(
+    surface: &mut Surface<M, C, S>,
+    origin: (i32, i32),
+    text: &str,
+    colour: M::Pixel,
+    background: Option<M::Pixel>,
+) -> Rect
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        M::Pixel: Blend,
+{
+    let (width, height) = text_size(text);
+    let bounds = Rect::new(origin.0, origin.1, width, height);
+    if let Some(background) = background {
+        fill_rect(surface, bounds, background);
+    }
+
+    for (row, line) in text.split('\n').enumerate() {
+        let top = origin.1 as i64 + row as i64 * GLYPH_HEIGHT as i64;
+        for (column, ch) in line.chars().enumerate() {
+            let left = origin.0 as i64 + column as i64 * GLYPH_WIDTH as i64;
+            for (dy, bits) in glyph(ch).iter().enumerate() {
+                for dx in 0..GLYPH_WIDTH as i64 {
+                    if bits & (0x80 >> dx) != 0 {
+                        plot(surface, left + dx, top + dy as i64, colour, 1.0);
+                    }
+                }
+            }
+        }
+    }
+    bounds
+}
+
+#[test]
+fn text_layout() {
+    assert_eq!(text_size(""), (0, 16));
+    assert_eq!(text_size("12:00"), (40, 16));
+    assert_eq!(text_size("cam 1\n12:00:00"), (64, 32));
+}
+
+#[test]
+fn glyph_pixels_and_background() {
+    use super::super::{Luma, ColorL};
+
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new_black(20, 18);
+    let bounds = draw_text(&mut surf, (2, 1), "I-", ColorL::new_l(255), Some(ColorL::new_l(9)));
+    assert_eq!(bounds, Rect::new(2, 1, 16, 16));
+
+    let mut expected = vec![0u8; 20 * 18];
+    for y in 1..17 {
+        for x in 2..18 {
+            expected[y * 20 + x] = 9;
+        }
+    }
+    for (ch, left) in [('I', 2), ('-', 10)].iter().cloned() {
+        for (dy, bits) in glyph(ch).iter().enumerate() {
+            for dx in 0..8 {
+                if bits & (0x80 >> dx) != 0 {
+                    expected[(1 + dy) * 20 + left + dx] = 255;
+                }
+            }
+        }
+    }
+    assert_eq!(surf.as_storage(), &expected[..]);
+    assert!(surf.as_storage().iter().filter(|&&v| v == 255).count() > 10);
+}
+
+#[test]
+fn text_is_clipped() {
+    use super::super::{Rgb, ColorRGB};
+
+    let mut surf: Surface<Rgb, u8, Vec<u8>> = Surface::new_black(10, 10);
+    draw_text(&mut surf, (-4, -9), "WW\nWW", ColorRGB::new_rgb(255, 0, 0), Some(ColorRGB::new_rgb(0, 0, 255)));
+    assert!(surf.iter_pixels().all(|px| px.b == 255 || px.r == 255));
+}
+
+#[test]
+fn text_on_yuv420p() {
+    use super::super::{Yuv420p, ColorYUV};
+
+    let mut surf: Surface<Yuv420p, u8, Vec<u8>> = Surface::new_black(16, 16);
+    draw_text(&mut surf, (0, 0), "0", ColorYUV::new_yuv(235, 128, 128), Some(ColorYUV::new_yuv(16, 90, 240)));
+    let (y, u, v) = surf.get_planes();
+    let lit = glyph('0').iter().map(|bits| bits.count_ones() as usize).sum::<usize>();
+    assert_eq!(y[..].iter().filter(|&&l| l == 235).count(), lit);
+    assert_eq!(y[..].iter().filter(|&&l| l == 16).count(), 8 * 16 - lit);
+    assert!(y.chunks(16).all(|row| row[8..].iter().all(|&l| l == 0)));
+    assert!(u.chunks(8).all(|row| row[4..].iter().all(|&c| c == 128)));
+    assert!(v.chunks(8).all(|row| row[..4].iter().all(|&c| c == 128 || c == 240)));
+}
@@ -0,0 +1,233 @@
+//! Global and contrast-limited adaptive histogram equalisation.
+//!
+//! Only the luma plane is changed; chroma is left untouched.  Channels must
+//! be `u8` or `u16` (`TableChannel`), so that every value has its own
+//! histogram bin.
+
+use std::ops::{Deref, DerefMut};
+
+use super::{Channel, LumaFormat, Surface, TableChannel};
+use super::stats::{Histogram, histogram_of};
+
+/// Parameters of `clahe`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Clahe {
+    /// Number of tiles across and down.  Each tile gets its own mapping,
+    /// blended bilinearly between tile centres.
+    pub tiles: (u32, u32),
+    /// Maximum height of a tile's histogram bins, as a multiple of the
+    /// average height.  Lower values limit the contrast gain more.
+    pub clip_limit: f64,
+}
+
+impl Default for Clahe {
+    fn default() -> Clahe {
+        Clahe { tiles: (8, 8), clip_limit: 2.0 }
+    }
+}
+
+#[inline]
+fn index_of<C: Channel>(value: C) -> usize {
+    value.to_usize().unwrap()
+}
+
+/// Stretches the luma of `plane` so its cumulative histogram is linear.
+/// A constant plane is left as it is.
+pub fn equalize_plane<C: TableChannel>(plane: &mut [C]) {
+    let cdf = histogram_of(plane, C::LEVELS).cumulative();
+    let total = plane.len() as u64;
+    let cdf_min = match cdf.iter().find(|&&count| count > 0) {
+        Some(&count) if count < total => count,
+        _ => return,
+    };
+    let max = C::max_value().to_f64().unwrap();
+    let scale = max / (total - cdf_min) as f64;
+    let lut: Vec<C> = cdf.iter()
+        .map(|&count| C::from_f64(count.saturating_sub(cdf_min) as f64 * scale))
+        .collect();
+    for value in plane.iter_mut() {
+        *value = lut[index_of(*value)];
+    }
+}
+
+/// Clips `bins` at `limit` and spreads the excess evenly over all bins.
+fn clip_histogram(bins: &mut [u64], limit: u64) {
+    let mut excess = 0;
+    for count in bins.iter_mut() {
+        if *count > limit {
+            excess += *count - limit;
+            *count = limit;
+        }
+    }
+    let len = bins.len() as u64;
+    let (share, remainder) = (excess / len, excess % len);
+    for count in bins.iter_mut() {
+        *count += share;
+    }
+    if let Some(step) = len.checked_div(remainder) {
+        for count in bins.iter_mut().step_by(step as usize).take(remainder as usize) {
+            *count += 1;
+        }
+    }
+}
+
+/// Start of tile `index` when `len` is split into `tiles` near-equal parts.
+#[inline]
+fn tile_start(index: usize, len: usize, tiles: usize) -> usize {
+    index * len / tiles
+}
+
+/// The two tiles whose centres surround `pos`, and the weight of the second.
+#[inline]
+fn neighbours(pos: usize, len: usize, tiles: usize) -> (usize, usize, f64) {
+    let grid = (pos as f64 + 0.5) * tiles as f64 / len as f64 - 0.5;
+    if grid <= 0.0 {
+        return (0, 0, 0.0);
+    }
+    let lo = (grid.floor() as usize).min(tiles - 1);
+    let hi = (lo + 1).min(tiles - 1);
+    (lo, hi, grid - lo as f64)
+}
+
+/// Contrast-limited adaptive histogram equalisation of a `width`x`height`
+/// plane.
+pub fn clahe_plane<C: TableChannel>(plane: &mut [C], width: u32, height: u32, params: Clahe) {
+    let (width, height) = (width as usize, height as usize);
+    if width == 0 || height == 0 {
+        return;
+    }
+    let bins = C::LEVELS;
+    let tiles_x = (params.tiles.0 as usize).clamp(1, width);
+    let tiles_y = (params.tiles.1 as usize).clamp(1, height);
+    let max = C::max_value().to_f64().unwrap();
+
+    // One mapping per tile, from the tile's clipped cumulative histogram.
+    let mut luts: Vec<Vec<f64>> = Vec::with_capacity(tiles_x * tiles_y);
+    for ty in 0..tiles_y {
+        let (y0, y1) = (tile_start(ty, height, tiles_y), tile_start(ty + 1, height, tiles_y));
+        for tx in 0..tiles_x {
+            let (x0, x1) = (tile_start(tx, width, tiles_x), tile_start(tx + 1, width, tiles_x));
+            let mut hist = Histogram::new(bins);
+            for row in plane[y0 * width..y1 * width].chunks(width) {
+                hist.add(&row[x0..x1]);
+            }
+            let mut counts = hist.bins().to_vec();
+            let area = ((x1 - x0) * (y1 - y0)) as u64;
+            let limit = (params.clip_limit * area as f64 / bins as f64).ceil().max(1.0) as u64;
+            clip_histogram(&mut counts, limit);
+
+            let scale = max / area as f64;
+            let mut acc = 0;
+            luts.push(counts.iter()
+                .map(|&count| {
+                    acc += count;
+                    acc as f64 * scale
+                })
+                .collect());
+        }
+    }
+
+    for y in 0..height {
+        let (ty0, ty1, wy) = neighbours(y, height, tiles_y);
+        for x in 0..width {
+            let (tx0, tx1, wx) = neighbours(x, width, tiles_x);
+            let value = &mut plane[y * width + x];
+            let bin = index_of(*value);
+            let at = |tx: usize, ty: usize| luts[ty * tiles_x + tx][bin];
+            let top = at(tx0, ty0) * (1.0 - wx) + at(tx1, ty0) * wx;
+            let bottom = at(tx0, ty1) * (1.0 - wx) + at(tx1, ty1) * wx;
+            *value = C::from_f64((top * (1.0 - wy) + bottom * wy).min(max));
+        }
+    }
+}
+
+/// Global histogram equalisation of the luma of `surface`.
+pub fn equalize_histogram<M, C, S>(surface: &mut Surface<M, C, S>)
+    where
+        M: LumaFormat<C>,
+        C: TableChannel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    surface.with_luma_plane_mut(equalize_plane);
+}
+
+/// Contrast-limited adaptive histogram equalisation of the luma of
+/// `surface`.
+pub fn clahe<M, C, S>(surface: &mut Surface<M, C, S>, params: Clahe)
+    where
+        M: LumaFormat<C>,
+        C: TableChannel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    let (width, height) = (surface.width(), surface.height());
+    surface.with_luma_plane_mut(|plane| clahe_plane(plane, width, height, params));
+}
+
+#[test]
+fn equalize_spreads_values() {
+    use super::Luma;
+
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new(4, 1, vec![100, 101, 101, 102]);
+    equalize_histogram(&mut surf);
+    assert_eq!(surf.as_storage(), &[0, 170, 170, 255][..]);
+
+    let mut flat: Surface<Luma, u8, Vec<u8>> = Surface::new(3, 1, vec![9, 9, 9]);
+    equalize_histogram(&mut flat);
+    assert_eq!(flat.as_storage(), &[9, 9, 9][..]);
+
+    let mut wide: Surface<Luma, u16, Vec<u16>> = Surface::new(2, 1, vec![1000, 1001]);
+    equalize_histogram(&mut wide);
+    assert_eq!(wide.as_storage(), &[0, 65535][..]);
+}
+
+#[test]
+fn equalize_leaves_chroma() {
+    use super::{Yuv420p, Yuv422, Yuv444};
+
+    let mut planar: Surface<Yuv420p, u8, Vec<u8>> = Surface::new(2, 2, vec![10, 20, 20, 30, 77, 88]);
+    equalize_histogram(&mut planar);
+    assert_eq!(planar.as_storage(), &[0, 170, 170, 255, 77, 88][..]);
+
+    let mut packed: Surface<Yuv422, u8, Vec<u8>> = Surface::new(3, 1, vec![10, 1, 20, 2, 30, 3, 99, 4]);
+    equalize_histogram(&mut packed);
+    assert_eq!(packed.as_storage(), &[0, 1, 128, 2, 255, 3, 99, 4][..]);
+
+    let mut yuv444: Surface<Yuv444, u8, Vec<u8>> = Surface::new(2, 1, vec![5, 1, 2, 6, 3, 4]);
+    equalize_histogram(&mut yuv444);
+    assert_eq!(yuv444.as_storage(), &[0, 1, 2, 255, 3, 4][..]);
+}
+
+#[test]
+fn clip_redistributes_excess() {
+    let mut bins = [10, 0, 0, 2];
+    clip_histogram(&mut bins, 4);
+    assert_eq!(bins, [6, 1, 2, 3]);
+    assert_eq!(bins.iter().sum::<u64>(), 12);
+}
+
+#[test]
+fn clahe_adapts_to_tiles() {
+    use super::Luma;
+
+    // A dark left half and a bright right half, each with faint texture.
+    let (width, height) = (32u32, 16u32);
+    let storage: Vec<u8> = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let base = if x < width / 2 { 20 } else { 200 };
+            base + ((x + y) % 4) as u8
+        })
+        .collect();
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new(width, height, storage.clone());
+    clahe(&mut surf, Clahe { tiles: (2, 1), clip_limit: 40.0 });
+
+    // Texture within each half is stretched far beyond its original range.
+    let spread = |row: &[u8]| row.iter().max().unwrap() - row.iter().min().unwrap();
+    assert!(spread(&surf.as_storage()[..8]) > 100);
+    assert!(spread(&surf.as_storage()[24..32]) > 100);
+
+    // A low clip limit keeps the mapping close to the identity's slope.
+    let mut limited: Surface<Luma, u8, Vec<u8>> = Surface::new(width, height, storage);
+    clahe(&mut limited, Clahe { tiles: (2, 1), clip_limit: 1.0 });
+    assert!(spread(&limited.as_storage()[..8]) < 10);
+}
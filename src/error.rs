@@ -0,0 +1,40 @@
+use std::error;
+use std::fmt;
+
+/// Errors raised when constructing or validating surfaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The storage does not hold exactly as many channels as the format
+    /// requires for the given dimensions.
+    SizeMismatch { expected: usize, actual: usize },
+    /// The width or height is zero.
+    ZeroDimensions { width: u32, height: u32 },
+    /// The storage size for these dimensions does not fit in a `usize`.
+    Overflow { width: u32, height: u32 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::SizeMismatch { expected, actual } =>
+                write!(f, "storage holds {} channels, expected {}", actual, expected),
+            Error::ZeroDimensions { width, height } =>
+                write!(f, "dimensions {}x{} are empty", width, height),
+            Error::Overflow { width, height } =>
+                write!(f, "dimensions {}x{} overflow the address space", width, height),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// Validates dimensions common to all formats and returns the number of
+/// channels needed to store `channels_per_pixel` channels per pixel.
+pub fn checked_channel_count(width: u32, height: u32, channels_per_pixel: usize) -> Result<usize, Error> {
+    if width == 0 || height == 0 {
+        return Err(Error::ZeroDimensions { width, height });
+    }
+    (width as usize).checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(channels_per_pixel))
+        .ok_or(Error::Overflow { width, height })
+}
@@ -0,0 +1,340 @@
+//! Geometric transforms: flips, quarter-turn rotations and transposition.
+//!
+//! Planar YUV formats are transformed plane by plane.  When a chroma block
+//! no longer lines up with its luma block, because an odd dimension leaves
+//! a partial block at the edge or a quarter turn of `Yuv422p` swaps the
+//! subsampled axis, chroma is resampled: each output chroma sample averages
+//! the source chroma of the pixels it covers.  Packed `Yuv422` goes through
+//! `get_pixel`/`put_pixel` and keeps the chroma of one of the covered
+//! pixels.
+
+use std::any::TypeId;
+use std::cmp;
+use std::ops::Deref;
+
+use super::{Channel, Format, Surface, Yuv420p, Yuv422p};
+
+/// A transform mapping the pixel grid onto itself.  Rotations are
+/// clockwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    FlipHorizontal,
+    FlipVertical,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Transpose,
+}
+
+impl Transform {
+    /// Whether the transform exchanges width and height.
+    pub fn swaps_axes(self) -> bool {
+        matches!(self, Transform::Rotate90 | Transform::Rotate270 | Transform::Transpose)
+    }
+
+    pub fn output_size(self, width: u32, height: u32) -> (u32, u32) {
+        if self.swaps_axes() { (height, width) } else { (width, height) }
+    }
+
+    /// The source position of output pixel `(x, y)` for a `width`x`height`
+    /// input.
+    #[inline]
+    pub fn source(self, (x, y): (usize, usize), (width, height): (usize, usize)) -> (usize, usize) {
+        match self {
+            Transform::FlipHorizontal => (width - 1 - x, y),
+            Transform::FlipVertical => (x, height - 1 - y),
+            Transform::Rotate90 => (y, height - 1 - x),
+            Transform::Rotate180 => (width - 1 - x, height - 1 - y),
+            Transform::Rotate270 => (width - 1 - y, x),
+            Transform::Transpose => (y, x),
+        }
+    }
+}
+
+fn transform_plane<C: Copy>(src: &[C], (width, height): (usize, usize), dst: &mut [C], op: Transform) {
+    let (out_width, out_height) = if op.swaps_axes() { (height, width) } else { (width, height) };
+    if out_width == 0 {
+        return;
+    }
+    for (y, row) in dst[..out_width * out_height].chunks_mut(out_width).enumerate() {
+        for (x, out) in row.iter_mut().enumerate() {
+            let (sx, sy) = op.source((x, y), (width, height));
+            *out = src[sy * width + sx];
+        }
+    }
+}
+
+/// Transforms a chroma plane by resampling: every output chroma sample
+/// averages the source chroma of the luma pixels it covers, rounding down
+/// for integer channels.  `size` is the input luma size and the `sub`
+/// pairs are the horizontal and vertical subsampling of input and output.
+fn resample_chroma<C: Channel>(
+    src: &[C],
+    (width, height): (usize, usize),
+    (in_sub_x, in_sub_y): (usize, usize),
+    dst: &mut [C],
+    (out_sub_x, out_sub_y): (usize, usize),
+    op: Transform,
+) {
+    let (out_width, out_height) = if op.swaps_axes() { (height, width) } else { (width, height) };
+    let src_stride = width.div_ceil(in_sub_x);
+    let dst_stride = out_width.div_ceil(out_sub_x);
+    let integer = C::max_depth().is_some();
+    for cy in 0..out_height.div_ceil(out_sub_y) {
+        for cx in 0..dst_stride {
+            let (mut sum, mut count) = (0.0, 0.0);
+            for y in cy * out_sub_y..cmp::min((cy + 1) * out_sub_y, out_height) {
+                for x in cx * out_sub_x..cmp::min((cx + 1) * out_sub_x, out_width) {
+                    let (sx, sy) = op.source((x, y), (width, height));
+                    sum += src[sy / in_sub_y * src_stride + sx / in_sub_x].to_f64().unwrap();
+                    count += 1.0;
+                }
+            }
+            let mean = sum / count;
+            dst[cy * dst_stride + cx] = C::from_f64(if integer { mean.floor() } else { mean });
+        }
+    }
+}
+
+fn transform_yuv420p<C, S>(input: &Surface<Yuv420p, C, S>, output: &mut Surface<Yuv420p, C, Box<[C]>>, op: Transform)
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let (y_in, u_in, v_in) = input.get_planes();
+    let (y_out, u_out, v_out) = output.get_planes_mut();
+    transform_plane(y_in, (width, height), y_out, op);
+    if width % 2 == 0 && height % 2 == 0 {
+        // Whole 2x2 blocks map onto whole blocks.
+        let chroma = (width / 2, height / 2);
+        transform_plane(u_in, chroma, u_out, op);
+        transform_plane(v_in, chroma, v_out, op);
+    } else {
+        resample_chroma(u_in, (width, height), (2, 2), u_out, (2, 2), op);
+        resample_chroma(v_in, (width, height), (2, 2), v_out, (2, 2), op);
+    }
+}
+
+fn transform_yuv422p<C, S>(input: &Surface<Yuv422p, C, S>, output: &mut Surface<Yuv422p, C, Box<[C]>>, op: Transform)
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let (y_in, u_in, v_in) = input.get_planes();
+    let (y_out, u_out, v_out) = output.get_planes_mut();
+    transform_plane(y_in, (width, height), y_out, op);
+    if !op.swaps_axes() && width % 2 == 0 {
+        // Whole pixel pairs map onto whole pairs.
+        let chroma = (width / 2, height);
+        transform_plane(u_in, chroma, u_out, op);
+        transform_plane(v_in, chroma, v_out, op);
+    } else {
+        resample_chroma(u_in, (width, height), (2, 1), u_out, (2, 1), op);
+        resample_chroma(v_in, (width, height), (2, 1), v_out, (2, 1), op);
+    }
+}
+
+/// Applies `op` to `input`, returning a new surface.
+pub fn transform<M, C, S>(input: &Surface<M, C, S>, op: Transform) -> Surface<M, C, Box<[C]>>
+    where
+        M: Format<C> + 'static,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    let (width, height) = (input.width(), input.height());
+    let (out_width, out_height) = op.output_size(width, height);
+    let mut output: Surface<M, C, Box<[C]>> = Surface::new_black(out_width, out_height);
+
+    // Same-format views of the storage, for the plane-aware paths.
+    if TypeId::of::<M>() == TypeId::of::<Yuv420p>() {
+        let input: Surface<Yuv420p, C, &[C]> = Surface::new(width, height, input.as_storage());
+        let mut planar: Surface<Yuv420p, C, Box<[C]>> = Surface::new(out_width, out_height, output.into_storage());
+        transform_yuv420p(&input, &mut planar, op);
+        return Surface::new(out_width, out_height, planar.into_storage());
+    }
+    if TypeId::of::<M>() == TypeId::of::<Yuv422p>() {
+        let input: Surface<Yuv422p, C, &[C]> = Surface::new(width, height, input.as_storage());
+        let mut planar: Surface<Yuv422p, C, Box<[C]>> = Surface::new(out_width, out_height, output.into_storage());
+        transform_yuv422p(&input, &mut planar, op);
+        return Surface::new(out_width, out_height, planar.into_storage());
+    }
+
+    let size = (width as usize, height as usize);
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let (sx, sy) = op.source((x as usize, y as usize), size);
+            output.put_pixel(x, y, input.get_pixel(sx as u32, sy as u32));
+        }
+    }
+    output
+}
+
+pub fn flip_horizontal<M, C, S>(input: &Surface<M, C, S>) -> Surface<M, C, Box<[C]>>
+    where
+        M: Format<C> + 'static,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    transform(input, Transform::FlipHorizontal)
+}
+
+pub fn flip_vertical<M, C, S>(input: &Surface<M, C, S>) -> Surface<M, C, Box<[C]>>
+    where
+        M: Format<C> + 'static,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    transform(input, Transform::FlipVertical)
+}
+
+pub fn rotate90<M, C, S>(input: &Surface<M, C, S>) -> Surface<M, C, Box<[C]>>
+    where
+        M: Format<C> + 'static,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    transform(input, Transform::Rotate90)
+}
+
+pub fn rotate180<M, C, S>(input: &Surface<M, C, S>) -> Surface<M, C, Box<[C]>>
+    where
+        M: Format<C> + 'static,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    transform(input, Transform::Rotate180)
+}
+
+pub fn rotate270<M, C, S>(input: &Surface<M, C, S>) -> Surface<M, C, Box<[C]>>
+    where
+        M: Format<C> + 'static,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    transform(input, Transform::Rotate270)
+}
+
+pub fn transpose<M, C, S>(input: &Surface<M, C, S>) -> Surface<M, C, Box<[C]>>
+    where
+        M: Format<C> + 'static,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    transform(input, Transform::Transpose)
+}
+
+#[cfg(test)]
+const ALL: [Transform; 6] = [
+    Transform::FlipHorizontal,
+    Transform::FlipVertical,
+    Transform::Rotate90,
+    Transform::Rotate180,
+    Transform::Rotate270,
+    Transform::Transpose,
+];
+
+#[cfg(test)]
+fn inverse(op: Transform) -> Transform {
+    match op {
+        Transform::Rotate90 => Transform::Rotate270,
+        Transform::Rotate270 => Transform::Rotate90,
+        other => other,
+    }
+}
+
+#[test]
+fn rgb_transforms() {
+    use super::Rgb;
+
+    // 3x2, one channel value per pixel index.
+    let storage: Vec<u8> = (0..6).flat_map(|i| vec![i, 0, 0]).collect();
+    let surf: Surface<Rgb, u8, Vec<u8>> = Surface::new(3, 2, storage);
+    let reds = |s: &Surface<Rgb, u8, Box<[u8]>>| -> Vec<u8> {
+        s.iter_pixels().map(|px| px.r).collect()
+    };
+
+    assert_eq!(reds(&flip_horizontal(&surf)), vec![2, 1, 0, 5, 4, 3]);
+    assert_eq!(reds(&flip_vertical(&surf)), vec![3, 4, 5, 0, 1, 2]);
+    assert_eq!(reds(&rotate180(&surf)), vec![5, 4, 3, 2, 1, 0]);
+
+    let rotated = rotate90(&surf);
+    assert_eq!((rotated.width(), rotated.height()), (2, 3));
+    assert_eq!(reds(&rotated), vec![3, 0, 4, 1, 5, 2]);
+    assert_eq!(reds(&rotate270(&surf)), vec![2, 5, 1, 4, 0, 3]);
+    assert_eq!(reds(&transpose(&surf)), vec![0, 3, 1, 4, 2, 5]);
+
+    for &op in &ALL {
+        let back = transform(&transform(&surf, op), inverse(op));
+        assert_eq!(&back.as_storage()[..], &surf.as_storage()[..], "{:?}", op);
+    }
+}
+
+#[test]
+fn yuv420p_transforms_are_plane_aware() {
+    use super::colorspace::ColorYUV;
+
+    for &(w, h) in &[(4, 2), (6, 4), (5, 3), (5, 4), (4, 3)] {
+        let mut surf: Surface<Yuv420p, u8, Vec<u8>> = Surface::new_black(w, h);
+        surf.map_in_place(|x, y, _| ColorYUV::new_yuv((x + 10 * y) as u8, (x / 2 + 10 * (y / 2)) as u8, 200));
+        let chroma_of = |(sx, sy): (usize, usize)| sx / 2 + 10 * (sy / 2);
+
+        for &op in &ALL {
+            let out = transform(&surf, op);
+            let size = (w as usize, h as usize);
+            let (out_w, out_h) = (out.width() as usize, out.height() as usize);
+            for (x, y, px) in out.enumerate_pixels() {
+                let (x, y) = (x as usize, y as usize);
+                assert_eq!(px.y as usize, {
+                    let (sx, sy) = op.source((x, y), size);
+                    sx + 10 * sy
+                }, "{:?}", op);
+
+                // Chroma is the mean source chroma of the output block, which
+                // for whole blocks is the chroma of the matching source block.
+                let (bx, by) = (x / 2 * 2, y / 2 * 2);
+                let covered: Vec<usize> = (by..cmp::min(by + 2, out_h))
+                    .flat_map(|cy| (bx..cmp::min(bx + 2, out_w)).map(move |cx| (cx, cy)))
+                    .map(|p| chroma_of(op.source(p, size)))
+                    .collect();
+                let expected = covered.iter().sum::<usize>() / covered.len();
+                assert_eq!(px.u as usize, expected, "{:?} {}x{} at ({}, {})", op, w, h, x, y);
+                assert_eq!(px.v, 200);
+            }
+            if w % 2 == 0 && h % 2 == 0 {
+                let back = transform(&out, inverse(op));
+                assert_eq!(&back.as_storage()[..], &surf.as_storage()[..], "{:?}", op);
+            }
+        }
+    }
+
+    // At width 5 flipped blocks straddle two source blocks and average them.
+    let mut surf: Surface<Yuv420p, u8, Vec<u8>> = Surface::new_black(5, 2);
+    surf.map_in_place(|x, _, _| ColorYUV::new_yuv(0, 10 * (x / 2) as u8, 128));
+    let flipped = flip_horizontal(&surf);
+    let (_, u_plane, _) = flipped.get_planes();
+    assert_eq!(u_plane, &[15, 5, 0][..]);
+}
+
+#[test]
+fn yuv422p_rotation_resamples_chroma() {
+    use super::colorspace::ColorYUV;
+
+    // 4x2, chroma varies along x only: samples 10, 30 in each row.
+    let mut surf: Surface<Yuv422p, u8, Vec<u8>> = Surface::new_black(4, 2);
+    surf.map_in_place(|x, y, _| ColorYUV::new_yuv((x + 4 * y) as u8, 10 + 20 * (x / 2) as u8, 100 + y as u8));
+
+    let rotated = rotate90(&surf);
+    assert_eq!((rotated.width(), rotated.height()), (2, 4));
+    let (y_plane, u_plane, v_plane) = rotated.get_planes();
+    assert_eq!(y_plane, &[4, 0, 5, 1, 6, 2, 7, 3][..]);
+    // Each output row holds one source column, so U is constant per row.
+    assert_eq!(u_plane, &[10, 10, 30, 30][..]);
+    // Output chroma pairs span both source rows and are averaged.
+    assert_eq!(v_plane, &[100, 100, 100, 100][..]);
+
+    let flipped = flip_horizontal(&surf);
+    let (_, u_plane, _) = flipped.get_planes();
+    assert_eq!(u_plane, &[30, 10, 30, 10][..]);
+}
@@ -0,0 +1,115 @@
+//! Edge-preserving bilateral filtering of packed surfaces.
+
+use std::ops::{Deref, DerefMut};
+
+use super::super::{Channel, Format, PackedFormat, Surface};
+
+/// Bilateral filter: each pixel becomes a mean of its neighbours weighted
+/// both by distance, with a Gaussian of `sigma_space` pixels, and by
+/// difference in colour, with a Gaussian of `sigma_range` channel units.
+/// Colour difference is the Euclidean distance over all channels, so an
+/// edge in any channel is preserved in every channel.
+///
+/// The window reaches `ceil(2 * sigma_space)` pixels out and is clipped to
+/// the surface, so every pixel of `output` is written.
+pub fn bilateral_filter<M, C, S, S2>(
+    input: &Surface<M, C, S>,
+    sigma_space: f64,
+    sigma_range: f64,
+    output: &mut Surface<M, C, S2>,
+)
+    where
+        M: PackedFormat<C>,
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[C]> + DerefMut,
+{
+    assert_eq!(input.width(), output.width());
+    assert_eq!(input.height(), output.height());
+    assert!(sigma_space > 0.0 && sigma_range > 0.0, "sigmas must be positive");
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let channels = <M as Format<C>>::channel_data_size(1, 1);
+    let len = width * height * channels;
+    let src: Vec<f64> = input.as_storage()[..len].iter().map(|v| v.to_f64().unwrap()).collect();
+    let dst = &mut output.as_storage_mut()[..len];
+
+    let radius = (2.0 * sigma_space).ceil() as usize;
+    let span = 2 * radius + 1;
+    let spatial: Vec<f64> = (0..span * span)
+        .map(|i| {
+            let (dx, dy) = ((i % span) as f64 - radius as f64, (i / span) as f64 - radius as f64);
+            (-(dx * dx + dy * dy) / (2.0 * sigma_space * sigma_space)).exp()
+        })
+        .collect();
+    let range_scale = -1.0 / (2.0 * sigma_range * sigma_range);
+
+    let mut acc = vec![0.0; channels];
+    for y in 0..height {
+        let (y0, y1) = (y.saturating_sub(radius), (y + radius + 1).min(height));
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(width));
+            let centre = &src[(y * width + x) * channels..][..channels];
+            for value in acc.iter_mut() {
+                *value = 0.0;
+            }
+            let mut total = 0.0;
+            for wy in y0..y1 {
+                for wx in x0..x1 {
+                    let px = &src[(wy * width + wx) * channels..][..channels];
+                    let distance: f64 = px.iter().zip(centre).map(|(a, b)| (a - b) * (a - b)).sum();
+                    let weight = spatial[(wy + radius - y) * span + wx + radius - x]
+                        * (distance * range_scale).exp();
+                    for (value, &channel) in acc.iter_mut().zip(px) {
+                        *value += weight * channel;
+                    }
+                    total += weight;
+                }
+            }
+            let out = &mut dst[(y * width + x) * channels..][..channels];
+            for (out, value) in out.iter_mut().zip(&acc) {
+                *out = C::from_f64(value / total);
+            }
+        }
+    }
+}
+
+#[test]
+fn bilateral_smooths_flat_regions_and_keeps_edges() {
+    use super::super::Luma;
+
+    // A step from 40 to 200 with small ripples on both sides.
+    let (width, height) = (16u32, 8u32);
+    let storage: Vec<u8> = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let base = if x < 8 { 40 } else { 200 };
+            if (x + y) % 2 == 0 { base + 4 } else { base - 4 }
+        })
+        .collect();
+    let input: Surface<Luma, u8, Vec<u8>> = Surface::new(width, height, storage);
+    let mut output: Surface<Luma, u8, Vec<u8>> = Surface::new_black(width, height);
+    bilateral_filter(&input, 2.0, 20.0, &mut output);
+
+    for y in 2..6 {
+        // Ripples flatten out...
+        assert!((39..=41).contains(&output.get_pixel(4, y).l));
+        assert!((199..=201).contains(&output.get_pixel(11, y).l));
+        // ...while the step stays sharp.
+        assert!(output.get_pixel(7, y).l <= 45);
+        assert!(output.get_pixel(8, y).l >= 195);
+    }
+}
+
+#[test]
+fn bilateral_preserves_colour_edges() {
+    use super::super::Rgb;
+
+    // Red and blue halves of equal brightness stay apart.
+    let storage: Vec<u8> = (0..8 * 4)
+        .flat_map(|i| if i % 8 < 4 { vec![200, 0, 0] } else { vec![0, 0, 200] })
+        .collect();
+    let input: Surface<Rgb, u8, Vec<u8>> = Surface::new(8, 4, storage.clone());
+    let mut output: Surface<Rgb, u8, Vec<u8>> = Surface::new_black(8, 4);
+    bilateral_filter(&input, 1.5, 10.0, &mut output);
+    assert_eq!(&output.as_storage()[..], &storage[..]);
+}
@@ -0,0 +1,302 @@
+//! Sobel gradients and the Canny edge detector.
+//!
+//! Gradients are stored in `f64` surfaces in raw Sobel units, i.e. up to
+//! four times the channel range, and are not clamped to `0.0..1.0`.  Like
+//! `Sobel3x3`, only interior pixels of the gradient surfaces are written.
+
+use std::f64::consts::PI;
+use std::ops::{Deref, DerefMut};
+
+use super::super::{Channel, Luma, Surface};
+use super::threshold::{MASK_OFF, MASK_ON};
+
+/// Calls `f(index, gx, gy)` for every interior pixel of a plane.
+fn for_each_gradient<F>(plane: &[f64], width: usize, height: usize, mut f: F)
+    where
+        F: FnMut(usize, f64, f64),
+{
+    if width < 3 || height < 3 {
+        return;
+    }
+    for y in 1..(height - 1) {
+        let (r0, r1, r2) = (&plane[(y - 1) * width..], &plane[y * width..], &plane[(y + 1) * width..]);
+        for x in 1..(width - 1) {
+            let (l, c, r) = (x - 1, x, x + 1);
+            let gx = (r0[r] - r0[l]) + 2.0 * (r1[r] - r1[l]) + (r2[r] - r2[l]);
+            let gy = (r2[l] + 2.0 * r2[c] + r2[r]) - (r0[l] + 2.0 * r0[c] + r0[r]);
+            f(y * width + x, gx, gy);
+        }
+    }
+}
+
+fn plane_of<C, S>(input: &Surface<Luma, C, S>) -> Vec<f64>
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    let len = input.width() as usize * input.height() as usize;
+    input.as_storage()[..len].iter().map(|v| v.to_f64().unwrap()).collect()
+}
+
+fn check_size<C, S, S2>(input: &Surface<Luma, C, S>, output: &Surface<Luma, f64, S2>)
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[f64]>,
+{
+    assert_eq!(input.width(), output.width());
+    assert_eq!(input.height(), output.height());
+}
+
+/// The horizontal and vertical Sobel responses of `input`.  Positive `gx`
+/// points right and positive `gy` points down.
+pub fn sobel_gradients<C, S, S2, S3>(
+    input: &Surface<Luma, C, S>,
+    gx: &mut Surface<Luma, f64, S2>,
+    gy: &mut Surface<Luma, f64, S3>,
+)
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[f64]> + DerefMut,
+        S3: Deref<Target=[f64]> + DerefMut,
+{
+    check_size(input, gx);
+    check_size(input, gy);
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let (out_x, out_y) = (gx.as_storage_mut(), gy.as_storage_mut());
+    for_each_gradient(&plane_of(input), width, height, |i, x, y| {
+        out_x[i] = x;
+        out_y[i] = y;
+    });
+}
+
+/// The Sobel gradient of `input` in polar form: its magnitude, and its
+/// angle in radians from the positive x axis towards positive y, in
+/// `-PI..=PI`.
+pub fn sobel_polar<C, S, S2, S3>(
+    input: &Surface<Luma, C, S>,
+    magnitude: &mut Surface<Luma, f64, S2>,
+    angle: &mut Surface<Luma, f64, S3>,
+)
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[f64]> + DerefMut,
+        S3: Deref<Target=[f64]> + DerefMut,
+{
+    check_size(input, magnitude);
+    check_size(input, angle);
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let (out_mag, out_angle) = (magnitude.as_storage_mut(), angle.as_storage_mut());
+    for_each_gradient(&plane_of(input), width, height, |i, x, y| {
+        out_mag[i] = x.hypot(y);
+        out_angle[i] = y.atan2(x);
+    });
+}
+
+/// Parameters of `canny`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Canny {
+    /// Standard deviation of the Gaussian smoothing, in pixels.  Zero
+    /// disables smoothing.
+    pub sigma: f64,
+    /// Gradient magnitude, in raw Sobel units of the input channel, below
+    /// which no pixel is an edge.
+    pub low: f64,
+    /// Gradient magnitude above which a pixel is always an edge.  Pixels
+    /// between `low` and `high` are edges when connected to such a pixel.
+    pub high: f64,
+}
+
+impl Default for Canny {
+    /// Thresholds suited to `u8` input.
+    fn default() -> Canny {
+        Canny { sigma: 1.4, low: 50.0, high: 100.0 }
+    }
+}
+
+/// Separable Gaussian blur of a plane, clamping taps to the edge.
+pub fn gaussian_blur(plane: &[f64], width: usize, height: usize, sigma: f64) -> Vec<f64> {
+    if sigma <= 0.0 {
+        return plane.to_vec();
+    }
+    let radius = (3.0 * sigma).ceil() as isize;
+    let taps: Vec<f64> = (-radius..=radius)
+        .map(|d| (-(d * d) as f64 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = taps.iter().sum();
+    let taps: Vec<f64> = taps.iter().map(|t| t / sum).collect();
+
+    let clamp = |pos: isize, len: usize| pos.clamp(0, len as isize - 1) as usize;
+    let mut rows = vec![0.0; plane.len()];
+    for y in 0..height {
+        for x in 0..width {
+            rows[y * width + x] = taps.iter().enumerate()
+                .map(|(i, t)| t * plane[y * width + clamp(x as isize + i as isize - radius, width)])
+                .sum();
+        }
+    }
+    let mut out = vec![0.0; plane.len()];
+    for y in 0..height {
+        for x in 0..width {
+            out[y * width + x] = taps.iter().enumerate()
+                .map(|(i, t)| t * rows[clamp(y as isize + i as isize - radius, height) * width + x])
+                .sum();
+        }
+    }
+    out
+}
+
+/// The offset of the neighbour along the gradient direction, quantised to
+/// one of four axes.
+#[inline]
+fn along_gradient(angle: f64, width: usize) -> isize {
+    let width = width as isize;
+    // Fold into 0..PI, since both neighbours are compared.
+    let angle = if angle < 0.0 { angle + PI } else { angle };
+    let sector = (angle / (PI / 4.0)).round() as usize % 4;
+    match sector {
+        0 => 1,
+        1 => width + 1,
+        2 => width,
+        _ => width - 1,
+    }
+}
+
+/// Canny edge detection.  Every pixel of `output` is written: `MASK_ON`
+/// on edges and `MASK_OFF` elsewhere, including the one-pixel border where
+/// no gradient is defined.
+pub fn canny<C, S, S2>(input: &Surface<Luma, C, S>, params: Canny, output: &mut Surface<Luma, u8, S2>)
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    assert_eq!(input.width(), output.width());
+    assert_eq!(input.height(), output.height());
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let len = width * height;
+    let smoothed = gaussian_blur(&plane_of(input), width, height, params.sigma);
+
+    let mut magnitude = vec![0.0; len];
+    let mut angle = vec![0.0; len];
+    for_each_gradient(&smoothed, width, height, |i, x, y| {
+        magnitude[i] = x.hypot(y);
+        angle[i] = y.atan2(x);
+    });
+
+    // Non-maximum suppression: keep local maxima across the edge.  Ties go
+    // to the earlier pixel, so a two-pixel plateau still gives a one-pixel
+    // edge.  Border magnitudes are zero, so neighbour lookups stay in bounds.
+    let mut thin = vec![0.0; len];
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let i = y * width + x;
+            let mag = magnitude[i];
+            if mag < params.low {
+                continue;
+            }
+            let step = along_gradient(angle[i], width);
+            let (before, after) = ((i as isize - step) as usize, (i as isize + step) as usize);
+            if mag > magnitude[before] && mag >= magnitude[after] {
+                thin[i] = mag;
+            }
+        }
+    }
+
+    // Hysteresis: grow edges from strong pixels through weak neighbours.
+    let dst = &mut output.as_storage_mut()[..len];
+    for value in dst.iter_mut() {
+        *value = MASK_OFF;
+    }
+    let mut stack: Vec<usize> = Vec::new();
+    for (i, &mag) in thin.iter().enumerate() {
+        if mag >= params.high && dst[i] == MASK_OFF {
+            dst[i] = MASK_ON;
+            stack.push(i);
+        }
+        while let Some(at) = stack.pop() {
+            let (x, y) = (at % width, at / width);
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let n = ny * width + nx;
+                    if dst[n] == MASK_OFF && thin[n] > 0.0 {
+                        dst[n] = MASK_ON;
+                        stack.push(n);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn gradients_of_ramp() {
+    let storage: Vec<u8> = (0..20).map(|i| (i % 5) as u8 * 10).collect();
+    let input: Surface<Luma, u8, Vec<u8>> = Surface::new(5, 4, storage);
+    let mut gx: Surface<Luma, f64, Vec<f64>> = Surface::new(5, 4, vec![-1.0; 20]);
+    let mut gy: Surface<Luma, f64, Vec<f64>> = Surface::new(5, 4, vec![-1.0; 20]);
+    sobel_gradients(&input, &mut gx, &mut gy);
+    assert_eq!((gx.get_pixel(2, 1).l, gy.get_pixel(2, 1).l), (80.0, 0.0));
+    assert_eq!(gx.get_pixel(0, 1).l, -1.0);
+
+    let mut mag: Surface<Luma, f64, Vec<f64>> = Surface::new_black(5, 4);
+    let mut angle: Surface<Luma, f64, Vec<f64>> = Surface::new_black(5, 4);
+    let transposed: Vec<u8> = (0..20).map(|i| (i / 5) as u8 * 10).collect();
+    sobel_polar(&Surface::<Luma, u8, _>::new(5, 4, transposed), &mut mag, &mut angle);
+    assert_eq!(mag.get_pixel(3, 2).l, 80.0);
+    assert!((angle.get_pixel(3, 2).l - PI / 2.0).abs() < 1e-12);
+}
+
+#[test]
+fn canny_finds_thin_square_outline() {
+    let (width, height) = (24u32, 24u32);
+    let storage: Vec<u8> = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            if (6..18).contains(&x) && (6..18).contains(&y) { 200 } else { 20 }
+        })
+        .collect();
+    let input: Surface<Luma, u8, Vec<u8>> = Surface::new(width, height, storage);
+    let mut edges: Surface<Luma, u8, Vec<u8>> = Surface::new(width, height, vec![7; (width * height) as usize]);
+    canny(&input, Canny::default(), &mut edges);
+
+    assert!(edges.as_storage().iter().all(|&v| v == MASK_ON || v == MASK_OFF));
+    // Every row crossing the square has a thin edge on each side of it.
+    for y in 8..16 {
+        let row = &edges.as_storage()[(y * width) as usize..((y + 1) * width) as usize];
+        let on: Vec<usize> = row.iter().enumerate().filter(|&(_, &v)| v == MASK_ON).map(|(x, _)| x).collect();
+        assert_eq!(on.len(), 2, "row {}: {:?}", y, on);
+        assert!((5..=6).contains(&on[0]) && (17..=18).contains(&on[1]), "row {}: {:?}", y, on);
+    }
+    // Flat regions stay empty.
+    assert_eq!(edges.get_pixel(12, 12).l, MASK_OFF);
+    assert_eq!(edges.get_pixel(2, 2).l, MASK_OFF);
+}
+
+#[test]
+fn canny_hysteresis_follows_weak_edges() {
+    // A step whose contrast fades along its length: only the part joined
+    // to the strong end survives a high threshold above the weak contrast.
+    let (width, height) = (12u32, 16u32);
+    let storage: Vec<u8> = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            if x < 6 { 0 } else if y < 8 { 60 } else { 25 }
+        })
+        .collect();
+    let input: Surface<Luma, u8, Vec<u8>> = Surface::new(width, height, storage);
+    let params = Canny { sigma: 0.0, low: 80.0, high: 200.0 };
+    let mut edges: Surface<Luma, u8, Vec<u8>> = Surface::new_black(width, height);
+    canny(&input, params, &mut edges);
+    let on_rows = |surf: &Surface<Luma, u8, Vec<u8>>| (1..height - 1)
+        .filter(|&y| (0..width).any(|x| surf.get_pixel(x, y).l == MASK_ON))
+        .count();
+    assert_eq!(on_rows(&edges), (height - 2) as usize);
+
+    // Without a strong seed nothing is kept.
+    let mut none: Surface<Luma, u8, Vec<u8>> = Surface::new_black(width, height);
+    canny(&input, Canny { high: 300.0, ..params }, &mut none);
+    assert_eq!(on_rows(&none), 0);
+}
@@ -0,0 +1,320 @@
+//! Integral images (summed-area tables) of integer `Luma` surfaces.
+//!
+//! Entry `(x, y)` of a table holds the sum of all pixels above and to the
+//! left of `(x, y)`, so the sum over any rectangle takes four lookups.
+//! Tables are accumulated with wrapping arithmetic: a `u32` table gives
+//! exact rectangle sums as long as each queried sum fits in a `u32`, even
+//! when the sum over the whole surface does not.
+
+use std::ops::{Deref, DerefMut};
+
+use super::super::{Channel, IntegerChannel, Luma, Surface, TableChannel};
+
+/// The integer type an `IntegralImage` accumulates in.
+pub trait Accumulator: Copy + Default {
+    fn from_u64(value: u64) -> Self;
+    fn to_u64(self) -> u64;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_sub(self, other: Self) -> Self;
+}
+
+impl Accumulator for u32 {
+    #[inline]
+    fn from_u64(value: u64) -> u32 {
+        value as u32
+    }
+
+    #[inline]
+    fn to_u64(self) -> u64 {
+        self as u64
+    }
+
+    #[inline]
+    fn wrapping_add(self, other: u32) -> u32 {
+        u32::wrapping_add(self, other)
+    }
+
+    #[inline]
+    fn wrapping_sub(self, other: u32) -> u32 {
+        u32::wrapping_sub(self, other)
+    }
+}
+
+impl Accumulator for u64 {
+    #[inline]
+    fn from_u64(value: u64) -> u64 {
+        value
+    }
+
+    #[inline]
+    fn to_u64(self) -> u64 {
+        self
+    }
+
+    #[inline]
+    fn wrapping_add(self, other: u64) -> u64 {
+        u64::wrapping_add(self, other)
+    }
+
+    #[inline]
+    fn wrapping_sub(self, other: u64) -> u64 {
+        u64::wrapping_sub(self, other)
+    }
+}
+
+/// Summed-area tables of a `Luma` surface with integer channels, and
+/// optionally of its squared values.  Squares are only kept for channels
+/// of 16 bits or less, so that each fits in 32 bits, and are always
+/// accumulated in `u64`.
+#[derive(Clone, Debug)]
+pub struct IntegralImage<A> {
+    width: u32,
+    height: u32,
+    sums: Vec<A>,
+    squares: Option<Vec<u64>>,
+}
+
+/// Builds a `(width + 1)`x`(height + 1)` table with a zero first row and
+/// column.
+fn table<A, F>(plane: &[u64], width: usize, height: usize, f: F) -> Vec<A>
+    where
+        A: Accumulator,
+        F: Fn(u64) -> u64,
+{
+    let stride = width + 1;
+    let mut out = vec![A::default(); stride * (height + 1)];
+    for y in 0..height {
+        let mut row_sum = A::default();
+        for x in 0..width {
+            row_sum = row_sum.wrapping_add(A::from_u64(f(plane[y * width + x])));
+            out[(y + 1) * stride + x + 1] = out[y * stride + x + 1].wrapping_add(row_sum);
+        }
+    }
+    out
+}
+
+impl<A: Accumulator> IntegralImage<A> {
+    /// The table of sums of `input`.
+    pub fn new<C, S>(input: &Surface<Luma, C, S>) -> IntegralImage<A>
+        where
+            C: IntegerChannel,
+            S: Deref<Target=[C]>,
+    {
+        IntegralImage::build(input, false)
+    }
+
+    /// Like `new`, with a table of squared values for `square_sum` and
+    /// `variance`.
+    pub fn with_squares<C, S>(input: &Surface<Luma, C, S>) -> IntegralImage<A>
+        where
+            C: TableChannel,
+            S: Deref<Target=[C]>,
+    {
+        IntegralImage::build(input, true)
+    }
+
+    fn build<C, S>(input: &Surface<Luma, C, S>, squares: bool) -> IntegralImage<A>
+        where
+            C: IntegerChannel,
+            S: Deref<Target=[C]>,
+    {
+        let (width, height) = (input.width() as usize, input.height() as usize);
+        let plane: Vec<u64> = input.as_storage()[..width * height].iter()
+            .map(|v| v.to_u64().unwrap())
+            .collect();
+        IntegralImage {
+            width: input.width(),
+            height: input.height(),
+            sums: table(&plane, width, height, |v| v),
+            squares: if squares { Some(table(&plane, width, height, |v| v * v)) } else { None },
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Table indices of the corners of a rectangle, top-left first.
+    #[inline]
+    fn corners(&self, x: u32, y: u32, width: u32, height: u32) -> [usize; 4] {
+        let inside = |start: u32, len: u32, limit: u32| start.checked_add(len).is_some_and(|end| end <= limit);
+        assert!(inside(x, width, self.width) && inside(y, height, self.height), "rectangle out of bounds");
+        let stride = self.width as usize + 1;
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = (x0 + width as usize, y0 + height as usize);
+        [y0 * stride + x0, y0 * stride + x1, y1 * stride + x0, y1 * stride + x1]
+    }
+
+    /// The sum of the `width`x`height` rectangle at `(x, y)`.
+    pub fn sum(&self, x: u32, y: u32, width: u32, height: u32) -> A {
+        let [tl, tr, bl, br] = self.corners(x, y, width, height);
+        let s = &self.sums;
+        s[br].wrapping_sub(s[tr]).wrapping_sub(s[bl]).wrapping_add(s[tl])
+    }
+
+    /// The sum of squared values over a rectangle, if the image was built
+    /// `with_squares`.
+    pub fn square_sum(&self, x: u32, y: u32, width: u32, height: u32) -> Option<u64> {
+        let [tl, tr, bl, br] = self.corners(x, y, width, height);
+        self.squares.as_ref().map(|s| {
+            s[br].wrapping_sub(s[tr]).wrapping_sub(s[bl]).wrapping_add(s[tl])
+        })
+    }
+
+    /// The mean over a non-empty rectangle.
+    pub fn mean(&self, x: u32, y: u32, width: u32, height: u32) -> f64 {
+        let count = width as f64 * height as f64;
+        self.sum(x, y, width, height).to_u64() as f64 / count
+    }
+
+    /// The population variance over a non-empty rectangle, if the image
+    /// was built `with_squares`.
+    pub fn variance(&self, x: u32, y: u32, width: u32, height: u32) -> Option<f64> {
+        let count = width as f64 * height as f64;
+        let mean = self.mean(x, y, width, height);
+        self.square_sum(x, y, width, height)
+            .map(|sq| (sq as f64 / count - mean * mean).max(0.0))
+    }
+
+    /// The `(2 * radius + 1)`-wide square around `(x, y)`, clipped to the
+    /// image, as `(x, y, width, height)`.
+    #[inline]
+    fn window(&self, x: u32, y: u32, radius: u32) -> (u32, u32, u32, u32) {
+        let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+        let x1 = x.saturating_add(radius).saturating_add(1).min(self.width);
+        let y1 = y.saturating_add(radius).saturating_add(1).min(self.height);
+        (x0, y0, x1 - x0, y1 - y0)
+    }
+}
+
+fn check_size<C, S, T, S2>(input: &Surface<Luma, C, S>, output: &Surface<Luma, T, S2>)
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+        T: Channel,
+        S2: Deref<Target=[T]>,
+{
+    assert_eq!(input.width(), output.width());
+    assert_eq!(input.height(), output.height());
+}
+
+/// Box blur with a `(2 * radius + 1)`-wide square window, in time
+/// independent of the radius.  Near the edges the window is clipped to the
+/// surface, so every pixel of `output` is written.
+pub fn box_blur<C, S, S2>(input: &Surface<Luma, C, S>, radius: u32, output: &mut Surface<Luma, C, S2>)
+    where
+        C: IntegerChannel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[C]> + DerefMut,
+{
+    check_size(input, output);
+    let integral: IntegralImage<u64> = IntegralImage::new(input);
+    let width = input.width();
+    let dst = output.as_storage_mut();
+    for y in 0..input.height() {
+        for x in 0..width {
+            let (wx, wy, ww, wh) = integral.window(x, y, radius);
+            dst[y as usize * width as usize + x as usize] = C::from_f64(integral.mean(wx, wy, ww, wh));
+        }
+    }
+}
+
+/// The mean and population variance of the `(2 * radius + 1)`-wide square
+/// around every pixel, clipped to the surface as in `box_blur`.
+pub fn local_mean_variance<C, S, S2, S3>(
+    input: &Surface<Luma, C, S>,
+    radius: u32,
+    mean: &mut Surface<Luma, f64, S2>,
+    variance: &mut Surface<Luma, f64, S3>,
+)
+    where
+        C: TableChannel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[f64]> + DerefMut,
+        S3: Deref<Target=[f64]> + DerefMut,
+{
+    check_size(input, mean);
+    check_size(input, variance);
+    let integral: IntegralImage<u64> = IntegralImage::with_squares(input);
+    let width = input.width();
+    let (means, variances) = (mean.as_storage_mut(), variance.as_storage_mut());
+    for y in 0..input.height() {
+        for x in 0..width {
+            let (wx, wy, ww, wh) = integral.window(x, y, radius);
+            let at = y as usize * width as usize + x as usize;
+            means[at] = integral.mean(wx, wy, ww, wh);
+            variances[at] = integral.variance(wx, wy, ww, wh).unwrap();
+        }
+    }
+}
+
+#[test]
+fn rectangle_sums() {
+    let storage: Vec<u8> = (1..=12).collect();
+    let input: Surface<Luma, u8, Vec<u8>> = Surface::new(4, 3, storage);
+    let integral: IntegralImage<u32> = IntegralImage::with_squares(&input);
+    assert_eq!(integral.sum(0, 0, 4, 3), 78);
+    assert_eq!(integral.sum(1, 1, 2, 2), 6 + 7 + 10 + 11);
+    assert_eq!(integral.sum(3, 2, 1, 1), 12);
+    assert_eq!(integral.sum(2, 0, 0, 3), 0);
+    assert_eq!(integral.square_sum(0, 0, 2, 1), Some(1 + 4));
+    assert_eq!(integral.mean(0, 0, 4, 1), 2.5);
+    assert_eq!(integral.variance(0, 0, 4, 1), Some(1.25));
+
+    let plain: IntegralImage<u64> = IntegralImage::new(&input);
+    assert_eq!(plain.square_sum(0, 0, 1, 1), None);
+
+    let wide: Surface<Luma, u32, Vec<u32>> = Surface::new(2, 1, vec![u32::MAX, 1]);
+    let wide: IntegralImage<u64> = IntegralImage::new(&wide);
+    assert_eq!(wide.sum(0, 0, 2, 1), u32::MAX as u64 + 1);
+}
+
+#[test]
+fn wrapping_table_gives_exact_local_sums() {
+    // The whole-surface sum overflows u32, but every row's does not.
+    let (width, height) = (256u32, 256u32);
+    let input: Surface<Luma, u16, Vec<u16>> = Surface::new(width, height, vec![0xFFFF; (width * height) as usize]);
+    let integral: IntegralImage<u32> = IntegralImage::new(&input);
+    assert_eq!(integral.sum(0, 200, width, 1), 0xFFFF * 256);
+    assert_eq!(integral.sum(250, 250, 6, 6), 0xFFFF * 36);
+}
+
+#[test]
+fn box_blur_and_local_statistics() {
+    let storage: Vec<u8> = vec![
+        0, 0, 0, 0, 0,
+        0, 90, 90, 90, 0,
+        0, 90, 90, 90, 0,
+    ];
+    let input: Surface<Luma, u8, Vec<u8>> = Surface::new(5, 3, storage);
+    let mut blurred: Surface<Luma, u8, Vec<u8>> = Surface::new_black(5, 3);
+    box_blur(&input, 1, &mut blurred);
+    assert_eq!(blurred.get_pixel(2, 1).l, 60);
+    assert_eq!(blurred.get_pixel(0, 0).l, 23);
+    assert_eq!(blurred.get_pixel(2, 2).l, 90);
+
+    box_blur(&input, 100, &mut blurred);
+    assert!(blurred.as_storage().iter().all(|&v| v == 36));
+
+    let mut mean: Surface<Luma, f64, Vec<f64>> = Surface::new_black(5, 3);
+    let mut variance: Surface<Luma, f64, Vec<f64>> = Surface::new_black(5, 3);
+    local_mean_variance(&input, 0, &mut mean, &mut variance);
+    assert_eq!(mean.get_pixel(1, 1).l, 90.0);
+    assert!(variance.as_storage().iter().all(|&v| v == 0.0));
+    local_mean_variance(&input, 1, &mut mean, &mut variance);
+    assert_eq!(mean.get_pixel(2, 2).l, 90.0);
+    assert_eq!(variance.get_pixel(2, 2).l, 0.0);
+    assert_eq!(variance.get_pixel(2, 1).l, 1800.0);
+}
+
+#[test]
+#[should_panic(expected = "rectangle out of bounds")]
+fn rectangle_past_u32_range_is_out_of_bounds() {
+    let input: Surface<Luma, u8, Vec<u8>> = Surface::new(4, 1, vec![1, 2, 3, 4]);
+    let integral: IntegralImage<u32> = IntegralImage::new(&input);
+    integral.sum(2, 0, u32::MAX, 1);
+}
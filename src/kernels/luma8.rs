@@ -0,0 +1,309 @@
+//! Vectorised kernels over `Surface<Luma, u8, _>`.
+//!
+//! Every kernel has a scalar implementation and, on x86-64, SSE2 and AVX2
+//! implementations selected at runtime.  All backends produce identical
+//! output.  The 3x3 kernels only write interior pixels, leaving the
+//! one-pixel border of the output untouched, like `run_luma8_kernel_3x3`.
+
+use std::ops::{Deref, DerefMut};
+
+use super::super::{Luma, Surface};
+
+#[cfg(target_arch = "x86_64")]
+use super::luma8_x86_64 as x86_64;
+
+/// An implementation strategy for the kernels in this module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Scalar,
+    Sse2,
+    Avx2,
+}
+
+impl Backend {
+    /// The fastest backend supported by the running CPU.
+    pub fn detect() -> Backend {
+        if Backend::Avx2.is_supported() {
+            Backend::Avx2
+        } else if Backend::Sse2.is_supported() {
+            Backend::Sse2
+        } else {
+            Backend::Scalar
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn is_supported(self) -> bool {
+        match self {
+            Backend::Scalar => true,
+            Backend::Sse2 => is_x86_feature_detected!("sse2"),
+            Backend::Avx2 => is_x86_feature_detected!("avx2"),
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn is_supported(self) -> bool {
+        self == Backend::Scalar
+    }
+}
+
+/// Computes one output row of a 3x3 kernel from the three input rows
+/// around it, for `x` in `start..width - 1`.
+type RowKernel = fn(&[u8], &[u8], &[u8], &mut [u8], usize);
+
+#[inline]
+pub fn sobel_magnitude(gx: i32, gy: i32) -> u8 {
+    // f32 keeps this bit-identical to the vector paths; sqrt is correctly
+    // rounded in both and the result can never land on a .5 tie.
+    let mag = ((gx * gx + gy * gy) as f32).sqrt().round();
+    if 255.0 < mag { 255 } else { mag as u8 }
+}
+
+pub fn sobel_row_scalar(r0: &[u8], r1: &[u8], r2: &[u8], out: &mut [u8], start: usize) {
+    for x in start..(out.len() - 1) {
+        let (l, c, r) = (x - 1, x, x + 1);
+        let gx = (r0[r] as i32 - r0[l] as i32)
+            + 2 * (r1[r] as i32 - r1[l] as i32)
+            + (r2[r] as i32 - r2[l] as i32);
+        let gy = (r2[l] as i32 + 2 * r2[c] as i32 + r2[r] as i32)
+            - (r0[l] as i32 + 2 * r0[c] as i32 + r0[r] as i32);
+        out[x] = sobel_magnitude(gx, gy);
+    }
+}
+
+pub fn box_blur_row_scalar(r0: &[u8], r1: &[u8], r2: &[u8], out: &mut [u8], start: usize) {
+    for x in start..(out.len() - 1) {
+        let mut acc = 0;
+        for row in &[r0, r1, r2] {
+            acc += row[x - 1] as u32 + row[x] as u32 + row[x + 1] as u32;
+        }
+        out[x] = (acc / 9) as u8;
+    }
+}
+
+fn run_rows<S, S2>(
+    input: &Surface<Luma, u8, S>,
+    output: &mut Surface<Luma, u8, S2>,
+    backend: Backend,
+    kernels: (RowKernel, RowKernel, RowKernel),
+)
+    where
+        S: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    assert_eq!(input.width(), output.width());
+    assert_eq!(input.height(), output.height());
+    assert!(backend.is_supported(), "{:?} is not supported on this CPU", backend);
+
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    if width < 3 || height < 3 {
+        return;
+    }
+    let (scalar, sse2, avx2) = kernels;
+    let kernel = match backend {
+        Backend::Scalar => scalar,
+        Backend::Sse2 => sse2,
+        Backend::Avx2 => avx2,
+    };
+
+    let src = &input.as_storage()[..width * height];
+    let dst = output.as_storage_mut();
+    for (y, out) in dst[..width * height].chunks_mut(width).enumerate().skip(1).take(height - 2) {
+        let rows = &src[(y - 1) * width..];
+        kernel(&rows[..width], &rows[width..][..width], &rows[2 * width..][..width], out, 1);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn sobel_kernels() -> (RowKernel, RowKernel, RowKernel) {
+    (sobel_row_scalar, x86_64::sobel_row_sse2, x86_64::sobel_row_avx2)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn sobel_kernels() -> (RowKernel, RowKernel, RowKernel) {
+    (sobel_row_scalar, sobel_row_scalar, sobel_row_scalar)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn box_blur_kernels() -> (RowKernel, RowKernel, RowKernel) {
+    (box_blur_row_scalar, x86_64::box_blur_row_sse2, x86_64::box_blur_row_avx2)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn box_blur_kernels() -> (RowKernel, RowKernel, RowKernel) {
+    (box_blur_row_scalar, box_blur_row_scalar, box_blur_row_scalar)
+}
+
+/// Sobel gradient magnitude, `min(255, round(sqrt(gx² + gy²)))`.
+pub fn sobel_luma8<S, S2>(input: &Surface<Luma, u8, S>, output: &mut Surface<Luma, u8, S2>)
+    where
+        S: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    sobel_luma8_with(Backend::detect(), input, output)
+}
+
+/// Like `sobel_luma8`, on a specific backend.  Panics if the backend is
+/// not supported.
+pub fn sobel_luma8_with<S, S2>(backend: Backend, input: &Surface<Luma, u8, S>, output: &mut Surface<Luma, u8, S2>)
+    where
+        S: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    run_rows(input, output, backend, sobel_kernels())
+}
+
+/// 3x3 box blur, the truncated mean of each neighbourhood.
+pub fn box_blur_luma8<S, S2>(input: &Surface<Luma, u8, S>, output: &mut Surface<Luma, u8, S2>)
+    where
+        S: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    box_blur_luma8_with(Backend::detect(), input, output)
+}
+
+pub fn box_blur_luma8_with<S, S2>(backend: Backend, input: &Surface<Luma, u8, S>, output: &mut Surface<Luma, u8, S2>)
+    where
+        S: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    run_rows(input, output, backend, box_blur_kernels())
+}
+
+/// Per-pixel `|left - right|`, written to every pixel of `output`.
+pub fn absolute_diff_luma8<S1, S2, S3>(
+    left: &Surface<Luma, u8, S1>,
+    right: &Surface<Luma, u8, S2>,
+    output: &mut Surface<Luma, u8, S3>,
+)
+    where
+        S1: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]>,
+        S3: Deref<Target=[u8]> + DerefMut,
+{
+    absolute_diff_luma8_with(Backend::detect(), left, right, output)
+}
+
+pub fn absolute_diff_luma8_with<S1, S2, S3>(
+    backend: Backend,
+    left: &Surface<Luma, u8, S1>,
+    right: &Surface<Luma, u8, S2>,
+    output: &mut Surface<Luma, u8, S3>,
+)
+    where
+        S1: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]>,
+        S3: Deref<Target=[u8]> + DerefMut,
+{
+    assert_eq!((left.width(), left.height()), (right.width(), right.height()));
+    assert_eq!((left.width(), left.height()), (output.width(), output.height()));
+    assert!(backend.is_supported(), "{:?} is not supported on this CPU", backend);
+
+    let len = left.width() as usize * left.height() as usize;
+    let (a, b) = (&left.as_storage()[..len], &right.as_storage()[..len]);
+    let out = &mut output.as_storage_mut()[..len];
+
+    let done = match backend {
+        Backend::Scalar => 0,
+        #[cfg(target_arch = "x86_64")]
+        Backend::Sse2 => x86_64::absolute_diff_sse2(a, b, out),
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx2 => x86_64::absolute_diff_avx2(a, b, out),
+        #[cfg(not(target_arch = "x86_64"))]
+        _ => 0,
+    };
+    for ((o, &l), &r) in out[done..].iter_mut().zip(&a[done..]).zip(&b[done..]) {
+        *o = l.abs_diff(r);
+    }
+}
+
+#[cfg(test)]
+pub fn noise(width: u32, height: u32, seed: u32) -> Surface<Luma, u8, Box<[u8]>> {
+    let mut state = seed.wrapping_mul(2654435761).wrapping_add(1);
+    let storage: Vec<u8> = (0..width as usize * height as usize)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            // Mix in saturated runs so clamping paths are exercised.
+            match state >> 29 {
+                0 => 0,
+                1 => 255,
+                _ => (state >> 21) as u8,
+            }
+        })
+        .collect();
+    Surface::new(width, height, storage.into_boxed_slice())
+}
+
+#[cfg(test)]
+const BACKENDS: [Backend; 3] = [Backend::Scalar, Backend::Sse2, Backend::Avx2];
+
+#[test]
+fn sobel_backends_agree() {
+    use super::super::Pixel;
+    use super::super::colorspace::ColorL;
+
+    for &(w, h) in &[(3, 3), (17, 5), (18, 4), (64, 9), (131, 23)] {
+        let input = noise(w, h, w * h);
+        let mut reference: Surface<Luma, u8, Box<[u8]>> = Surface::new_black(w, h);
+        sobel_luma8_with(Backend::Scalar, &input, &mut reference);
+
+        // The scalar path agrees with a straightforward neighbourhood loop.
+        for y in 1..(h - 1) {
+            for x in 1..(w - 1) {
+                let px = |dx: u32, dy: u32| input.get_pixel(x + dx - 1, y + dy - 1).l as i32;
+                let gx = px(2, 0) + 2 * px(2, 1) + px(2, 2) - px(0, 0) - 2 * px(0, 1) - px(0, 2);
+                let gy = px(0, 2) + 2 * px(1, 2) + px(2, 2) - px(0, 0) - 2 * px(1, 0) - px(2, 0);
+                let expected = ((gx * gx + gy * gy) as f64).sqrt().round().min(255.0) as u8;
+                assert_eq!(reference.get_pixel(x, y).l, expected);
+            }
+        }
+        assert_eq!(reference.get_pixel(0, 0).l, ColorL::<u8>::black().l);
+
+        for &backend in BACKENDS.iter().filter(|b| b.is_supported()) {
+            let mut out: Surface<Luma, u8, Box<[u8]>> = Surface::new_black(w, h);
+            sobel_luma8_with(backend, &input, &mut out);
+            assert_eq!(out.as_storage(), reference.as_storage(), "{:?} {}x{}", backend, w, h);
+        }
+    }
+}
+
+#[test]
+fn box_blur_backends_agree() {
+    for &(w, h) in &[(3, 3), (17, 5), (18, 4), (64, 9), (131, 23)] {
+        let input = noise(w, h, 7 + w);
+        let mut reference: Surface<Luma, u8, Box<[u8]>> = Surface::new_black(w, h);
+        box_blur_luma8_with(Backend::Scalar, &input, &mut reference);
+
+        for &backend in BACKENDS.iter().filter(|b| b.is_supported()) {
+            let mut out: Surface<Luma, u8, Box<[u8]>> = Surface::new_black(w, h);
+            box_blur_luma8_with(backend, &input, &mut out);
+            assert_eq!(out.as_storage(), reference.as_storage(), "{:?} {}x{}", backend, w, h);
+        }
+    }
+
+    let flat: Surface<Luma, u8, Box<[u8]>> = Surface::new(3, 3, vec![200; 9].into_boxed_slice());
+    let mut out: Surface<Luma, u8, Box<[u8]>> = Surface::new_black(3, 3);
+    box_blur_luma8(&flat, &mut out);
+    assert_eq!(out.get_pixel(1, 1).l, 200);
+}
+
+#[test]
+fn absolute_diff_backends_agree() {
+    for &(w, h) in &[(1, 1), (15, 3), (33, 2), (100, 7)] {
+        let (left, right) = (noise(w, h, 1), noise(w, h, 2));
+        let mut reference: Surface<Luma, u8, Box<[u8]>> = Surface::new_black(w, h);
+        absolute_diff_luma8_with(Backend::Scalar, &left, &right, &mut reference);
+        for (i, &d) in reference.as_storage().iter().enumerate() {
+            let (l, r) = (left.as_storage()[i] as i16, right.as_storage()[i] as i16);
+            assert_eq!(d as i16, (l - r).abs());
+        }
+
+        for &backend in BACKENDS.iter().filter(|b| b.is_supported()) {
+            let mut out: Surface<Luma, u8, Box<[u8]>> = Surface::new_black(w, h);
+            absolute_diff_luma8_with(backend, &left, &right, &mut out);
+            assert_eq!(out.as_storage(), reference.as_storage(), "{:?} {}x{}", backend, w, h);
+        }
+    }
+}
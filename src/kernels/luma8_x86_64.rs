@@ -0,0 +1,207 @@
+//! SSE2 and AVX2 row kernels for `luma8`.
+//!
+//! Each vector routine handles as many pixels as it can and returns the
+//! index of the first pixel left over, which the scalar routine finishes.
+//! The safe wrappers are only reachable once `Backend::is_supported` has
+//! been checked.
+
+use std::arch::x86_64::*;
+
+use super::luma8::{sobel_row_scalar, box_blur_row_scalar};
+
+// floor(sum / 9) == (sum * BOX_DIV_9) >> 16 for every 3x3 sum of u8.
+const BOX_DIV_9: i16 = 7282;
+
+pub fn sobel_row_sse2(r0: &[u8], r1: &[u8], r2: &[u8], out: &mut [u8], start: usize) {
+    let next = unsafe { sobel_sse2(r0, r1, r2, out, start) };
+    sobel_row_scalar(r0, r1, r2, out, next);
+}
+
+pub fn sobel_row_avx2(r0: &[u8], r1: &[u8], r2: &[u8], out: &mut [u8], start: usize) {
+    let next = unsafe { sobel_avx2(r0, r1, r2, out, start) };
+    sobel_row_scalar(r0, r1, r2, out, next);
+}
+
+pub fn box_blur_row_sse2(r0: &[u8], r1: &[u8], r2: &[u8], out: &mut [u8], start: usize) {
+    let next = unsafe { box_blur_sse2(r0, r1, r2, out, start) };
+    box_blur_row_scalar(r0, r1, r2, out, next);
+}
+
+pub fn box_blur_row_avx2(r0: &[u8], r1: &[u8], r2: &[u8], out: &mut [u8], start: usize) {
+    let next = unsafe { box_blur_avx2(r0, r1, r2, out, start) };
+    box_blur_row_scalar(r0, r1, r2, out, next);
+}
+
+pub fn absolute_diff_sse2(a: &[u8], b: &[u8], out: &mut [u8]) -> usize {
+    unsafe { absolute_diff_sse2_impl(a, b, out) }
+}
+
+pub fn absolute_diff_avx2(a: &[u8], b: &[u8], out: &mut [u8]) -> usize {
+    unsafe { absolute_diff_avx2_impl(a, b, out) }
+}
+
+/// Widens 8 bytes at `row[offset..]` to 16-bit lanes.
+#[inline]
+#[target_feature(enable = "sse2")]
+unsafe fn load8_epi16(row: &[u8], offset: usize) -> __m128i {
+    debug_assert!(offset + 8 <= row.len());
+    let bytes = _mm_loadl_epi64(row.as_ptr().add(offset) as *const __m128i);
+    _mm_unpacklo_epi8(bytes, _mm_setzero_si128())
+}
+
+/// Widens 16 bytes at `row[offset..]` to 16-bit lanes.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn load16_epi16(row: &[u8], offset: usize) -> __m256i {
+    debug_assert!(offset + 16 <= row.len());
+    _mm256_cvtepu8_epi16(_mm_loadu_si128(row.as_ptr().add(offset) as *const __m128i))
+}
+
+/// Narrows 16 16-bit lanes, in order, to saturated bytes.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn store16_epu8(out: &mut [u8], offset: usize, v: __m256i) {
+    debug_assert!(offset + 16 <= out.len());
+    let packed = _mm256_permute4x64_epi64::<0b00_00_10_00>(_mm256_packus_epi16(v, v));
+    _mm_storeu_si128(out.as_mut_ptr().add(offset) as *mut __m128i, _mm256_castsi256_si128(packed));
+}
+
+#[inline]
+#[target_feature(enable = "sse2")]
+unsafe fn magnitude_sse2(gx: __m128i, gy: __m128i) -> __m128i {
+    let lo = _mm_unpacklo_epi16(gx, gy);
+    let hi = _mm_unpackhi_epi16(gx, gy);
+    let lo = _mm_cvtps_epi32(_mm_sqrt_ps(_mm_cvtepi32_ps(_mm_madd_epi16(lo, lo))));
+    let hi = _mm_cvtps_epi32(_mm_sqrt_ps(_mm_cvtepi32_ps(_mm_madd_epi16(hi, hi))));
+    _mm_packs_epi32(lo, hi)
+}
+
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn magnitude_avx2(gx: __m256i, gy: __m256i) -> __m256i {
+    // unpack works within 128-bit lanes, and packs undoes it.
+    let lo = _mm256_unpacklo_epi16(gx, gy);
+    let hi = _mm256_unpackhi_epi16(gx, gy);
+    let lo = _mm256_cvtps_epi32(_mm256_sqrt_ps(_mm256_cvtepi32_ps(_mm256_madd_epi16(lo, lo))));
+    let hi = _mm256_cvtps_epi32(_mm256_sqrt_ps(_mm256_cvtepi32_ps(_mm256_madd_epi16(hi, hi))));
+    _mm256_packs_epi32(lo, hi)
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn sobel_sse2(r0: &[u8], r1: &[u8], r2: &[u8], out: &mut [u8], start: usize) -> usize {
+    let width = out.len();
+    let mut x = start;
+    while x + 8 < width {
+        let (l0, c0, r0v) = (load8_epi16(r0, x - 1), load8_epi16(r0, x), load8_epi16(r0, x + 1));
+        let (l1, r1v) = (load8_epi16(r1, x - 1), load8_epi16(r1, x + 1));
+        let (l2, c2, r2v) = (load8_epi16(r2, x - 1), load8_epi16(r2, x), load8_epi16(r2, x + 1));
+
+        let d1 = _mm_sub_epi16(r1v, l1);
+        let gx = _mm_add_epi16(
+            _mm_add_epi16(_mm_sub_epi16(r0v, l0), _mm_sub_epi16(r2v, l2)),
+            _mm_add_epi16(d1, d1));
+        let top = _mm_add_epi16(_mm_add_epi16(l0, r0v), _mm_add_epi16(c0, c0));
+        let bottom = _mm_add_epi16(_mm_add_epi16(l2, r2v), _mm_add_epi16(c2, c2));
+        let gy = _mm_sub_epi16(bottom, top);
+
+        let mag = magnitude_sse2(gx, gy);
+        _mm_storel_epi64(out.as_mut_ptr().add(x) as *mut __m128i, _mm_packus_epi16(mag, mag));
+        x += 8;
+    }
+    x
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn sobel_avx2(r0: &[u8], r1: &[u8], r2: &[u8], out: &mut [u8], start: usize) -> usize {
+    let width = out.len();
+    let mut x = start;
+    while x + 16 < width {
+        let (l0, c0, r0v) = (load16_epi16(r0, x - 1), load16_epi16(r0, x), load16_epi16(r0, x + 1));
+        let (l1, r1v) = (load16_epi16(r1, x - 1), load16_epi16(r1, x + 1));
+        let (l2, c2, r2v) = (load16_epi16(r2, x - 1), load16_epi16(r2, x), load16_epi16(r2, x + 1));
+
+        let d1 = _mm256_sub_epi16(r1v, l1);
+        let gx = _mm256_add_epi16(
+            _mm256_add_epi16(_mm256_sub_epi16(r0v, l0), _mm256_sub_epi16(r2v, l2)),
+            _mm256_add_epi16(d1, d1));
+        let top = _mm256_add_epi16(_mm256_add_epi16(l0, r0v), _mm256_add_epi16(c0, c0));
+        let bottom = _mm256_add_epi16(_mm256_add_epi16(l2, r2v), _mm256_add_epi16(c2, c2));
+        let gy = _mm256_sub_epi16(bottom, top);
+
+        store16_epu8(out, x, magnitude_avx2(gx, gy));
+        x += 16;
+    }
+    x
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn box_blur_sse2(r0: &[u8], r1: &[u8], r2: &[u8], out: &mut [u8], start: usize) -> usize {
+    let width = out.len();
+    let div = _mm_set1_epi16(BOX_DIV_9);
+    let mut x = start;
+    while x + 8 < width {
+        let mut acc = _mm_setzero_si128();
+        for row in &[r0, r1, r2] {
+            acc = _mm_add_epi16(acc, load8_epi16(row, x - 1));
+            acc = _mm_add_epi16(acc, load8_epi16(row, x));
+            acc = _mm_add_epi16(acc, load8_epi16(row, x + 1));
+        }
+        let mean = _mm_mulhi_epu16(acc, div);
+        _mm_storel_epi64(out.as_mut_ptr().add(x) as *mut __m128i, _mm_packus_epi16(mean, mean));
+        x += 8;
+    }
+    x
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn box_blur_avx2(r0: &[u8], r1: &[u8], r2: &[u8], out: &mut [u8], start: usize) -> usize {
+    let width = out.len();
+    let div = _mm256_set1_epi16(BOX_DIV_9);
+    let mut x = start;
+    while x + 16 < width {
+        let mut acc = _mm256_setzero_si256();
+        for row in &[r0, r1, r2] {
+            acc = _mm256_add_epi16(acc, load16_epi16(row, x - 1));
+            acc = _mm256_add_epi16(acc, load16_epi16(row, x));
+            acc = _mm256_add_epi16(acc, load16_epi16(row, x + 1));
+        }
+        store16_epu8(out, x, _mm256_mulhi_epu16(acc, div));
+        x += 16;
+    }
+    x
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn absolute_diff_sse2_impl(a: &[u8], b: &[u8], out: &mut [u8]) -> usize {
+    let len = out.len();
+    let mut i = 0;
+    while i + 16 <= len {
+        let va = _mm_loadu_si128(a.as_ptr().add(i) as *const __m128i);
+        let vb = _mm_loadu_si128(b.as_ptr().add(i) as *const __m128i);
+        let diff = _mm_or_si128(_mm_subs_epu8(va, vb), _mm_subs_epu8(vb, va));
+        _mm_storeu_si128(out.as_mut_ptr().add(i) as *mut __m128i, diff);
+        i += 16;
+    }
+    i
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn absolute_diff_avx2_impl(a: &[u8], b: &[u8], out: &mut [u8]) -> usize {
+    let len = out.len();
+    let mut i = 0;
+    while i + 32 <= len {
+        let va = _mm256_loadu_si256(a.as_ptr().add(i) as *const __m256i);
+        let vb = _mm256_loadu_si256(b.as_ptr().add(i) as *const __m256i);
+        let diff = _mm256_or_si256(_mm256_subs_epu8(va, vb), _mm256_subs_epu8(vb, va));
+        _mm256_storeu_si256(out.as_mut_ptr().add(i) as *mut __m256i, diff);
+        i += 32;
+    }
+    i
+}
+
+#[test]
+fn box_div_9_is_exact() {
+    for sum in 0..(9 * 255 + 1) {
+        assert_eq!((sum * BOX_DIV_9 as u32) >> 16, sum / 9);
+    }
+}
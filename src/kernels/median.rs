@@ -0,0 +1,220 @@
+//! Median filtering of packed surfaces, channel by channel.
+//!
+//! The window is a `(2 * radius + 1)`-wide square clipped to the surface,
+//! so every pixel of the output is written.  Clipped windows may hold an
+//! even number of samples, in which case the lower median is used.
+
+use std::ops::{Deref, DerefMut};
+
+use super::super::{Channel, Format, PackedFormat, Surface};
+
+/// Radius above which `u8` channels use the constant-time histogram
+/// method.  Below it, sorting the window is faster.
+const HISTOGRAM_RADIUS: usize = 3;
+
+/// Median of every window of one channel by selection.
+fn median_by_selection<C: Channel>(
+    src: &[C],
+    dst: &mut [C],
+    stride: usize,
+    width: usize,
+    height: usize,
+    radius: usize,
+) {
+    let mut window = Vec::with_capacity((2 * radius + 1) * (2 * radius + 1));
+    for y in 0..height {
+        let (y0, y1) = (y.saturating_sub(radius), (y + radius + 1).min(height));
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(width));
+            window.clear();
+            for wy in y0..y1 {
+                window.extend((x0..x1).map(|wx| src[(wy * width + wx) * stride]));
+            }
+            let mid = (window.len() - 1) / 2;
+            window.select_nth_unstable_by(mid, |a, b| a.to_f64().unwrap().total_cmp(&b.to_f64().unwrap()));
+            dst[(y * width + x) * stride] = window[mid];
+        }
+    }
+}
+
+/// Median of every window of one `u8` channel, in time independent of the
+/// radius (Perreault and Hébert).  One histogram per column covers the
+/// window's rows; the window histogram slides along each row adding and
+/// removing whole column histograms.
+///
+/// Only `u8` is supported: merging a column costs one add per bin, and
+/// 65536 bins per column would cost more than sorting any practical
+/// window.
+fn median_by_histogram<C: Channel>(
+    src: &[C],
+    dst: &mut [C],
+    stride: usize,
+    width: usize,
+    height: usize,
+    radius: usize,
+) {
+    let at = |x: usize, y: usize| src[(y * width + x) * stride].to_usize().unwrap();
+    let mut columns = vec![[0u32; 256]; width];
+    for y in 0..radius.min(height) {
+        for (x, column) in columns.iter_mut().enumerate() {
+            column[at(x, y)] += 1;
+        }
+    }
+
+    for y in 0..height {
+        // Slide the column histograms down to rows y - radius ..= y + radius.
+        if y + radius < height {
+            for (x, column) in columns.iter_mut().enumerate() {
+                column[at(x, y + radius)] += 1;
+            }
+        }
+        if y > radius {
+            for (x, column) in columns.iter_mut().enumerate() {
+                column[at(x, y - radius - 1)] -= 1;
+            }
+        }
+        let rows = (y + radius + 1).min(height) - y.saturating_sub(radius);
+
+        let mut window = [0u32; 256];
+        for column in &columns[..radius.min(width)] {
+            for (bin, &count) in window.iter_mut().zip(column.iter()) {
+                *bin += count;
+            }
+        }
+        for x in 0..width {
+            if x + radius < width {
+                for (bin, &count) in window.iter_mut().zip(columns[x + radius].iter()) {
+                    *bin += count;
+                }
+            }
+            if x > radius {
+                for (bin, &count) in window.iter_mut().zip(columns[x - radius - 1].iter()) {
+                    *bin -= count;
+                }
+            }
+            let cols = (x + radius + 1).min(width) - x.saturating_sub(radius);
+            let rank = ((rows * cols - 1) / 2) as u32;
+            let mut seen = 0;
+            for (value, &count) in window.iter().enumerate() {
+                seen += count;
+                if seen > rank {
+                    dst[(y * width + x) * stride] = C::from_f64(value as f64);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Median filter with a `(2 * radius + 1)`-wide square window, applied to
+/// each channel separately.  `u8` channels with large radii use a
+/// histogram method whose cost does not grow with the radius; other
+/// channel types always sort the window, so their cost grows with the
+/// square of the radius.  Float channels order NaN above every number.
+pub fn median_filter<M, C, S, S2>(input: &Surface<M, C, S>, radius: u32, output: &mut Surface<M, C, S2>)
+    where
+        M: PackedFormat<C>,
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[C]> + DerefMut,
+{
+    assert_eq!(input.width(), output.width());
+    assert_eq!(input.height(), output.height());
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let radius = radius as usize;
+    let channels = <M as Format<C>>::channel_data_size(1, 1);
+    let len = width * height * channels;
+    let (src, dst) = (&input.as_storage()[..len], &mut output.as_storage_mut()[..len]);
+    let histogram = radius > HISTOGRAM_RADIUS && C::max_depth() == Some(0xFF);
+    for channel in 0..channels {
+        let (src, dst) = (&src[channel..], &mut dst[channel..]);
+        if histogram {
+            median_by_histogram(src, dst, channels, width, height, radius);
+        } else {
+            median_by_selection(src, dst, channels, width, height, radius);
+        }
+    }
+}
+
+/// 3x3 median filter.
+pub fn median_3x3<M, C, S, S2>(input: &Surface<M, C, S>, output: &mut Surface<M, C, S2>)
+    where
+        M: PackedFormat<C>,
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[C]> + DerefMut,
+{
+    median_filter(input, 1, output)
+}
+
+/// 5x5 median filter.
+pub fn median_5x5<M, C, S, S2>(input: &Surface<M, C, S>, output: &mut Surface<M, C, S2>)
+    where
+        M: PackedFormat<C>,
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[C]> + DerefMut,
+{
+    median_filter(input, 2, output)
+}
+
+#[test]
+fn median_removes_salt_and_pepper() {
+    use super::super::Luma;
+
+    let mut storage = vec![100u8; 49];
+    storage[8] = 255;
+    storage[24] = 0;
+    storage[40] = 255;
+    let input: Surface<Luma, u8, Vec<u8>> = Surface::new(7, 7, storage);
+    let mut output: Surface<Luma, u8, Vec<u8>> = Surface::new_black(7, 7);
+    median_3x3(&input, &mut output);
+    assert!(output.as_storage().iter().all(|&v| v == 100));
+    median_5x5(&input, &mut output);
+    assert!(output.as_storage().iter().all(|&v| v == 100));
+}
+
+#[test]
+fn median_per_channel_and_clipped() {
+    use super::super::Rgb;
+
+    let storage = vec![
+        1, 50, 9,   2, 40, 9,   3, 30, 9,
+        4, 20, 9,   5, 10, 9,   6, 0, 9,
+    ];
+    let input: Surface<Rgb, u8, Vec<u8>> = Surface::new(3, 2, storage);
+    let mut output: Surface<Rgb, u8, Vec<u8>> = Surface::new_black(3, 2);
+    median_3x3(&input, &mut output);
+    // The corner window holds 1, 2, 4, 5: the lower median is 2.
+    assert_eq!(&output.as_storage()[..3], &[2, 20, 9][..]);
+    // The middle windows hold all six values.
+    assert_eq!(&output.as_storage()[3..6], &[3, 20, 9][..]);
+
+    let wide: Surface<Rgb, u16, Vec<u16>> = Surface::new(1, 3, vec![7, 1000, 0, 9, 3, 0, 8, 2000, 0]);
+    let mut wide_out: Surface<Rgb, u16, Vec<u16>> = Surface::new_black(1, 3);
+    median_3x3(&wide, &mut wide_out);
+    assert_eq!(&wide_out.as_storage()[3..6], &[8, 1000, 0][..]);
+
+    let float: Surface<Rgb, f64, Vec<f64>> = Surface::new(1, 3, vec![0.1, f64::NAN, 0.0, 0.3, 0.5, 0.0, 0.2, 0.4, 0.0]);
+    let mut float_out: Surface<Rgb, f64, Vec<f64>> = Surface::new_black(1, 3);
+    median_3x3(&float, &mut float_out);
+    assert_eq!(&float_out.as_storage()[3..6], &[0.2, 0.5, 0.0][..]);
+}
+
+#[test]
+fn histogram_median_matches_selection() {
+    use super::super::Luma;
+    use super::luma8::noise;
+
+    let input = noise(23, 17, 5);
+    for &radius in &[1usize, 4, 7, 30] {
+        let (mut by_hist, mut by_sort) = (vec![0u8; 23 * 17], vec![0u8; 23 * 17]);
+        median_by_histogram(&input.as_storage()[..], &mut by_hist, 1, 23, 17, radius);
+        median_by_selection(&input.as_storage()[..], &mut by_sort, 1, 23, 17, radius);
+        assert_eq!(by_hist, by_sort, "radius {}", radius);
+
+        let mut output: Surface<Luma, u8, Vec<u8>> = Surface::new_black(23, 17);
+        median_filter(&input, radius as u32, &mut output);
+        assert_eq!(output.as_storage(), &by_sort);
+    }
+}
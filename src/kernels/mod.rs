@@ -7,13 +7,81 @@ use super::{
     ColorYUV,
 };
 
+mod bilateral;
+mod canny;
+mod integral;
 mod kernel_3x3;
+mod luma8;
+mod median;
+#[cfg(target_arch = "x86_64")]
+mod luma8_x86_64;
+mod morphology;
+mod sharpen;
+mod threshold;
 
 pub use self::kernel_3x3::{
     Sobel3x3,
     Average3x3,
 };
 
+pub use self::luma8::{
+    Backend,
+    sobel_luma8,
+    sobel_luma8_with,
+    box_blur_luma8,
+    box_blur_luma8_with,
+    absolute_diff_luma8,
+    absolute_diff_luma8_with,
+};
+
+pub use self::sharpen::{
+    UnsharpMask,
+    unsharp_mask_plane,
+    laplacian_sharpen_plane,
+    unsharp_mask,
+    laplacian_sharpen,
+};
+
+pub use self::threshold::{
+    MASK_OFF,
+    MASK_ON,
+    AdaptiveMethod,
+    threshold,
+    otsu_level,
+    threshold_otsu,
+    threshold_adaptive,
+};
+
+pub use self::bilateral::bilateral_filter;
+
+pub use self::canny::{
+    Canny,
+    sobel_gradients,
+    sobel_polar,
+    canny,
+};
+
+pub use self::integral::{
+    Accumulator,
+    IntegralImage,
+    box_blur,
+    local_mean_variance,
+};
+
+pub use self::median::{
+    median_filter,
+    median_3x3,
+    median_5x5,
+};
+
+pub use self::morphology::{
+    StructuringElement,
+    erode,
+    dilate,
+    open,
+    close,
+};
+
 
 pub trait Kernel3x3<C>
     where
@@ -0,0 +1,224 @@
+//! Erosion, dilation, opening and closing of `Luma` surfaces.
+//!
+//! Erosion takes the minimum and dilation the maximum over the pixels
+//! selected by a structuring element, so the operations work on grayscale
+//! images as well as on threshold masks.  Like the 3x3 kernels, only pixels
+//! whose whole element lies inside the surface are written; the border of
+//! `output` is left untouched.
+
+use std::ops::{Deref, DerefMut};
+
+use super::super::{Luma, Surface};
+
+/// A neighbourhood shape with odd dimensions, anchored at its centre.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StructuringElement {
+    width: u32,
+    height: u32,
+    mask: Vec<bool>,
+}
+
+impl StructuringElement {
+    /// An element from a row-major `mask`.  Both dimensions must be odd.
+    pub fn new(width: u32, height: u32, mask: Vec<bool>) -> StructuringElement {
+        assert!(width % 2 == 1 && height % 2 == 1, "structuring elements need odd dimensions");
+        assert_eq!(mask.len(), width as usize * height as usize);
+        StructuringElement { width, height, mask }
+    }
+
+    /// A `width`x`height` rectangle.
+    pub fn rect(width: u32, height: u32) -> StructuringElement {
+        StructuringElement::new(width, height, vec![true; width as usize * height as usize])
+    }
+
+    /// A `size`x`size` square.
+    pub fn square(size: u32) -> StructuringElement {
+        StructuringElement::rect(size, size)
+    }
+
+    /// The centre row and column of a `size`x`size` square.
+    pub fn cross(size: u32) -> StructuringElement {
+        let centre = size / 2;
+        let mask = (0..size * size)
+            .map(|i| i % size == centre || i / size == centre)
+            .collect();
+        StructuringElement::new(size, size, mask)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Offsets of the selected pixels from the top-left of the element.
+    fn offsets(&self, stride: usize) -> Vec<usize> {
+        let width = self.width as usize;
+        self.mask.iter().enumerate()
+            .filter(|&(_, &on)| on)
+            .map(|(i, _)| (i / width) * stride + i % width)
+            .collect()
+    }
+}
+
+fn apply<S, S2, F>(
+    input: &Surface<Luma, u8, S>,
+    element: &StructuringElement,
+    output: &mut Surface<Luma, u8, S2>,
+    init: u8,
+    combine: F,
+)
+    where
+        S: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+        F: Fn(u8, u8) -> u8,
+{
+    assert_eq!(input.width(), output.width());
+    assert_eq!(input.height(), output.height());
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let (se_width, se_height) = (element.width as usize, element.height as usize);
+    if width < se_width || height < se_height {
+        return;
+    }
+    let (rx, ry) = (se_width / 2, se_height / 2);
+    let offsets = element.offsets(width);
+    let src = input.as_storage();
+    let dst = output.as_storage_mut();
+    for y in 0..(height - se_height + 1) {
+        for x in 0..(width - se_width + 1) {
+            let base = y * width + x;
+            dst[(y + ry) * width + x + rx] = offsets.iter()
+                .fold(init, |acc, &offset| combine(acc, src[base + offset]));
+        }
+    }
+}
+
+/// Minimum over `element` around each pixel.
+pub fn erode<S, S2>(input: &Surface<Luma, u8, S>, element: &StructuringElement, output: &mut Surface<Luma, u8, S2>)
+    where
+        S: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    apply(input, element, output, 255, ::std::cmp::min);
+}
+
+/// Maximum over `element` around each pixel.
+pub fn dilate<S, S2>(input: &Surface<Luma, u8, S>, element: &StructuringElement, output: &mut Surface<Luma, u8, S2>)
+    where
+        S: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    apply(input, element, output, 0, ::std::cmp::max);
+}
+
+/// Erosion followed by dilation; removes specks smaller than `element`.
+/// The intermediate starts as a copy of `input`, so border pixels pass
+/// through the first step unchanged.
+pub fn open<S, S2>(input: &Surface<Luma, u8, S>, element: &StructuringElement, output: &mut Surface<Luma, u8, S2>)
+    where
+        S: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    let mut eroded = input.to_owned();
+    erode(input, element, &mut eroded);
+    dilate(&eroded, element, output);
+}
+
+/// Dilation followed by erosion; fills holes smaller than `element`.
+/// The intermediate starts as a copy of `input`, as in `open`.
+pub fn close<S, S2>(input: &Surface<Luma, u8, S>, element: &StructuringElement, output: &mut Surface<Luma, u8, S2>)
+    where
+        S: Deref<Target=[u8]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    let mut dilated = input.to_owned();
+    dilate(input, element, &mut dilated);
+    erode(&dilated, element, output);
+}
+
+#[cfg(test)]
+fn mask_from(rows: &[&str]) -> Surface<Luma, u8, Vec<u8>> {
+    let storage = rows.iter()
+        .flat_map(|row| row.bytes().map(|b| if b == b'#' { 255 } else { 0 }))
+        .collect();
+    Surface::new(rows[0].len() as u32, rows.len() as u32, storage)
+}
+
+#[test]
+fn erode_and_dilate_square() {
+    let input = mask_from(&[
+        ".......",
+        ".####..",
+        ".####..",
+        ".####..",
+        ".......",
+    ]);
+    let se = StructuringElement::square(3);
+
+    let mut eroded: Surface<Luma, u8, Vec<u8>> = Surface::new(7, 5, vec![9; 35]);
+    erode(&input, &se, &mut eroded);
+    let interior: Vec<u8> = eroded.as_storage()[7..28].to_vec();
+    assert_eq!(interior, vec![
+        9, 0, 0, 0, 0, 0, 9,
+        9, 0, 255, 255, 0, 0, 9,
+        9, 0, 0, 0, 0, 0, 9,
+    ]);
+    assert!(eroded.as_storage()[..7].iter().all(|&v| v == 9));
+
+    let mut dilated: Surface<Luma, u8, Vec<u8>> = Surface::new_black(7, 5);
+    dilate(&input, &se, &mut dilated);
+    assert_eq!(&dilated.as_storage()[7..14], &[0, 255, 255, 255, 255, 255, 0][..]);
+    assert_eq!(&dilated.as_storage()[28..35], &[0; 7][..]);
+}
+
+#[test]
+fn open_removes_specks_and_close_fills_holes() {
+    let specks = mask_from(&[
+        ".......",
+        ".#.....",
+        "...###.",
+        "...###.",
+        "...###.",
+        ".......",
+    ]);
+    let se = StructuringElement::square(3);
+    let mut opened: Surface<Luma, u8, Vec<u8>> = Surface::new_black(7, 6);
+    open(&specks, &se, &mut opened);
+    assert_eq!(opened.get_pixel(1, 1).l, 0);
+    assert_eq!(opened.get_pixel(4, 3).l, 255);
+    assert_eq!(opened.get_pixel(3, 2).l, 255);
+
+    let holed = mask_from(&[
+        "#######",
+        "#######",
+        "###.###",
+        "#######",
+        "#######",
+    ]);
+    let mut closed: Surface<Luma, u8, Vec<u8>> = Surface::new_black(7, 5);
+    close(&holed, &StructuringElement::cross(3), &mut closed);
+    assert_eq!(closed.get_pixel(3, 2).l, 255);
+}
+
+#[test]
+fn custom_elements() {
+    let cross = StructuringElement::cross(3);
+    assert_eq!(cross.mask, vec![false, true, false, true, true, true, false, true, false]);
+
+    // A horizontal line only grows sideways.
+    let line = StructuringElement::rect(3, 1);
+    let input = mask_from(&[
+        ".....",
+        "..#..",
+        ".....",
+    ]);
+    let mut out: Surface<Luma, u8, Vec<u8>> = Surface::new_black(5, 3);
+    dilate(&input, &line, &mut out);
+    assert_eq!(&out.as_storage()[..], &mask_from(&[
+        ".....",
+        ".###.",
+        ".....",
+    ]).as_storage()[..]);
+}
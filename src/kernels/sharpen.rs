@@ -0,0 +1,199 @@
+//! Unsharp masking and Laplacian sharpening.
+//!
+//! For YUV formats only the luma plane is sharpened, since sharpening
+//! chroma causes colour fringes.  Other formats have every colour channel
+//! sharpened and alpha left alone.  Edges are handled by clamping the
+//! filter taps, so every pixel is processed.
+
+use std::ops::{Deref, DerefMut};
+
+use super::super::{Channel, ChannelAccess, Surface, ToneFormat};
+#[cfg(test)]
+use super::super::{Luma, Rgba, Yuv420p, Yuv444};
+use super::canny::gaussian_blur;
+
+/// Parameters of `unsharp_mask`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnsharpMask {
+    /// Standard deviation of the blur, in pixels.
+    pub radius: f64,
+    /// How much of the difference from the blur is added back.
+    pub amount: f64,
+    /// Smallest difference from the blur, in channel units, that is
+    /// sharpened.  Raising it keeps flat, noisy areas from being
+    /// amplified.
+    pub threshold: f64,
+}
+
+impl Default for UnsharpMask {
+    fn default() -> UnsharpMask {
+        UnsharpMask { radius: 1.0, amount: 1.0, threshold: 0.0 }
+    }
+}
+
+fn clamp_channel<C: Channel>(val: f64) -> C {
+    let lo = C::min_value().to_f64().unwrap();
+    let hi = C::max_value().to_f64().unwrap();
+    C::from_f64(val.max(lo).min(hi))
+}
+
+/// Unsharp masking of a `width`x`height` plane.
+pub fn unsharp_mask_plane<C: Channel>(plane: &mut [C], width: u32, height: u32, params: UnsharpMask) {
+    let (width, height) = (width as usize, height as usize);
+    let plane = &mut plane[..width * height];
+    let values: Vec<f64> = plane.iter().map(|v| v.to_f64().unwrap()).collect();
+    let blurred = gaussian_blur(&values, width, height, params.radius);
+    for ((out, &value), &blur) in plane.iter_mut().zip(&values).zip(&blurred) {
+        let detail = value - blur;
+        if detail.abs() >= params.threshold {
+            *out = clamp_channel(value + params.amount * detail);
+        }
+    }
+}
+
+/// Laplacian sharpening of a `width`x`height` plane: subtracts `amount`
+/// times the four-neighbour Laplacian from each value.
+pub fn laplacian_sharpen_plane<C: Channel>(plane: &mut [C], width: u32, height: u32, amount: f64) {
+    let (width, height) = (width as usize, height as usize);
+    let plane = &mut plane[..width * height];
+    let values: Vec<f64> = plane.iter().map(|v| v.to_f64().unwrap()).collect();
+    for y in 0..height {
+        let (up, down) = (y.saturating_sub(1), (y + 1).min(height - 1));
+        for x in 0..width {
+            let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+            let value = values[y * width + x];
+            let laplacian = values[up * width + x] + values[down * width + x]
+                + values[y * width + left] + values[y * width + right]
+                - 4.0 * value;
+            plane[y * width + x] = clamp_channel(value - amount * laplacian);
+        }
+    }
+}
+
+/// Runs `f` on each plane of `surface` that should be sharpened.
+fn with_sharpened_planes<M, C, S, F>(surface: &mut Surface<M, C, S>, mut f: F)
+    where
+        M: ToneFormat<C>,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+        F: FnMut(&mut [C], u32, u32),
+{
+    let (width, height) = (surface.width(), surface.height());
+    let pixels = width as usize * height as usize;
+    if M::LUMA_PLANE {
+        f(&mut surface.as_storage_mut()[..pixels], width, height);
+        return;
+    }
+
+    let channels = <M as ToneFormat<C>>::TONE_CHANNELS;
+    let mut planes = vec![Vec::with_capacity(pixels); channels];
+    for px in surface.iter_pixels() {
+        for (index, plane) in planes.iter_mut().enumerate() {
+            plane.push(px.channel(index));
+        }
+    }
+    for plane in planes.iter_mut() {
+        f(plane, width, height);
+    }
+    surface.map_in_place(|x, y, px| {
+        let at = y as usize * width as usize + x as usize;
+        px.map_channels(|index, value| if index < channels { planes[index][at] } else { value })
+    });
+}
+
+/// Unsharp masking: adds back `amount` times the difference between each
+/// value and a Gaussian blur of radius `params.radius`.
+pub fn unsharp_mask<M, C, S>(surface: &mut Surface<M, C, S>, params: UnsharpMask)
+    where
+        M: ToneFormat<C>,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    with_sharpened_planes(surface, |plane, width, height| unsharp_mask_plane(plane, width, height, params));
+}
+
+/// Laplacian sharpening with the given strength; 1.0 is the classic
+/// `[0, -1, 0; -1, 5, -1; 0, -1, 0]` kernel.
+pub fn laplacian_sharpen<M, C, S>(surface: &mut Surface<M, C, S>, amount: f64)
+    where
+        M: ToneFormat<C>,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    with_sharpened_planes(surface, |plane, width, height| laplacian_sharpen_plane(plane, width, height, amount));
+}
+
+#[cfg(test)]
+fn soft_step(x: u32) -> u8 {
+    match x {
+        0..=3 => 60,
+        4 => 100,
+        5 => 140,
+        _ => 180,
+    }
+}
+
+#[test]
+fn unsharp_mask_steepens_edges() {
+    let storage: Vec<u8> = (0..40).map(|i| soft_step(i % 10)).collect();
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new(10, 4, storage.clone());
+    unsharp_mask(&mut surf, UnsharpMask::default());
+    let row = &surf.as_storage()[10..20];
+    // Overshoot on both sides of the edge, flat areas untouched.
+    assert!(row[3] < 60 && row[6] > 180, "{:?}", row);
+    assert_eq!((row[0], row[9]), (60, 180));
+
+    // A threshold above the edge contrast leaves the image alone.
+    let mut untouched: Surface<Luma, u8, Vec<u8>> = Surface::new(10, 4, storage);
+    unsharp_mask(&mut untouched, UnsharpMask { threshold: 100.0, ..UnsharpMask::default() });
+    assert_eq!(&untouched.as_storage()[10..20], &[60, 60, 60, 60, 100, 140, 180, 180, 180, 180][..]);
+}
+
+#[test]
+fn laplacian_matches_kernel() {
+    let mut surf: Surface<Luma, u8, Vec<u8>> = Surface::new(3, 3, vec![
+        10, 10, 10,
+        10, 20, 10,
+        10, 10, 10,
+    ]);
+    laplacian_sharpen(&mut surf, 1.0);
+    assert_eq!(surf.get_pixel(1, 1).l, 5 * 20 - 4 * 10);
+    // Edge taps are clamped: the corner sees itself twice.
+    assert_eq!(surf.get_pixel(0, 0).l, 10);
+    assert_eq!(surf.get_pixel(1, 0).l, 0);
+}
+
+#[test]
+fn sharpening_leaves_chroma_and_alpha() {
+    use super::super::{ColorRGBA, ColorYUV};
+
+    let mut yuv: Surface<Yuv420p, u8, Vec<u8>> = Surface::new_black(10, 2);
+    {
+        let (y, u, v) = yuv.get_planes_mut();
+        for (i, l) in y.iter_mut().enumerate() {
+            *l = soft_step(i as u32 % 10);
+        }
+        for (i, c) in u.iter_mut().chain(v.iter_mut()).enumerate() {
+            *c = (i * 20) as u8;
+        }
+    }
+    let chroma = yuv.as_storage()[20..].to_vec();
+    unsharp_mask(&mut yuv, UnsharpMask::default());
+    assert_eq!(&yuv.as_storage()[20..], &chroma[..]);
+    assert!(yuv.as_storage()[3] < 60);
+
+    let storage: Vec<u8> = (0..10).flat_map(|x| vec![soft_step(x), 128, 1 + x as u8 * 2]).collect();
+    let mut packed: Surface<Yuv444, u8, Vec<u8>> = Surface::new(10, 1, storage);
+    laplacian_sharpen(&mut packed, 1.0);
+    let px: ColorYUV<u8> = packed.get_pixel(3, 0);
+    assert_eq!((px.y, px.u, px.v), (20, 128, 7));
+
+    let mut rgba: Surface<Rgba, u8, Vec<u8>> = Surface::new_black(10, 1);
+    rgba.map_in_place(|x, _, _| ColorRGBA::new_rgba(soft_step(x), soft_step(x), 0, x as u8));
+    laplacian_sharpen(&mut rgba, 1.0);
+    let px: ColorRGBA<u8> = rgba.get_pixel(6, 0);
+    assert_eq!((px.r, px.g, px.b, px.a), (220, 220, 0, 6));
+}
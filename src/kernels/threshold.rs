@@ -0,0 +1,217 @@
+//! Thresholding of `Luma` surfaces into binary masks.
+//!
+//! Masks hold `MASK_OFF` (0) and `MASK_ON` (255).  Adaptive thresholding
+//! compares each pixel with its neighbourhood, so like the 3x3 kernels it
+//! only writes pixels whose whole window lies inside the surface, leaving a
+//! border of `block_size / 2` pixels of `output` untouched.
+
+use std::ops::{Deref, DerefMut};
+
+use super::super::{Channel, Luma, Surface};
+use super::super::stats::{Histogram, histograms};
+
+pub const MASK_OFF: u8 = 0;
+pub const MASK_ON: u8 = 255;
+
+fn check_sizes<C, S, S2>(input: &Surface<Luma, C, S>, output: &Surface<Luma, u8, S2>)
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[u8]>,
+{
+    assert_eq!(input.width(), output.width());
+    assert_eq!(input.height(), output.height());
+}
+
+/// Sets pixels brighter than `level` and clears the rest.
+pub fn threshold<C, S, S2>(input: &Surface<Luma, C, S>, level: C, output: &mut Surface<Luma, u8, S2>)
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    check_sizes(input, output);
+    let level = level.to_f64().unwrap();
+    let len = input.width() as usize * input.height() as usize;
+    let src = &input.as_storage()[..len];
+    for (out, value) in output.as_storage_mut()[..len].iter_mut().zip(src) {
+        *out = if value.to_f64().unwrap() > level { MASK_ON } else { MASK_OFF };
+    }
+}
+
+/// The level separating the histogram of `input` into two classes with
+/// the largest between-class variance (Otsu's method).
+pub fn otsu_level<C, S>(input: &Surface<Luma, C, S>) -> C
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    let bins = Histogram::default_bins::<C>();
+    let hist = histograms(input, Some(bins)).remove(0);
+    let counts = hist.bins();
+    let total: u64 = counts.iter().sum();
+    let weighted_total: f64 = counts.iter().enumerate().map(|(i, &n)| i as f64 * n as f64).sum();
+
+    let (mut best, mut best_variance) = (0, -1.0);
+    let (mut below, mut weighted_below) = (0u64, 0.0);
+    for (i, &n) in counts.iter().enumerate() {
+        below += n;
+        weighted_below += i as f64 * n as f64;
+        let above = total - below;
+        if below == 0 || above == 0 {
+            continue;
+        }
+        let mean_below = weighted_below / below as f64;
+        let mean_above = (weighted_total - weighted_below) / above as f64;
+        let diff = mean_below - mean_above;
+        let variance = below as f64 * above as f64 * diff * diff;
+        if variance > best_variance {
+            best = i;
+            best_variance = variance;
+        }
+    }
+
+    // Values in bins up to `best` fall below the threshold.
+    if C::max_depth().is_some() {
+        C::from_f64(Histogram::bin_start::<C>(best + 1, bins) - 1.0)
+    } else {
+        C::from_f64(Histogram::bin_start::<C>(best + 1, bins))
+    }
+}
+
+/// Thresholds `input` at `otsu_level`, returning the level used.
+pub fn threshold_otsu<C, S, S2>(input: &Surface<Luma, C, S>, output: &mut Surface<Luma, u8, S2>) -> C
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    let level = otsu_level(input);
+    threshold(input, level, output);
+    level
+}
+
+/// How the local level of `threshold_adaptive` is computed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdaptiveMethod {
+    /// Mean of the block.
+    Mean,
+    /// Gaussian-weighted mean of the block, with the sigma OpenCV derives
+    /// from the block size.
+    Gaussian,
+}
+
+fn window_taps(method: AdaptiveMethod, size: usize) -> Vec<f64> {
+    let taps: Vec<f64> = match method {
+        AdaptiveMethod::Mean => vec![1.0; size],
+        AdaptiveMethod::Gaussian => {
+            let sigma = 0.3 * ((size as f64 - 1.0) * 0.5 - 1.0) + 0.8;
+            let centre = (size / 2) as f64;
+            (0..size)
+                .map(|i| {
+                    let d = i as f64 - centre;
+                    (-d * d / (2.0 * sigma * sigma)).exp()
+                })
+                .collect()
+        }
+    };
+    let sum: f64 = taps.iter().sum();
+    taps.into_iter().map(|t| t / sum).collect()
+}
+
+/// Sets each pixel brighter than its local level minus `offset`, where the
+/// local level is taken over the `block_size`x`block_size` square around
+/// it.  `block_size` must be odd and at least 3.
+pub fn threshold_adaptive<C, S, S2>(
+    input: &Surface<Luma, C, S>,
+    method: AdaptiveMethod,
+    block_size: u32,
+    offset: f64,
+    output: &mut Surface<Luma, u8, S2>,
+)
+    where
+        C: Channel,
+        S: Deref<Target=[C]>,
+        S2: Deref<Target=[u8]> + DerefMut,
+{
+    check_sizes(input, output);
+    assert!(block_size >= 3 && block_size % 2 == 1, "block size must be odd and at least 3");
+    let (width, height) = (input.width() as usize, input.height() as usize);
+    let size = block_size as usize;
+    if width < size || height < size {
+        return;
+    }
+    let radius = size / 2;
+    let taps = window_taps(method, size);
+    let src: Vec<f64> = input.as_storage()[..width * height].iter()
+        .map(|v| v.to_f64().unwrap())
+        .collect();
+
+    // Horizontal pass over every row, then vertical into the mask.
+    let inner_width = width - 2 * radius;
+    let mut rows = Vec::with_capacity(inner_width * height);
+    for row in src.chunks(width) {
+        for x in 0..inner_width {
+            rows.push(taps.iter().zip(&row[x..x + size]).map(|(t, v)| t * v).sum::<f64>());
+        }
+    }
+    let dst = output.as_storage_mut();
+    for y in radius..(height - radius) {
+        for x in 0..inner_width {
+            let level: f64 = taps.iter().enumerate()
+                .map(|(i, t)| t * rows[(y - radius + i) * inner_width + x])
+                .sum();
+            let at = y * width + x + radius;
+            dst[at] = if src[at] > level - offset { MASK_ON } else { MASK_OFF };
+        }
+    }
+}
+
+#[test]
+fn fixed_threshold() {
+    let input: Surface<Luma, u16, Vec<u16>> = Surface::new(4, 1, vec![0, 1000, 1001, 60000]);
+    let mut mask: Surface<Luma, u8, Vec<u8>> = Surface::new_black(4, 1);
+    threshold(&input, 1000, &mut mask);
+    assert_eq!(mask.as_storage(), &[0, 0, 255, 255][..]);
+}
+
+#[test]
+fn otsu_splits_bimodal_histogram() {
+    let mut storage = vec![40u8; 50];
+    storage.extend(vec![42u8; 50]);
+    storage.extend(vec![200u8; 60]);
+    storage.extend(vec![210u8; 40]);
+    let input: Surface<Luma, u8, Vec<u8>> = Surface::new(20, 10, storage);
+    let mut mask: Surface<Luma, u8, Vec<u8>> = Surface::new_black(20, 10);
+    let level = threshold_otsu(&input, &mut mask);
+    assert!((42..200).contains(&level), "{}", level);
+    assert_eq!(mask.as_storage().iter().filter(|&&v| v == MASK_ON).count(), 100);
+
+    let floats: Surface<Luma, f64, Vec<f64>> = Surface::new(4, 1, vec![0.1, 0.15, 0.8, 0.9]);
+    let level = otsu_level(&floats);
+    assert!((0.15..0.8).contains(&level), "{}", level);
+}
+
+#[test]
+fn adaptive_threshold_follows_gradient() {
+    // A horizontal ramp with a faint dot: a global threshold cannot find
+    // the dot, a local one can.
+    let (width, height) = (24u32, 9u32);
+    let mut storage: Vec<u8> = (0..width * height).map(|i| (i % width * 10) as u8).collect();
+    storage[(4 * width + 12) as usize] += 8;
+    let input: Surface<Luma, u8, Vec<u8>> = Surface::new(width, height, storage);
+
+    for &method in &[AdaptiveMethod::Mean, AdaptiveMethod::Gaussian] {
+        let mut mask: Surface<Luma, u8, Vec<u8>> = Surface::new(width, height, vec![7; (width * height) as usize]);
+        threshold_adaptive(&input, method, 5, -4.0, &mut mask);
+        for (x, y, px) in mask.enumerate_pixels() {
+            let inside = (2..width - 2).contains(&x) && (2..height - 2).contains(&y);
+            let expected = match (inside, x, y) {
+                (false, _, _) => 7,
+                (true, 12, 4) => MASK_ON,
+                _ => MASK_OFF,
+            };
+            assert_eq!(px.l, expected, "{:?} at ({}, {})", method, x, y);
+        }
+    }
+}
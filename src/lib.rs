@@ -2,6 +2,9 @@ extern crate num;
 
 //extern crate netpbm;
 
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
 #[cfg(test)]
 extern crate test;
 
@@ -11,14 +14,21 @@ pub const BOX_WIDTH: usize = 1 << 7;
 pub const BOX_HEIGHT_SHL: usize = 3;
 pub const BOX_HEIGHT: usize = 1 << 3;
 
+pub mod adjust;
 pub mod colorspace;
-pub use self::colorspace::{Channel, Pixel};
+pub use self::colorspace::{Channel, ChannelAccess, IntegerChannel, Pixel, TableChannel};
 pub use self::colorspace::{
     ColorL,
     ColorLA,
     ColorRGB,
     ColorRGBA,
     ColorYUV, 
+    ColorHSV,
+    ColorHSL,
+    ColorXYZ,
+    ColorLab,
+    ColorCMYK,
+    Separation,
 };
 
 // pub mod kernels;
@@ -27,12 +37,30 @@ pub mod kernels;
 mod unsafe_impl;
 mod unified;
 mod resize;
+mod pool;
+mod error;
+mod tone;
+pub mod dither;
+pub mod draw;
+pub mod equalize;
+pub mod geometry;
+pub mod lut;
+pub mod quantize;
+pub mod quality;
+pub mod stats;
+pub mod warp;
+#[cfg(feature = "parallel")]
+pub mod parallel;
 
 pub use self::unified::{
     surface_byte_size,
     Surface,
     Format,
+    InterleavedFormat,
+    PackedFormat,
     PlanarFormat,
+    LumaFormat,
+    ToneFormat,
     Yuv420p,
     Yuv422,
     Yuv422p,
@@ -41,13 +69,32 @@ pub use self::unified::{
     Rgba,
     RgbPlanar,
     RgbaPlanar,
+    Hsv,
+    Hsl,
+    Xyz,
+    Lab,
+    Cmyk,
+    CmykPlanar,
+    separate_cmyk,
+    write_cmyk_pam,
+    Indexed8,
     Luma,
+    StorageAlloc,
+    Pixels,
+    EnumeratePixels,
 };
 
+pub use self::pool::{FramePool, PooledBuffer};
+pub use self::error::Error;
+
 pub mod experimental {
     pub use super::unified::extract_luma;
+    #[cfg(feature = "parallel")]
+    pub use super::unified::extract_luma_par;
 
     pub mod resize {
         pub use super::super::resize::resize_nearest;
+        #[cfg(feature = "parallel")]
+        pub use super::super::resize::resize_nearest_par;
     }
 }
\ No newline at end of file
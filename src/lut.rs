@@ -0,0 +1,459 @@
+//! Lookup-table colour transforms.
+//!
+//! `Lut1d` holds one curve per channel and applies to the same tone
+//! channels as the `adjust` functions.  `Lut3d` maps RGB to RGB through a
+//! lattice and applies to the RGB formats, leaving alpha alone.  Both can
+//! be read from the `.cube` files written by Resolve and Adobe tools.
+//!
+//! Curves and lattices hold normalised values, so one table serves every
+//! channel type.
+
+use std::error;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use super::{Channel, ChannelAccess, Format, Surface, ToneFormat};
+use super::tone::{apply_luts, is_rgb};
+
+/// A problem reading a `.cube` file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CubeError {
+    /// The line, counting from 1, is not valid `.cube` syntax.
+    Syntax { line: usize },
+    /// The file does not declare the table size this kind of LUT needs.
+    MissingSize,
+    /// The file declares both `LUT_1D_SIZE` and `LUT_3D_SIZE`.
+    ConflictingSizes,
+    /// The declared lattice has more entries than can be addressed.
+    SizeTooLarge { size: usize },
+    /// The file holds a different number of table rows than it declares.
+    RowCount { expected: usize, actual: usize },
+}
+
+impl fmt::Display for CubeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CubeError::Syntax { line } =>
+                write!(f, "invalid .cube syntax on line {}", line),
+            CubeError::MissingSize =>
+                write!(f, ".cube file does not declare a size for this kind of LUT"),
+            CubeError::ConflictingSizes =>
+                write!(f, ".cube file declares both a 1D and a 3D size"),
+            CubeError::SizeTooLarge { size } =>
+                write!(f, ".cube lattice size {} is too large", size),
+            CubeError::RowCount { expected, actual } =>
+                write!(f, ".cube file has {} table rows, expected {}", actual, expected),
+        }
+    }
+}
+
+impl error::Error for CubeError {}
+
+/// The parts of a `.cube` file both kinds of LUT use.
+struct Cube {
+    size_1d: Option<usize>,
+    size_3d: Option<usize>,
+    domain_min: [f64; 3],
+    domain_max: [f64; 3],
+    rows: Vec<[f64; 3]>,
+}
+
+fn parse_triple<'a, I: Iterator<Item=&'a str>>(words: I) -> Option<[f64; 3]> {
+    let values: Vec<f64> = words.map(|w| w.parse().ok()).collect::<Option<_>>()?;
+    if values.len() == 3 {
+        Some([values[0], values[1], values[2]])
+    } else {
+        None
+    }
+}
+
+fn parse_cube(text: &str) -> Result<Cube, CubeError> {
+    let mut cube = Cube {
+        size_1d: None,
+        size_3d: None,
+        domain_min: [0.0; 3],
+        domain_max: [1.0; 3],
+        rows: Vec::new(),
+    };
+    for (index, line) in text.lines().enumerate() {
+        let syntax = CubeError::Syntax { line: index + 1 };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let keyword = words.next().unwrap();
+        match keyword {
+            "TITLE" => {}
+            "LUT_1D_SIZE" | "LUT_3D_SIZE" => {
+                let size = words.next().and_then(|w| w.parse::<usize>().ok()).ok_or(syntax.clone())?;
+                if size < 2 || words.next().is_some() {
+                    return Err(syntax);
+                }
+                if keyword == "LUT_1D_SIZE" {
+                    cube.size_1d = Some(size);
+                } else {
+                    cube.size_3d = Some(size);
+                }
+            }
+            "DOMAIN_MIN" => cube.domain_min = parse_triple(words).ok_or(syntax)?,
+            "DOMAIN_MAX" => cube.domain_max = parse_triple(words).ok_or(syntax)?,
+            "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                let range: Vec<f64> = words.map(|w| w.parse().ok()).collect::<Option<_>>().ok_or(syntax.clone())?;
+                if range.len() != 2 {
+                    return Err(syntax);
+                }
+                cube.domain_min = [range[0]; 3];
+                cube.domain_max = [range[1]; 3];
+            }
+            _ => {
+                let row = parse_triple(line.split_whitespace()).ok_or(syntax)?;
+                cube.rows.push(row);
+            }
+        }
+    }
+    Ok(cube)
+}
+
+fn check_rows(cube: &Cube, expected: usize) -> Result<(), CubeError> {
+    if cube.rows.len() != expected {
+        return Err(CubeError::RowCount { expected, actual: cube.rows.len() });
+    }
+    Ok(())
+}
+
+/// Maps `value` from `min..=max` onto `0.0..=1.0`, clamping.
+#[inline]
+fn to_unit(value: f64, min: f64, max: f64) -> f64 {
+    ((value - min) / (max - min)).clamp(0.0, 1.0)
+}
+
+/// One curve per channel, sampled evenly over the input range and
+/// interpolated linearly between samples.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut1d {
+    curves: Vec<Vec<f64>>,
+    domain_min: [f64; 3],
+    domain_max: [f64; 3],
+}
+
+impl Lut1d {
+    /// Curves of normalised output values.  Each needs at least two
+    /// samples; a single curve applies to every tone channel.
+    pub fn new(curves: Vec<Vec<f64>>) -> Lut1d {
+        assert!(!curves.is_empty(), "a LUT needs at least one curve");
+        assert!(curves.iter().all(|curve| curve.len() >= 2), "a curve needs at least two samples");
+        Lut1d { curves, domain_min: [0.0; 3], domain_max: [1.0; 3] }
+    }
+
+    /// Samples `f` over `0.0..=1.0` at `size` points for a curve shared by
+    /// every tone channel.
+    pub fn from_fn<F: Fn(f64) -> f64>(size: usize, f: F) -> Lut1d {
+        assert!(size >= 2, "a curve needs at least two samples");
+        let last = (size - 1) as f64;
+        Lut1d::new(vec![(0..size).map(|i| f(i as f64 / last)).collect()])
+    }
+
+    /// Reads a `.cube` file declaring `LUT_1D_SIZE`, giving three curves.
+    pub fn from_cube(text: &str) -> Result<Lut1d, CubeError> {
+        let cube = parse_cube(text)?;
+        if cube.size_3d.is_some() && cube.size_1d.is_some() {
+            return Err(CubeError::ConflictingSizes);
+        }
+        let size = cube.size_1d.ok_or(CubeError::MissingSize)?;
+        check_rows(&cube, size)?;
+        let curves = (0..3).map(|ch| cube.rows.iter().map(|row| row[ch]).collect()).collect();
+        Ok(Lut1d { curves, domain_min: cube.domain_min, domain_max: cube.domain_max })
+    }
+
+    pub fn curve_count(&self) -> usize {
+        self.curves.len()
+    }
+
+    /// The output of curve `index` for the normalised input `value`.
+    pub fn eval(&self, index: usize, value: f64) -> f64 {
+        let curve = &self.curves[index];
+        let domain = index.min(2);
+        let last = curve.len() - 1;
+        let pos = to_unit(value, self.domain_min[domain], self.domain_max[domain]) * last as f64;
+        let lo = (pos as usize).min(last - 1);
+        let frac = pos - lo as f64;
+        curve[lo] + (curve[lo + 1] - curve[lo]) * frac
+    }
+
+    /// The curve for tone channel `index`.
+    fn curve_for(&self, index: usize) -> usize {
+        if self.curves.len() == 1 { 0 } else { index }
+    }
+}
+
+/// Applies `lut` to the tone channels of `surface`: luma for `Luma` and
+/// YUV formats, colour for RGB formats.  The LUT needs a single curve or
+/// one per tone channel.  `u8` and `u16` channels go through a table with
+/// an entry per channel value.
+pub fn apply_lut_1d<M, C, S>(surface: &mut Surface<M, C, S>, lut: &Lut1d)
+    where
+        M: ToneFormat<C>,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    let channels = <M as ToneFormat<C>>::TONE_CHANNELS;
+    assert!(lut.curve_count() == 1 || lut.curve_count() == channels,
+        "one curve or one per tone channel is needed");
+    let max = C::max_value().to_f64().unwrap();
+
+    match C::max_depth() {
+        Some(depth) if depth <= 0xFFFF => {
+            let luts: Vec<Vec<C>> = (0..channels)
+                .map(|index| {
+                    let curve = lut.curve_for(index);
+                    (0..=depth).map(|value| C::from_f64(lut.eval(curve, value as f64 / max) * max)).collect()
+                })
+                .collect();
+            apply_luts(surface, &luts);
+        }
+        _ => {
+            surface.map_in_place(|_, _, px| {
+                px.map_channels(|index, value| if index < channels {
+                    C::from_f64(lut.eval(lut.curve_for(index), value.to_f64().unwrap() / max) * max)
+                } else {
+                    value
+                })
+            });
+        }
+    }
+}
+
+/// How `Lut3d` interpolates between lattice points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Blends the eight corners of the enclosing cell.
+    Trilinear,
+    /// Blends the four corners of the enclosing tetrahedron.  Cheaper than
+    /// trilinear and keeps neutral greys neutral.
+    Tetrahedral,
+}
+
+/// An RGB to RGB lattice of `size`³ normalised output colours.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut3d {
+    size: usize,
+    table: Vec<[f64; 3]>,
+    domain_min: [f64; 3],
+    domain_max: [f64; 3],
+}
+
+impl Lut3d {
+    /// A lattice in `.cube` order: red varies fastest, then green, then
+    /// blue.
+    pub fn new(size: usize, table: Vec<[f64; 3]>) -> Lut3d {
+        assert!(size >= 2, "a lattice needs at least two points per axis");
+        assert_eq!(Some(table.len()), size.checked_pow(3), "the table must hold size³ entries");
+        Lut3d { size, table, domain_min: [0.0; 3], domain_max: [1.0; 3] }
+    }
+
+    /// Samples `f` at every lattice point.
+    pub fn from_fn<F: Fn([f64; 3]) -> [f64; 3]>(size: usize, f: F) -> Lut3d {
+        assert!(size >= 2, "a lattice needs at least two points per axis");
+        let last = (size - 1) as f64;
+        let table = (0..size * size * size)
+            .map(|i| f([(i % size) as f64 / last, (i / size % size) as f64 / last, (i / size / size) as f64 / last]))
+            .collect();
+        Lut3d::new(size, table)
+    }
+
+    /// The LUT that leaves colours unchanged.
+    pub fn identity(size: usize) -> Lut3d {
+        Lut3d::from_fn(size, |rgb| rgb)
+    }
+
+    /// Reads a `.cube` file declaring `LUT_3D_SIZE`.
+    pub fn from_cube(text: &str) -> Result<Lut3d, CubeError> {
+        let cube = parse_cube(text)?;
+        if cube.size_1d.is_some() && cube.size_3d.is_some() {
+            return Err(CubeError::ConflictingSizes);
+        }
+        let size = cube.size_3d.ok_or(CubeError::MissingSize)?;
+        let entries = size.checked_pow(3).ok_or(CubeError::SizeTooLarge { size })?;
+        check_rows(&cube, entries)?;
+        Ok(Lut3d { size, table: cube.rows, domain_min: cube.domain_min, domain_max: cube.domain_max })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[inline]
+    fn at(&self, r: usize, g: usize, b: usize) -> [f64; 3] {
+        self.table[r + self.size * (g + self.size * b)]
+    }
+
+    /// The output for a normalised input colour.
+    pub fn lookup(&self, rgb: [f64; 3], interpolation: Interpolation) -> [f64; 3] {
+        let last = self.size - 1;
+        let mut base = [0; 3];
+        let mut frac = [0.0; 3];
+        for i in 0..3 {
+            let pos = to_unit(rgb[i], self.domain_min[i], self.domain_max[i]) * last as f64;
+            base[i] = (pos as usize).min(last - 1);
+            frac[i] = pos - base[i] as f64;
+        }
+        let [r, g, b] = base;
+        let [fr, fg, fb] = frac;
+        let corner = |dr: usize, dg: usize, db: usize| self.at(r + dr, g + dg, b + db);
+        let blend = |weights: &[(f64, [f64; 3])]| {
+            let mut out = [0.0; 3];
+            for &(weight, value) in weights {
+                for i in 0..3 {
+                    out[i] += weight * value[i];
+                }
+            }
+            out
+        };
+
+        match interpolation {
+            Interpolation::Trilinear => {
+                let mut weights = Vec::with_capacity(8);
+                for &(dr, dg, db) in &[(0, 0, 0), (1, 0, 0), (0, 1, 0), (1, 1, 0), (0, 0, 1), (1, 0, 1), (0, 1, 1), (1, 1, 1)] {
+                    let wr = if dr == 1 { fr } else { 1.0 - fr };
+                    let wg = if dg == 1 { fg } else { 1.0 - fg };
+                    let wb = if db == 1 { fb } else { 1.0 - fb };
+                    weights.push((wr * wg * wb, corner(dr, dg, db)));
+                }
+                blend(&weights)
+            }
+            Interpolation::Tetrahedral => {
+                let (c000, c111) = (corner(0, 0, 0), corner(1, 1, 1));
+                if fr > fg {
+                    if fg > fb {
+                        blend(&[(1.0 - fr, c000), (fr - fg, corner(1, 0, 0)), (fg - fb, corner(1, 1, 0)), (fb, c111)])
+                    } else if fr > fb {
+                        blend(&[(1.0 - fr, c000), (fr - fb, corner(1, 0, 0)), (fb - fg, corner(1, 0, 1)), (fg, c111)])
+                    } else {
+                        blend(&[(1.0 - fb, c000), (fb - fr, corner(0, 0, 1)), (fr - fg, corner(1, 0, 1)), (fg, c111)])
+                    }
+                } else if fb > fg {
+                    blend(&[(1.0 - fb, c000), (fb - fg, corner(0, 0, 1)), (fg - fr, corner(0, 1, 1)), (fr, c111)])
+                } else if fb > fr {
+                    blend(&[(1.0 - fg, c000), (fg - fb, corner(0, 1, 0)), (fb - fr, corner(0, 1, 1)), (fr, c111)])
+                } else {
+                    blend(&[(1.0 - fg, c000), (fg - fr, corner(0, 1, 0)), (fr - fb, corner(1, 1, 0)), (fb, c111)])
+                }
+            }
+        }
+    }
+}
+
+/// Applies `lut` to the colour channels of an `Rgb`, `Rgba`, `RgbPlanar`
+/// or `RgbaPlanar` surface.
+pub fn apply_lut_3d<M, C, S>(surface: &mut Surface<M, C, S>, lut: &Lut3d, interpolation: Interpolation)
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    assert!(is_rgb::<M>(), "3D LUTs apply to RGB formats");
+
+    let max = C::max_value().to_f64().unwrap();
+    surface.map_in_place(|_, _, px| {
+        let norm = |index: usize| px.channel(index).to_f64().unwrap() / max;
+        let out = lut.lookup([norm(0), norm(1), norm(2)], interpolation);
+        px.map_channels(|index, value| if index < 3 { C::from_f64(out[index] * max) } else { value })
+    });
+}
+
+#[cfg(test)]
+const GRADE_CUBE: &str = "\
+# A warming grade
+TITLE \"warm\"
+LUT_3D_SIZE 2
+DOMAIN_MIN 0.0 0.0 0.0
+DOMAIN_MAX 1.0 1.0 1.0
+
+0.1 0.0 0.0
+1.0 0.0 0.0
+0.0 1.0 0.0
+1.0 1.0 0.0
+0.0 0.0 0.8
+1.0 0.0 0.8
+0.0 1.0 0.8
+1.0 1.0 0.8
+";
+
+#[test]
+fn cube_parsing() {
+    let lut = Lut3d::from_cube(GRADE_CUBE).unwrap();
+    assert_eq!(lut.size(), 2);
+    assert_eq!(lut.lookup([0.0, 0.0, 0.0], Interpolation::Trilinear), [0.1, 0.0, 0.0]);
+    assert_eq!(lut.lookup([1.0, 1.0, 1.0], Interpolation::Tetrahedral), [1.0, 1.0, 0.8]);
+
+    let shaper = Lut1d::from_cube("LUT_1D_SIZE 3\nLUT_1D_INPUT_RANGE 0 2\n0 0 1\n0.5 0.25 0.5\n1 1 0\n").unwrap();
+    assert_eq!(shaper.curve_count(), 3);
+    assert_eq!(shaper.eval(0, 0.5), 0.25);
+    assert_eq!(shaper.eval(1, 1.0), 0.25);
+    assert_eq!(shaper.eval(2, 2.0), 0.0);
+
+    assert_eq!(Lut3d::from_cube("LUT_3D_SIZE 2\n0 0 0\n"), Err(CubeError::RowCount { expected: 8, actual: 1 }));
+    assert_eq!(Lut3d::from_cube("LUT_3D_SIZE 2\n0 0 zero\n"), Err(CubeError::Syntax { line: 2 }));
+    assert_eq!(Lut3d::from_cube("LUT_3D_SIZE 1\n"), Err(CubeError::Syntax { line: 1 }));
+    assert_eq!(Lut3d::from_cube("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n"), Err(CubeError::MissingSize));
+    assert_eq!(Lut1d::from_cube(GRADE_CUBE), Err(CubeError::MissingSize));
+    assert_eq!(Lut3d::from_cube("LUT_3D_SIZE 3000000\n0 0 0\n"), Err(CubeError::SizeTooLarge { size: 3000000 }));
+    let both = "LUT_1D_SIZE 2\nLUT_3D_SIZE 2\n0 0 0\n1 1 1\n";
+    assert_eq!(Lut1d::from_cube(both), Err(CubeError::ConflictingSizes));
+    assert_eq!(Lut3d::from_cube(both), Err(CubeError::ConflictingSizes));
+}
+
+#[test]
+fn interpolation_between_lattice_points() {
+    // Both schemes reproduce an affine transform exactly.
+    let affine = |rgb: [f64; 3]| [0.5 * rgb[0] + 0.25 * rgb[2], rgb[1] * 0.8 + 0.1, 1.0 - rgb[2]];
+    let lut = Lut3d::from_fn(5, affine);
+    for &rgb in &[[0.3, 0.7, 0.1], [0.05, 0.95, 0.5], [1.0, 0.0, 0.33]] {
+        for &interp in &[Interpolation::Trilinear, Interpolation::Tetrahedral] {
+            let out = lut.lookup(rgb, interp);
+            let expected = affine(rgb);
+            assert!((0..3).all(|i| (out[i] - expected[i]).abs() < 1e-12));
+        }
+    }
+
+    // On a non-linear lattice they differ inside a cell but agree on greys.
+    let product = Lut3d::from_fn(2, |rgb| [rgb[0] * rgb[1] * rgb[2]; 3]);
+    let tri = product.lookup([0.5, 0.5, 0.5], Interpolation::Trilinear);
+    let tet = product.lookup([0.5, 0.5, 0.5], Interpolation::Tetrahedral);
+    assert_eq!((tri[0], tet[0]), (0.125, 0.5));
+    let tri = product.lookup([0.8, 0.2, 0.5], Interpolation::Trilinear);
+    let tet = product.lookup([0.8, 0.2, 0.5], Interpolation::Tetrahedral);
+    assert!((tri[0] - 0.08).abs() < 1e-12 && (tet[0] - 0.2).abs() < 1e-12);
+}
+
+#[test]
+fn apply_luts_to_surfaces() {
+    use super::{ColorRGBA, Luma, Rgb, RgbPlanar, Rgba};
+
+    let mut rgba: Surface<Rgba, u8, Vec<u8>> = Surface::new(2, 1, vec![0, 0, 0, 9, 255, 255, 255, 200]);
+    apply_lut_3d(&mut rgba, &Lut3d::from_cube(GRADE_CUBE).unwrap(), Interpolation::Tetrahedral);
+    let px: ColorRGBA<u8> = rgba.get_pixel(0, 0);
+    assert_eq!((px.r, px.g, px.b, px.a), (26, 0, 0, 9));
+    assert_eq!(&rgba.as_storage()[4..], &[255, 255, 204, 200]);
+
+    let mut planar: Surface<RgbPlanar, f64, Vec<f64>> = Surface::new(1, 1, vec![0.25, 0.5, 0.75]);
+    apply_lut_3d(&mut planar, &Lut3d::identity(17), Interpolation::Trilinear);
+    assert!(planar.as_storage().iter().zip(&[0.25, 0.5, 0.75]).all(|(a, b)| (a - b).abs() < 1e-12));
+
+    let invert = Lut1d::from_fn(2, |v| 1.0 - v);
+    let mut rgba: Surface<Rgba, u16, Vec<u16>> = Surface::new(1, 1, vec![0, 1000, 65535, 7]);
+    apply_lut_1d(&mut rgba, &invert);
+    assert_eq!(&rgba.as_storage()[..], &[65535, 64535, 0, 7]);
+
+    let per_channel = Lut1d::new(vec![vec![0.0, 1.0], vec![0.0, 0.5], vec![1.0, 1.0]]);
+    let mut rgb: Surface<Rgb, u8, Vec<u8>> = Surface::new(1, 1, vec![100, 100, 100]);
+    apply_lut_1d(&mut rgb, &per_channel);
+    assert_eq!(&rgb.as_storage()[..], &[100, 50, 255]);
+
+    let mut luma: Surface<Luma, u32, Vec<u32>> = Surface::new(2, 1, vec![0, u32::MAX]);
+    apply_lut_1d(&mut luma, &invert);
+    assert_eq!(&luma.as_storage()[..], &[u32::MAX, 0]);
+}
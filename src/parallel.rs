@@ -0,0 +1,236 @@
+//! Parallel execution across disjoint regions of a surface.
+//!
+//! Work runs on the current rayon thread pool; wrap calls in
+//! `ThreadPool::install` to use a dedicated pool.
+
+use std::cmp::{min, max};
+use std::ops::{Deref, DerefMut};
+use std::slice;
+
+use rayon::prelude::*;
+
+use super::{BOX_WIDTH, BOX_HEIGHT};
+use super::{Channel, Format, PackedFormat, Surface};
+
+/// How a surface is split up for parallel processing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Partition {
+    /// Full-width bands of the given number of rows.
+    Bands(u32),
+    /// `BOX_WIDTH`x`BOX_HEIGHT` tiles, clipped at the right and bottom
+    /// edges.
+    Tiles,
+}
+
+impl Default for Partition {
+    fn default() -> Partition {
+        Partition::Bands(BOX_HEIGHT as u32)
+    }
+}
+
+/// A rectangular, exclusively borrowed part of a packed surface.
+pub struct RegionMut<'a, C: 'a> {
+    x: u32,
+    y: u32,
+    width: u32,
+    rows: Vec<&'a mut [C]>,
+}
+
+impl<'a, C> RegionMut<'a, C> {
+    /// Position of the top-left pixel within the surface.
+    pub fn origin(&self) -> (u32, u32) {
+        (self.x, self.y)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.rows.len() as u32
+    }
+
+    /// The channels of row `y`, counted from the top of the region.
+    pub fn row_mut(&mut self, y: u32) -> &mut [C] {
+        self.rows[y as usize]
+    }
+
+    pub fn rows_mut(&mut self) -> slice::IterMut<'_, &'a mut [C]> {
+        self.rows.iter_mut()
+    }
+}
+
+fn split_regions<C>(storage: &mut [C], width: u32, height: u32, channels: usize, partition: Partition)
+    -> Vec<RegionMut<'_, C>>
+{
+    let (band_height, tile_width) = match partition {
+        Partition::Bands(rows) => (rows, width),
+        Partition::Tiles => (BOX_HEIGHT as u32, BOX_WIDTH as u32),
+    };
+    let (band_height, tile_width) = (max(band_height, 1), max(tile_width, 1));
+    let row_len = channels * width as usize;
+    let mut regions = Vec::new();
+    if row_len == 0 {
+        return regions;
+    }
+
+    let mut rows = storage[..row_len * height as usize].chunks_mut(row_len);
+    for y in (0..height).step_by(band_height as usize) {
+        let band: Vec<&mut [C]> = rows.by_ref().take(band_height as usize).collect();
+        let first = regions.len();
+        let mut x = 0;
+        while x < width {
+            regions.push(RegionMut {
+                x,
+                y,
+                width: min(tile_width, width - x),
+                rows: Vec::with_capacity(band.len()),
+            });
+            x += tile_width;
+        }
+        for row in band {
+            let segments = row.chunks_mut(channels * tile_width as usize);
+            for (region, segment) in regions[first..].iter_mut().zip(segments) {
+                region.rows.push(segment);
+            }
+        }
+    }
+    regions
+}
+
+/// Runs `f` on every region of `surface`, in parallel.
+pub fn for_each_region_mut<M, C, S, F>(surface: &mut Surface<M, C, S>, partition: Partition, f: F)
+    where
+        M: PackedFormat<C>,
+        C: Channel + Send,
+        S: Deref<Target=[C]> + DerefMut,
+        F: Fn(&mut RegionMut<C>) + Sync,
+{
+    let (width, height) = (surface.width(), surface.height());
+    let channels = <M as Format<C>>::channel_data_size(1, 1);
+    let storage = surface.as_storage_mut();
+    split_regions(storage, width, height, channels, partition)
+        .into_par_iter()
+        .for_each(|mut region| f(&mut region));
+}
+
+/// Fills `output` in parallel, one region at a time, with `f(x, y)`.
+pub fn fill_with<M, C, S, F>(output: &mut Surface<M, C, S>, partition: Partition, f: F)
+    where
+        M: PackedFormat<C>,
+        C: Channel + Send,
+        S: Deref<Target=[C]> + DerefMut,
+        F: Fn(u32, u32) -> M::Pixel + Sync,
+{
+    for_each_region_mut(output, partition, |region| {
+        let (x0, y0) = region.origin();
+        for (dy, row) in region.rows_mut().enumerate() {
+            let pixels = <M as PackedFormat<C>>::as_pixels_mut(row);
+            for (dx, px) in pixels.iter_mut().enumerate() {
+                *px = f(x0 + dx as u32, y0 + dy as u32);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+pub fn test_image(width: u32, height: u32, seed: u32) -> Surface<super::Rgb, u8, Box<[u8]>> {
+    let mut state = seed.wrapping_mul(2654435761).wrapping_add(1);
+    let storage: Vec<u8> = (0..3 * width as usize * height as usize)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state >> 24) as u8
+        })
+        .collect();
+    Surface::new(width, height, storage.into_boxed_slice())
+}
+
+#[test]
+fn regions_cover_surface_once() {
+    use super::{Luma, ColorL};
+
+    for &partition in &[Partition::Bands(3), Partition::Bands(1000), Partition::Tiles] {
+        let (width, height) = (BOX_WIDTH as u32 * 2 + 5, BOX_HEIGHT as u32 * 3 + 1);
+        let mut surf: Surface<Luma, u32, Vec<u32>> = Surface::new_black(width, height);
+        for_each_region_mut(&mut surf, partition, |region| {
+            for row in region.rows_mut() {
+                for px in row.iter_mut() {
+                    *px += 1;
+                }
+            }
+        });
+        assert!(surf.as_storage().iter().all(|&v| v == 1), "{:?}", partition);
+
+        fill_with(&mut surf, partition, |x, y| ColorL::new_l(x + 1000 * y));
+        for (x, y, px) in surf.enumerate_pixels() {
+            assert_eq!(px.l, x + 1000 * y);
+        }
+    }
+}
+
+#[cfg(test)]
+const PARTITIONS: [Partition; 3] = [Partition::Bands(1), Partition::Bands(5), Partition::Tiles];
+
+#[test]
+fn extract_luma_par_matches_serial() {
+    use super::experimental::{extract_luma, extract_luma_par};
+
+    let rgb = test_image(300, 37, 1);
+    let serial = extract_luma(&rgb);
+    for &partition in &PARTITIONS {
+        let parallel = extract_luma_par(&rgb, partition);
+        assert_eq!(serial.as_storage(), parallel.as_storage());
+    }
+}
+
+#[test]
+fn kernel_3x3_par_matches_serial() {
+    use super::Luma;
+    use super::experimental::extract_luma;
+
+    fn spread(px: &[u8; 9]) -> u8 {
+        let max = px.iter().max().unwrap();
+        let min = px.iter().min().unwrap();
+        max - min
+    }
+
+    let luma = extract_luma(&test_image(259, 21, 2));
+    let mut serial: Surface<Luma, u8, Box<[u8]>> = Surface::new_black(259, 21);
+    luma.run_luma8_kernel_3x3(spread, &mut serial);
+    for &partition in &PARTITIONS {
+        let mut parallel: Surface<Luma, u8, Box<[u8]>> = Surface::new_black(259, 21);
+        luma.run_luma8_kernel_3x3_par(spread, &mut parallel, partition);
+        assert_eq!(serial.as_storage(), parallel.as_storage());
+    }
+}
+
+#[test]
+fn resize_nearest_par_matches_serial() {
+    use super::Rgb;
+    use super::experimental::resize::{resize_nearest, resize_nearest_par};
+
+    let rgb = test_image(400, 90, 3);
+    let mut serial: Surface<Rgb, u8, Box<[u8]>> = Surface::new_black(133, 31);
+    resize_nearest(&rgb, &mut serial);
+    for &partition in &PARTITIONS {
+        let mut parallel: Surface<Rgb, u8, Box<[u8]>> = Surface::new_black(133, 31);
+        resize_nearest_par(&rgb, &mut parallel, partition);
+        assert_eq!(serial.as_storage(), parallel.as_storage());
+    }
+}
+
+#[test]
+fn yuv444_to_rgb888_par_matches_serial() {
+    use super::Yuv444;
+    use super::colorspace::conversion::{yuv444_to_rgb888, yuv444_to_rgb888_par};
+
+    let rgb = test_image(150, 45, 4);
+    let yuv: Surface<Yuv444, u8, Box<[u8]>> = Surface::new(150, 45, rgb.into_storage());
+    let serial = yuv444_to_rgb888(&yuv);
+    for &partition in &PARTITIONS {
+        let parallel = yuv444_to_rgb888_par(&yuv, partition);
+        assert_eq!(serial.as_storage(), parallel.as_storage());
+    }
+}
@@ -0,0 +1,297 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+use super::{Channel, Format, Surface};
+use super::error::Error;
+use super::unified::{StorageAlloc, storage_len};
+
+/// Number of idle buffers kept per (format, width, height) unless
+/// configured otherwise.
+const DEFAULT_RETAIN: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct FrameKey {
+    format: TypeId,
+    width: u32,
+    height: u32,
+}
+
+struct PoolInner<C> {
+    retain: usize,
+    free: HashMap<FrameKey, Vec<Box<[C]>>>,
+}
+
+/// A pool of frame buffers, keyed by format and dimensions.
+///
+/// Buffers handed out by the pool are returned to it when dropped, so
+/// steady-state processing of same-sized frames does not allocate.
+/// Cloning a `FramePool` yields another handle to the same pool.  A panic
+/// while the pool's lock is held does not disable the pool: its idle
+/// buffers are only ever added or removed whole.
+pub struct FramePool<C> {
+    inner: Arc<Mutex<PoolInner<C>>>,
+}
+
+impl<C> Clone for FramePool<C> {
+    fn clone(&self) -> FramePool<C> {
+        FramePool { inner: self.inner.clone() }
+    }
+}
+
+impl<C> Default for FramePool<C> where C: Channel {
+    fn default() -> FramePool<C> {
+        FramePool::new()
+    }
+}
+
+impl<C> FramePool<C> where C: Channel {
+    pub fn new() -> FramePool<C> {
+        FramePool::with_retain(DEFAULT_RETAIN)
+    }
+
+    /// Creates a pool that keeps at most `retain` idle buffers for each
+    /// format and dimension pair; extra buffers are freed on return.
+    pub fn with_retain(retain: usize) -> FramePool<C> {
+        FramePool {
+            inner: Arc::new(Mutex::new(PoolInner {
+                retain,
+                free: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Takes a buffer sized for an `M` frame of `width`x`height`.
+    ///
+    /// The contents are unspecified: a recycled buffer still holds the
+    /// previous frame's data.
+    pub fn alloc<M>(&self, width: u32, height: u32) -> PooledBuffer<C>
+        where
+            M: Format<C> + 'static,
+    {
+        let key = FrameKey {
+            format: TypeId::of::<M>(),
+            width,
+            height,
+        };
+
+        let recycled = {
+            let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+            inner.free.get_mut(&key).and_then(|bufs| bufs.pop())
+        };
+
+        let storage = match recycled {
+            Some(storage) => storage,
+            None => {
+                StorageAlloc::alloc(storage_len::<M, C>(width, height))
+            }
+        };
+
+        PooledBuffer {
+            storage: Some(storage),
+            origin: Some((Arc::downgrade(&self.inner), key)),
+        }
+    }
+
+    /// Takes a buffer from the pool and initialises it to black.  Panics
+    /// where `Surface::new_black` does.
+    pub fn new_black<M>(&self, width: u32, height: u32) -> Surface<M, C, PooledBuffer<C>>
+        where
+            M: Format<C> + 'static,
+    {
+        let mut storage = self.alloc::<M>(width, height);
+        <M as Format<C>>::init_black(width, height, &mut storage);
+        Surface::new(width, height, storage)
+    }
+
+    /// Like `new_black`, but rejects dimensions the format cannot
+    /// represent.
+    pub fn try_new_black<M>(&self, width: u32, height: u32) -> Result<Surface<M, C, PooledBuffer<C>>, Error>
+        where
+            M: Format<C> + 'static,
+    {
+        <M as Format<C>>::try_channel_data_size(width, height)?;
+        Ok(self.new_black(width, height))
+    }
+
+    /// Copies `surface` into a buffer taken from the pool.  Panics if
+    /// `surface` holds fewer channels than its format needs.
+    pub fn copy_of<M, S>(&self, surface: &Surface<M, C, S>) -> Surface<M, C, PooledBuffer<C>>
+        where
+            M: Format<C> + 'static,
+            S: Deref<Target=[C]>,
+    {
+        self.try_copy_of(surface).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Copies `surface` into a buffer taken from the pool.  Channels past
+    /// the end of the image are not copied; too few channels, or
+    /// dimensions the format cannot represent, are an error.
+    pub fn try_copy_of<M, S>(&self, surface: &Surface<M, C, S>) -> Result<Surface<M, C, PooledBuffer<C>>, Error>
+        where
+            M: Format<C> + 'static,
+            S: Deref<Target=[C]>,
+    {
+        let (width, height) = (surface.width(), surface.height());
+        let length = <M as Format<C>>::try_channel_data_size(width, height)?;
+        let source = surface.as_storage();
+        if source.len() < length {
+            return Err(Error::SizeMismatch { expected: length, actual: source.len() });
+        }
+        let mut storage = self.alloc::<M>(width, height);
+        storage.copy_from_slice(&source[..length]);
+        Ok(Surface::new(width, height, storage))
+    }
+
+    /// The number of idle buffers currently held by the pool.
+    pub fn idle_count(&self) -> usize {
+        let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.free.values().map(|bufs| bufs.len()).sum()
+    }
+
+    /// Frees all idle buffers.  Buffers currently in use are unaffected
+    /// and will still be returned to the pool when dropped.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner).free.clear();
+    }
+}
+
+/// Frame storage borrowed from a `FramePool`.
+///
+/// Dereferences to the underlying channel slice and goes back to its pool
+/// on drop.  Buffers created through `StorageAlloc` belong to no pool and
+/// are simply freed.
+pub struct PooledBuffer<C> {
+    storage: Option<Box<[C]>>,
+    origin: Option<(Weak<Mutex<PoolInner<C>>>, FrameKey)>,
+}
+
+impl<C> PooledBuffer<C> {
+    /// Removes the buffer from pool management, returning the storage.
+    pub fn detach(mut self) -> Box<[C]> {
+        self.origin = None;
+        self.storage.take().unwrap()
+    }
+}
+
+impl<C> Deref for PooledBuffer<C> {
+    type Target = [C];
+
+    fn deref(&self) -> &[C] {
+        self.storage.as_ref().unwrap()
+    }
+}
+
+impl<C> DerefMut for PooledBuffer<C> {
+    fn deref_mut(&mut self) -> &mut [C] {
+        self.storage.as_mut().unwrap()
+    }
+}
+
+impl<C> StorageAlloc for PooledBuffer<C> where C: Channel {
+    fn alloc(length: usize) -> Self {
+        PooledBuffer {
+            storage: Some(StorageAlloc::alloc(length)),
+            origin: None,
+        }
+    }
+}
+
+impl<C> Drop for PooledBuffer<C> {
+    fn drop(&mut self) {
+        let (storage, (pool, key)) = match (self.storage.take(), self.origin.take()) {
+            (Some(storage), Some(origin)) => (storage, origin),
+            _ => return,
+        };
+        let pool = match pool.upgrade() {
+            Some(pool) => pool,
+            None => return,
+        };
+        let mut inner = pool.lock().unwrap_or_else(PoisonError::into_inner);
+        let retain = inner.retain;
+        let bufs = inner.free.entry(key).or_default();
+        if bufs.len() < retain {
+            bufs.push(storage);
+        }
+    }
+}
+
+#[test]
+fn pool_recycles_storage() {
+    use super::{Luma, Rgb};
+
+    let pool: FramePool<u8> = FramePool::new();
+    let ptr = {
+        let surf: Surface<Rgb, u8, _> = pool.new_black(16, 8);
+        surf.as_storage().as_ptr()
+    };
+    assert_eq!(pool.idle_count(), 1);
+
+    let surf: Surface<Rgb, u8, _> = pool.new_black(16, 8);
+    assert_eq!(surf.as_storage().as_ptr(), ptr);
+    assert_eq!(pool.idle_count(), 0);
+    drop(surf);
+
+    // A different format with the same buffer length gets its own bucket.
+    let luma: Surface<Luma, u8, _> = pool.new_black(48, 8);
+    assert!(luma.as_storage().as_ptr() != ptr);
+    assert_eq!(pool.idle_count(), 1);
+}
+
+#[test]
+fn pool_checks_sizes() {
+    use super::{Luma, Rgb};
+
+    let pool: FramePool<u8> = FramePool::new();
+    assert_eq!(pool.try_new_black::<Luma>(0, 4).err(), Some(Error::ZeroDimensions { width: 0, height: 4 }));
+    assert!(pool.try_new_black::<Rgb>(2, 2).is_ok());
+
+    let short: Surface<Rgb, u8, Vec<u8>> = Surface::new(2, 1, vec![1, 2, 3]);
+    assert_eq!(pool.try_copy_of(&short).err(), Some(Error::SizeMismatch { expected: 6, actual: 3 }));
+    let long: Surface<Rgb, u8, Vec<u8>> = Surface::new(1, 1, vec![1, 2, 3, 4]);
+    assert_eq!(&pool.copy_of(&long).as_storage()[..], &[1, 2, 3][..]);
+}
+
+#[test]
+fn pool_respects_retain_limit() {
+    use super::Luma;
+
+    let pool: FramePool<u8> = FramePool::with_retain(2);
+    let bufs: Vec<_> = (0..5).map(|_| pool.alloc::<Luma>(4, 4)).collect();
+    drop(bufs);
+    assert_eq!(pool.idle_count(), 2);
+
+    pool.clear();
+    assert_eq!(pool.idle_count(), 0);
+}
+
+#[test]
+fn pool_buffer_outlives_pool() {
+    use super::Luma;
+
+    let pool: FramePool<u8> = FramePool::new();
+    let mut surf: Surface<Luma, u8, _> = pool.new_black(4, 4);
+    drop(pool);
+    surf.put_pixel(1, 1, super::ColorL::new_l(7));
+    assert_eq!(surf.get_pixel(1, 1).l, 7);
+}
+
+#[test]
+fn pool_survives_poisoning() {
+    use super::Luma;
+    use std::thread;
+
+    let pool: FramePool<u8> = FramePool::new();
+    let inner = pool.inner.clone();
+    let _ = thread::spawn(move || {
+        let _guard = inner.lock().unwrap();
+        panic!("poison the pool");
+    }).join();
+    assert!(pool.inner.is_poisoned());
+
+    drop(pool.alloc::<Luma>(4, 4));
+    assert_eq!(pool.idle_count(), 1);
+    pool.clear();
+    assert_eq!(pool.idle_count(), 0);
+}
@@ -0,0 +1,385 @@
+//! Full-reference quality metrics between two surfaces of the same format
+//! and size.
+//!
+//! Results are reported per channel in the field order of the format's
+//! pixel.  YUV formats with subsampled chroma are measured plane by plane
+//! at each plane's own resolution, so U and V of `Yuv420p` are compared at
+//! quarter size and those of `Yuv422` and `Yuv422p` at half width;
+//! `yuv_weighted` combines the three into one score.
+
+use std::any::TypeId;
+use std::borrow::Cow;
+use std::ops::Deref;
+
+use super::{Channel, ChannelAccess, Format, PlanarFormat, Surface, Luma, Yuv420p, Yuv422, Yuv422p};
+
+struct Plane<'a, C: 'a + Clone> {
+    data: Cow<'a, [C]>,
+    width: usize,
+    height: usize,
+}
+
+/// Y, U and V planes of a `width`x`height` surface whose chroma is
+/// subsampled horizontally and is `chroma_height` rows high.
+fn yuv_planes<'a, C: Channel>(
+    y: Cow<'a, [C]>,
+    u: Cow<'a, [C]>,
+    v: Cow<'a, [C]>,
+    (width, height): (usize, usize),
+    chroma_height: usize,
+) -> Vec<Plane<'a, C>> {
+    let chroma_width = width.div_ceil(2);
+    vec![
+        Plane { data: y, width, height },
+        Plane { data: u, width: chroma_width, height: chroma_height },
+        Plane { data: v, width: chroma_width, height: chroma_height },
+    ]
+}
+
+/// The channels of `surface` as separate planes.
+fn channel_planes<M, C, S>(surface: &Surface<M, C, S>) -> Vec<Plane<'_, C>>
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    let size = (surface.width(), surface.height());
+    let (width, height) = (size.0 as usize, size.1 as usize);
+    let storage = &surface.as_storage()[..];
+    let format = TypeId::of::<M>();
+    if format == TypeId::of::<Luma>() {
+        return vec![Plane { data: Cow::Borrowed(&storage[..width * height]), width, height }];
+    }
+    if format == TypeId::of::<Yuv420p>() {
+        let (y, u, v) = <Yuv420p as PlanarFormat<C>>::get_planes(storage, size);
+        return yuv_planes(y.into(), u.into(), v.into(), (width, height), height.div_ceil(2));
+    }
+    if format == TypeId::of::<Yuv422p>() {
+        let (y, u, v) = <Yuv422p as PlanarFormat<C>>::get_planes(storage, size);
+        return yuv_planes(y.into(), u.into(), v.into(), (width, height), height);
+    }
+    if format == TypeId::of::<Yuv422>() {
+        // Y0 U Y1 V groups; chroma is taken once per group.
+        let chroma_width = width.div_ceil(2);
+        let mut y = Vec::with_capacity(width * height);
+        let (mut u, mut v) = (Vec::with_capacity(chroma_width * height), Vec::with_capacity(chroma_width * height));
+        if chroma_width > 0 {
+            for row in storage[..4 * chroma_width * height].chunks(4 * chroma_width) {
+                for (index, group) in row.chunks(4).enumerate() {
+                    y.push(group[0]);
+                    if 2 * index + 1 < width {
+                        y.push(group[2]);
+                    }
+                    u.push(group[1]);
+                    v.push(group[3]);
+                }
+            }
+        }
+        return yuv_planes(y.into(), u.into(), v.into(), (width, height), height);
+    }
+
+    let channels = <M::Pixel as ChannelAccess>::channel_count();
+    let mut planes: Vec<Vec<C>> = vec![Vec::with_capacity(width * height); channels];
+    for px in surface.iter_pixels() {
+        for (index, plane) in planes.iter_mut().enumerate() {
+            plane.push(px.channel(index));
+        }
+    }
+    planes.into_iter()
+        .map(|data| Plane { data: Cow::Owned(data), width, height })
+        .collect()
+}
+
+fn paired_planes<'a, M, C, SA, SB>(a: &'a Surface<M, C, SA>, b: &'a Surface<M, C, SB>)
+    -> Vec<(Plane<'a, C>, Plane<'a, C>)>
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        SA: Deref<Target=[C]>,
+        SB: Deref<Target=[C]>,
+{
+    assert_eq!((a.width(), a.height()), (b.width(), b.height()), "surfaces differ in size");
+    channel_planes(a).into_iter().zip(channel_planes(b)).collect()
+}
+
+fn peak<C: Channel>() -> f64 {
+    C::max_value().to_f64().unwrap() - C::min_value().to_f64().unwrap()
+}
+
+fn plane_mse<C: Channel>(a: &[C], b: &[C]) -> f64 {
+    if a.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = a.iter().zip(b.iter())
+        .map(|(&x, &y)| {
+            let d = x.to_f64().unwrap() - y.to_f64().unwrap();
+            d * d
+        })
+        .sum();
+    sum / a.len() as f64
+}
+
+fn psnr_of<C: Channel>(mse: f64) -> f64 {
+    if mse == 0.0 {
+        return f64::INFINITY;
+    }
+    let peak = peak::<C>();
+    10.0 * (peak * peak / mse).log10()
+}
+
+/// Mean squared error of each channel, in channel units.
+pub fn mse<M, C, SA, SB>(a: &Surface<M, C, SA>, b: &Surface<M, C, SB>) -> Vec<f64>
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        SA: Deref<Target=[C]>,
+        SB: Deref<Target=[C]>,
+{
+    paired_planes(a, b).iter()
+        .map(|(pa, pb)| plane_mse(&pa.data, &pb.data))
+        .collect()
+}
+
+/// Peak signal-to-noise ratio of each channel in decibels, relative to the
+/// channel's full range.  Identical channels give infinity.
+pub fn psnr<M, C, SA, SB>(a: &Surface<M, C, SA>, b: &Surface<M, C, SB>) -> Vec<f64>
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        SA: Deref<Target=[C]>,
+        SB: Deref<Target=[C]>,
+{
+    mse(a, b).into_iter().map(psnr_of::<C>).collect()
+}
+
+/// Combines per-plane Y, U and V scores with the customary 6:1:1 weights.
+/// For PSNR, combine MSEs with this and convert afterwards if a weighted
+/// PSNR in the usual sense is wanted.
+pub fn yuv_weighted(scores: &[f64]) -> f64 {
+    assert_eq!(scores.len(), 3, "expected Y, U and V scores");
+    (6.0 * scores[0] + scores[1] + scores[2]) / 8.0
+}
+
+const SSIM_WINDOW: usize = 11;
+const SSIM_SIGMA: f64 = 1.5;
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+/// Normalised Gaussian taps, truncated to `size`.
+fn gaussian(size: usize) -> Vec<f64> {
+    let centre = (size as f64 - 1.0) / 2.0;
+    let taps: Vec<f64> = (0..size)
+        .map(|i| {
+            let d = i as f64 - centre;
+            (-d * d / (2.0 * SSIM_SIGMA * SSIM_SIGMA)).exp()
+        })
+        .collect();
+    let sum: f64 = taps.iter().sum();
+    taps.into_iter().map(|t| t / sum).collect()
+}
+
+/// Separable filtering over the positions where the window fits.
+fn filter_valid(plane: &[f64], width: usize, height: usize, taps: &[f64]) -> Vec<f64> {
+    let n = taps.len();
+    let (out_w, out_h) = (width + 1 - n, height + 1 - n);
+    let mut rows = Vec::with_capacity(out_w * height);
+    for row in plane.chunks(width) {
+        for x in 0..out_w {
+            rows.push(taps.iter().zip(&row[x..x + n]).map(|(t, v)| t * v).sum::<f64>());
+        }
+    }
+    let mut out = Vec::with_capacity(out_w * out_h);
+    for y in 0..out_h {
+        for x in 0..out_w {
+            out.push(taps.iter().enumerate().map(|(i, t)| t * rows[(y + i) * out_w + x]).sum::<f64>());
+        }
+    }
+    out
+}
+
+/// Mean SSIM and mean contrast-structure term of two planes.
+fn ssim_terms(a: &[f64], b: &[f64], width: usize, height: usize, peak: f64) -> (f64, f64) {
+    let size = SSIM_WINDOW.min(width).min(height);
+    if size == 0 {
+        return (1.0, 1.0);
+    }
+    let taps = gaussian(size);
+    let filter = |plane: &[f64]| filter_valid(plane, width, height, &taps);
+    let product = |x: &[f64], y: &[f64]| -> Vec<f64> { x.iter().zip(y).map(|(p, q)| p * q).collect() };
+
+    let (mu_a, mu_b) = (filter(a), filter(b));
+    let (aa, bb, ab) = (filter(&product(a, a)), filter(&product(b, b)), filter(&product(a, b)));
+    let c1 = (0.01 * peak) * (0.01 * peak);
+    let c2 = (0.03 * peak) * (0.03 * peak);
+
+    let (mut ssim, mut cs) = (0.0, 0.0);
+    for i in 0..mu_a.len() {
+        let (ma, mb) = (mu_a[i], mu_b[i]);
+        let var_a = aa[i] - ma * ma;
+        let var_b = bb[i] - mb * mb;
+        let cov = ab[i] - ma * mb;
+        let contrast = (2.0 * cov + c2) / (var_a + var_b + c2);
+        cs += contrast;
+        ssim += contrast * (2.0 * ma * mb + c1) / (ma * ma + mb * mb + c1);
+    }
+    let n = mu_a.len() as f64;
+    (ssim / n, cs / n)
+}
+
+fn to_f64_plane<C: Channel>(plane: &Plane<C>) -> Vec<f64> {
+    plane.data.iter().map(|v| v.to_f64().unwrap()).collect()
+}
+
+/// Halves a plane with a 2x2 box filter, dropping an odd last row or column.
+fn downsample(plane: &[f64], width: usize, height: usize) -> (Vec<f64>, usize, usize) {
+    let (w, h) = (width / 2, height / 2);
+    let mut out = Vec::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            let at = |dx: usize, dy: usize| plane[(2 * y + dy) * width + 2 * x + dx];
+            out.push((at(0, 0) + at(1, 0) + at(0, 1) + at(1, 1)) / 4.0);
+        }
+    }
+    (out, w, h)
+}
+
+/// Structural similarity of each channel, using an 11x11 Gaussian window
+/// with sigma 1.5.  Planes smaller than the window use a truncated one.
+pub fn ssim<M, C, SA, SB>(a: &Surface<M, C, SA>, b: &Surface<M, C, SB>) -> Vec<f64>
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        SA: Deref<Target=[C]>,
+        SB: Deref<Target=[C]>,
+{
+    paired_planes(a, b).iter()
+        .map(|(pa, pb)| {
+            let (fa, fb) = (to_f64_plane(pa), to_f64_plane(pb));
+            ssim_terms(&fa, &fb, pa.width, pa.height, peak::<C>()).0
+        })
+        .collect()
+}
+
+/// Multi-scale structural similarity of each channel over up to five
+/// scales.  Scales at which a plane would be smaller than the window are
+/// skipped and the remaining weights renormalised.
+pub fn ms_ssim<M, C, SA, SB>(a: &Surface<M, C, SA>, b: &Surface<M, C, SB>) -> Vec<f64>
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        SA: Deref<Target=[C]>,
+        SB: Deref<Target=[C]>,
+{
+    paired_planes(a, b).iter()
+        .map(|(pa, pb)| {
+            let (mut fa, mut fb) = (to_f64_plane(pa), to_f64_plane(pb));
+            let (mut width, mut height) = (pa.width, pa.height);
+            let mut scales = 1;
+            while scales < MS_SSIM_WEIGHTS.len() && (width >> scales).min(height >> scales) >= SSIM_WINDOW {
+                scales += 1;
+            }
+            let weights = &MS_SSIM_WEIGHTS[..scales];
+            let total: f64 = weights.iter().sum();
+
+            let mut score = 1.0;
+            for (scale, weight) in weights.iter().enumerate() {
+                let (ssim, cs) = ssim_terms(&fa, &fb, width, height, peak::<C>());
+                let term = if scale + 1 == scales { ssim } else { cs };
+                score *= term.max(0.0).powf(weight / total);
+                if scale + 1 < scales {
+                    let (da, w, h) = downsample(&fa, width, height);
+                    fb = downsample(&fb, width, height).0;
+                    fa = da;
+                    width = w;
+                    height = h;
+                }
+            }
+            score
+        })
+        .collect()
+}
+
+#[cfg(test)]
+type RgbSurface = Surface<super::Rgb, u8, Vec<u8>>;
+
+#[cfg(test)]
+fn noisy_pair(width: u32, height: u32, amplitude: i32) -> (RgbSurface, RgbSurface) {
+    let len = 3 * width as usize * height as usize;
+    let original: Vec<u8> = (0..len).map(|i| (((i / 3) % width as usize) * 4 + i % 3 * 30) as u8).collect();
+    let mut state = 12345u32;
+    let noisy: Vec<u8> = original.iter()
+        .map(|&v| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let noise = (state >> 16) as i32 % (2 * amplitude + 1) - amplitude;
+            (v as i32 + noise).clamp(0, 255) as u8
+        })
+        .collect();
+    (Surface::new(width, height, original), Surface::new(width, height, noisy))
+}
+
+#[test]
+fn mse_and_psnr() {
+    use super::Luma;
+
+    let a: Surface<Luma, u8, Vec<u8>> = Surface::new(2, 2, vec![10, 20, 30, 40]);
+    let b: Surface<Luma, u8, Vec<u8>> = Surface::new(2, 2, vec![12, 20, 30, 38]);
+    assert_eq!(mse(&a, &b), vec![2.0]);
+    let expected = 10.0 * (255.0f64 * 255.0 / 2.0).log10();
+    assert!((psnr(&a, &b)[0] - expected).abs() < 1e-9);
+    assert_eq!(psnr(&a, &a)[0], f64::INFINITY);
+}
+
+#[test]
+fn yuv420p_is_measured_per_plane() {
+    let a: Surface<Yuv420p, u8, Vec<u8>> = Surface::new(2, 2, vec![1, 2, 3, 4, 100, 200]);
+    let b: Surface<Yuv420p, u8, Vec<u8>> = Surface::new(2, 2, vec![1, 2, 3, 8, 104, 200]);
+    let errors = mse(&a, &b);
+    assert_eq!(errors, vec![4.0, 16.0, 0.0]);
+    assert_eq!(yuv_weighted(&errors), 5.0);
+}
+
+#[test]
+fn packed_and_planar_422_agree() {
+    // Width 3: the packed row's last Y1 is padding and is not compared.
+    let a: Surface<Yuv422, u8, Vec<u8>> = Surface::new(3, 1, vec![10, 100, 20, 50, 30, 120, 0, 60]);
+    let b: Surface<Yuv422, u8, Vec<u8>> = Surface::new(3, 1, vec![10, 104, 26, 50, 30, 120, 99, 60]);
+    let pa: Surface<Yuv422p, u8, Vec<u8>> = Surface::new(3, 1, vec![10, 20, 30, 100, 120, 50, 60]);
+    let pb: Surface<Yuv422p, u8, Vec<u8>> = Surface::new(3, 1, vec![10, 26, 30, 104, 120, 50, 60]);
+    assert_eq!(mse(&a, &b), vec![12.0, 8.0, 0.0]);
+    assert_eq!(mse(&a, &b), mse(&pa, &pb));
+    assert_eq!(ssim(&a, &b), ssim(&pa, &pb));
+}
+
+#[test]
+fn ssim_ranks_distortion() {
+    let (original, slight) = noisy_pair(48, 40, 4);
+    let (_, heavy) = noisy_pair(48, 40, 40);
+
+    let same = ssim(&original, &original);
+    assert!(same.iter().all(|&s| (s - 1.0).abs() < 1e-12));
+
+    let (s_slight, s_heavy) = (ssim(&original, &slight), ssim(&original, &heavy));
+    assert_eq!(s_slight.len(), 3);
+    for c in 0..3 {
+        assert!(s_slight[c] < 1.0 && s_heavy[c] < s_slight[c], "{:?} {:?}", s_slight, s_heavy);
+    }
+
+    let (m_slight, m_heavy) = (ms_ssim(&original, &slight), ms_ssim(&original, &heavy));
+    assert!(ms_ssim(&original, &original).iter().all(|&s| (s - 1.0).abs() < 1e-12));
+    for c in 0..3 {
+        assert!(m_slight[c] < 1.0 && m_heavy[c] < m_slight[c], "{:?} {:?}", m_slight, m_heavy);
+    }
+}
+
+#[test]
+fn tiny_planes_use_truncated_window() {
+    let a: Surface<Yuv420p, u8, Vec<u8>> = Surface::new(4, 4, (0..24).collect());
+    let scores = ssim(&a, &a);
+    assert_eq!(scores.len(), 3);
+    assert!(scores.iter().all(|&s| (s - 1.0).abs() < 1e-12));
+}
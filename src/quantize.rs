@@ -0,0 +1,399 @@
+//! Palette generation and indexed colour.
+//!
+//! A palette of at most 256 `ColorRGBA<u8>` entries is built from an 8-bit
+//! RGB surface by median cut or by an octree, and the surface is then
+//! mapped onto it, optionally with Floyd–Steinberg dithering.  The result
+//! is an `IndexedImage`: an `Indexed8` surface of palette indices together
+//! with its palette.
+
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use super::{ChannelAccess, ColorL, ColorRGBA, Format, Surface};
+use super::dither::{Dither, Ditherer};
+use super::tone::is_rgb;
+use super::{Indexed8, Rgba};
+
+/// The most entries an `Indexed8` palette can address.
+pub const MAX_PALETTE_SIZE: usize = 256;
+
+/// An `Indexed8` surface with the palette its indices refer to.
+#[derive(Clone)]
+pub struct IndexedImage {
+    indices: Surface<Indexed8, u8, Box<[u8]>>,
+    palette: Vec<ColorRGBA<u8>>,
+}
+
+impl IndexedImage {
+    /// Panics if the palette is empty, too large, or lacks an entry for
+    /// one of the indices.
+    pub fn new(indices: Surface<Indexed8, u8, Box<[u8]>>, palette: Vec<ColorRGBA<u8>>) -> IndexedImage {
+        assert!(!palette.is_empty() && palette.len() <= MAX_PALETTE_SIZE, "a palette holds 1 to 256 colours");
+        assert!(indices.as_storage().iter().all(|&index| (index as usize) < palette.len()),
+            "every index needs a palette entry");
+        IndexedImage { indices, palette }
+    }
+
+    pub fn indices(&self) -> &Surface<Indexed8, u8, Box<[u8]>> {
+        &self.indices
+    }
+
+    pub fn palette(&self) -> &[ColorRGBA<u8>] {
+        &self.palette
+    }
+
+    pub fn width(&self) -> u32 {
+        self.indices.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.indices.height()
+    }
+
+    /// The palette colour at `(x, y)`.
+    pub fn color(&self, x: u32, y: u32) -> ColorRGBA<u8> {
+        self.palette[self.indices.get_pixel(x, y).l as usize]
+    }
+
+    /// Expands the indices into colours.
+    pub fn to_rgba(&self) -> Surface<Rgba, u8, Box<[u8]>> {
+        let mut out: Surface<Rgba, u8, Box<[u8]>> = Surface::new_black(self.width(), self.height());
+        out.map_in_place(|x, y, _| self.color(x, y));
+        out
+    }
+}
+
+/// How `generate_palette` chooses colours.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaletteMethod {
+    /// Repeatedly splits the box of colours with the widest channel range
+    /// at its median.  Slower, and better at keeping rare colours.
+    MedianCut,
+    /// Merges the least used branches of an RGB octree.  Alpha is averaged
+    /// per entry.
+    Octree,
+}
+
+/// The pixel as RGBA, with formats without alpha opaque.
+#[inline]
+fn rgba_of<P: ChannelAccess<Channel=u8>>(px: &P) -> [u8; 4] {
+    let alpha = if P::channel_count() > 3 { px.channel(3) } else { 0xFF };
+    [px.channel(0), px.channel(1), px.channel(2), alpha]
+}
+
+fn to_color(rgba: [u8; 4]) -> ColorRGBA<u8> {
+    ColorRGBA::new_rgba(rgba[0], rgba[1], rgba[2], rgba[3])
+}
+
+/// Adds `n` pixels of `rgba` to a per-channel sum.
+#[inline]
+fn accumulate(sum: &mut [u64; 4], rgba: [u8; 4], n: u64) {
+    for (total, &value) in sum.iter_mut().zip(&rgba) {
+        *total += value as u64 * n;
+    }
+}
+
+/// The count-weighted mean of some colours.
+fn mean_color(sum: [u64; 4], count: u64) -> ColorRGBA<u8> {
+    let mean = |ch: usize| ((sum[ch] + count / 2) / count) as u8;
+    to_color([mean(0), mean(1), mean(2), mean(3)])
+}
+
+/// Every distinct colour with its pixel count, in a fixed order.
+fn histogram<M, S>(input: &Surface<M, u8, S>) -> Vec<([u8; 4], u64)>
+    where
+        M: Format<u8>,
+        M::Pixel: ChannelAccess,
+        S: Deref<Target=[u8]>,
+{
+    let mut counts: HashMap<[u8; 4], u64> = HashMap::new();
+    for px in input.iter_pixels() {
+        *counts.entry(rgba_of(&px)).or_insert(0) += 1;
+    }
+    let mut colors: Vec<_> = counts.into_iter().collect();
+    colors.sort_unstable();
+    colors
+}
+
+fn channel_range(colors: &[([u8; 4], u64)], ch: usize) -> u8 {
+    let min = colors.iter().map(|c| c.0[ch]).min().unwrap();
+    let max = colors.iter().map(|c| c.0[ch]).max().unwrap();
+    max - min
+}
+
+/// The channel with the widest range and that range.
+fn widest_channel(colors: &[([u8; 4], u64)]) -> (usize, u8) {
+    (0..4).map(|ch| (ch, channel_range(colors, ch)))
+        .fold((0, 0), |best, cur| if cur.1 > best.1 { cur } else { best })
+}
+
+fn median_cut(colors: Vec<([u8; 4], u64)>, max_colors: usize) -> Vec<ColorRGBA<u8>> {
+    let mut boxes = vec![colors];
+    while boxes.len() < max_colors {
+        let widest = boxes.iter().enumerate()
+            .filter(|&(_, b)| b.len() > 1)
+            .map(|(index, b)| (index, widest_channel(b)))
+            .fold(None, |best: Option<(usize, (usize, u8))>, cur| match best {
+                Some(best) if best.1 .1 >= cur.1 .1 => Some(best),
+                _ => Some(cur),
+            });
+        let (index, (ch, _)) = match widest {
+            Some(widest) => widest,
+            None => break,
+        };
+
+        let mut split = boxes.swap_remove(index);
+        split.sort_by_key(|c| c.0[ch]);
+        let total: u64 = split.iter().map(|c| c.1).sum();
+        let mut seen = 0;
+        let mut at = split.len() - 1;
+        for (i, c) in split.iter().enumerate() {
+            seen += c.1;
+            if 2 * seen >= total {
+                at = i + 1;
+                break;
+            }
+        }
+        let at = at.clamp(1, split.len() - 1);
+        let upper = split.split_off(at);
+        boxes.push(split);
+        boxes.push(upper);
+    }
+
+    boxes.iter()
+        .map(|b| {
+            let mut sum = [0u64; 4];
+            let mut count = 0;
+            for &(rgba, n) in b {
+                accumulate(&mut sum, rgba, n);
+                count += n;
+            }
+            mean_color(sum, count)
+        })
+        .collect()
+}
+
+struct OctreeNode {
+    children: [Option<usize>; 8],
+    sum: [u64; 4],
+    count: u64,
+}
+
+const OCTREE_DEPTH: usize = 8;
+
+fn octree(colors: &[([u8; 4], u64)], max_colors: usize) -> Vec<ColorRGBA<u8>> {
+    let empty = || OctreeNode { children: [None; 8], sum: [0; 4], count: 0 };
+    let mut nodes = vec![empty()];
+    // Internal nodes by depth, so the deepest can be merged first.
+    let mut internal: Vec<Vec<usize>> = vec![Vec::new(); OCTREE_DEPTH];
+    let mut leaves = 0;
+
+    for &(rgba, n) in colors {
+        let mut node = 0;
+        accumulate(&mut nodes[0].sum, rgba, n);
+        nodes[0].count += n;
+        for (level, internal) in internal.iter_mut().enumerate() {
+            let bit = 7 - level;
+            let child = (((rgba[0] >> bit) & 1) << 2 | ((rgba[1] >> bit) & 1) << 1 | ((rgba[2] >> bit) & 1)) as usize;
+            node = match nodes[node].children[child] {
+                Some(next) => next,
+                None => {
+                    if nodes[node].children.iter().all(Option::is_none) {
+                        internal.push(node);
+                    }
+                    nodes.push(empty());
+                    let next = nodes.len() - 1;
+                    nodes[node].children[child] = Some(next);
+                    if level + 1 == OCTREE_DEPTH {
+                        leaves += 1;
+                    }
+                    next
+                }
+            };
+            accumulate(&mut nodes[node].sum, rgba, n);
+            nodes[node].count += n;
+        }
+    }
+
+    while leaves > max_colors {
+        let level = internal.iter().rposition(|nodes| !nodes.is_empty()).unwrap();
+        let (position, _) = internal[level].iter().enumerate()
+            .min_by_key(|&(_, &node)| nodes[node].count)
+            .unwrap();
+        let node = internal[level].swap_remove(position);
+        let merged = nodes[node].children.iter().filter(|c| c.is_some()).count();
+        nodes[node].children = [None; 8];
+        leaves -= merged - 1;
+    }
+
+    let mut palette = Vec::with_capacity(leaves);
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        let children: Vec<usize> = nodes[node].children.iter().filter_map(|&c| c).collect();
+        if children.is_empty() {
+            palette.push(mean_color(nodes[node].sum, nodes[node].count));
+        } else {
+            stack.extend(children.into_iter().rev());
+        }
+    }
+    palette
+}
+
+/// Builds a palette of at most `max_colors` entries from an 8-bit `Rgb`,
+/// `Rgba`, `RgbPlanar` or `RgbaPlanar` surface.  Surfaces with fewer
+/// distinct colours get one entry per colour.
+pub fn generate_palette<M, S>(input: &Surface<M, u8, S>, max_colors: usize, method: PaletteMethod) -> Vec<ColorRGBA<u8>>
+    where
+        M: Format<u8> + 'static,
+        M::Pixel: ChannelAccess,
+        S: Deref<Target=[u8]>,
+{
+    assert!(is_rgb::<M>(), "palettes are built from RGB formats");
+    assert!((1..=MAX_PALETTE_SIZE).contains(&max_colors), "a palette holds 1 to 256 colours");
+
+    let colors = histogram(input);
+    if colors.is_empty() {
+        return vec![ColorRGBA::new_rgba(0, 0, 0, 0xFF)];
+    }
+    if colors.len() <= max_colors {
+        return colors.into_iter().map(|(rgba, _)| to_color(rgba)).collect();
+    }
+    match method {
+        PaletteMethod::MedianCut => median_cut(colors, max_colors),
+        PaletteMethod::Octree => octree(&colors, max_colors),
+    }
+}
+
+/// The index of the palette entry nearest `rgba` in RGBA space.
+fn nearest(palette: &[[i32; 4]], rgba: [i32; 4]) -> u8 {
+    let distance = |entry: &[i32; 4]| (0..4).map(|ch| (entry[ch] - rgba[ch]).pow(2)).sum::<i32>();
+    let (index, _) = palette.iter().enumerate().min_by_key(|&(_, entry)| distance(entry)).unwrap();
+    index as u8
+}
+
+/// Maps an 8-bit RGB surface onto `palette`, choosing the nearest entry
+/// for each pixel.  With `dither` the rounding error is spread over the
+/// following pixels with Floyd–Steinberg weights.
+pub fn map_to_palette<M, S>(input: &Surface<M, u8, S>, palette: &[ColorRGBA<u8>], dither: bool) -> IndexedImage
+    where
+        M: Format<u8> + 'static,
+        M::Pixel: ChannelAccess,
+        S: Deref<Target=[u8]>,
+{
+    assert!(is_rgb::<M>(), "palettes are built from RGB formats");
+    assert!(!palette.is_empty() && palette.len() <= MAX_PALETTE_SIZE, "a palette holds 1 to 256 colours");
+
+    let entries: Vec<[i32; 4]> = palette.iter().map(|c| [c.r as i32, c.g as i32, c.b as i32, c.a as i32]).collect();
+    let (width, height) = (input.width(), input.height());
+    let mut indices: Surface<Indexed8, u8, Box<[u8]>> = Surface::new_black(width, height);
+
+    if !dither {
+        let mut cache: HashMap<[u8; 4], u8> = HashMap::new();
+        indices.map_in_place(|x, y, _| {
+            let rgba = rgba_of(&input.get_pixel(x, y));
+            let index = *cache.entry(rgba).or_insert_with(|| {
+                nearest(&entries, [rgba[0] as i32, rgba[1] as i32, rgba[2] as i32, rgba[3] as i32])
+            });
+            ColorL::new_l(index)
+        });
+        return IndexedImage { indices, palette: palette.to_vec() };
+    }
+
+    let mut ditherer = Ditherer::new(Dither::FloydSteinberg, width, 4);
+    indices.map_in_place(|x, y, _| {
+        let rgba = rgba_of(&input.get_pixel(x, y));
+        let mut wanted = [0.0; 4];
+        let mut target = [0; 4];
+        for ch in 0..4 {
+            wanted[ch] = ditherer.carried(x, y, ch, rgba[ch] as f64).clamp(0.0, 255.0);
+            target[ch] = wanted[ch].round() as i32;
+        }
+        let index = nearest(&entries, target);
+        for ch in 0..4 {
+            ditherer.diffuse(x, ch, wanted[ch] - entries[index as usize][ch] as f64);
+        }
+        ColorL::new_l(index)
+    });
+    IndexedImage { indices, palette: palette.to_vec() }
+}
+
+/// Generates a palette of at most `max_colors` entries and maps `input`
+/// onto it.
+pub fn quantize<M, S>(input: &Surface<M, u8, S>, max_colors: usize, method: PaletteMethod, dither: bool) -> IndexedImage
+    where
+        M: Format<u8> + 'static,
+        M::Pixel: ChannelAccess,
+        S: Deref<Target=[u8]>,
+{
+    let palette = generate_palette(input, max_colors, method);
+    map_to_palette(input, &palette, dither)
+}
+
+#[cfg(test)]
+fn gradient(width: u32, height: u32) -> Surface<super::Rgb, u8, Vec<u8>> {
+    use super::{ColorRGB, Rgb};
+
+    let mut surf: Surface<Rgb, u8, Vec<u8>> = Surface::new_black(width, height);
+    surf.map_in_place(|x, y, _| ColorRGB::new_rgb((x * 255 / (width - 1)) as u8, (y * 255 / (height - 1)) as u8, 96));
+    surf
+}
+
+#[test]
+fn palettes_from_few_colours_are_exact() {
+    let rgba: Surface<Rgba, u8, Vec<u8>> = Surface::new(3, 1, vec![
+        10, 20, 30, 255,
+        200, 0, 0, 128,
+        10, 20, 30, 255,
+    ]);
+    for &method in &[PaletteMethod::MedianCut, PaletteMethod::Octree] {
+        let image = quantize(&rgba, 16, method, false);
+        assert_eq!(image.palette().len(), 2);
+        let back = image.to_rgba();
+        assert_eq!(&back.as_storage()[..], &rgba.as_storage()[..]);
+        assert_eq!(image.indices().get_pixel(0, 0).l, image.indices().get_pixel(2, 0).l);
+    }
+}
+
+#[test]
+fn palettes_are_limited_and_representative() {
+    use super::Rgb;
+
+    let surf = gradient(64, 64);
+    for &method in &[PaletteMethod::MedianCut, PaletteMethod::Octree] {
+        let palette = generate_palette(&surf, 16, method);
+        assert!(palette.len() <= 16 && palette.len() >= 8, "{:?} gave {} colours", method, palette.len());
+        assert!(palette.iter().all(|c| c.b == 96 && c.a == 255));
+
+        let image = map_to_palette(&surf, &palette, false);
+        let error: f64 = surf.enumerate_pixels()
+            .map(|(x, y, px)| {
+                let c = image.color(x, y);
+                ((px.r as f64 - c.r as f64).powi(2) + (px.g as f64 - c.g as f64).powi(2)).sqrt()
+            })
+            .sum::<f64>() / (64.0 * 64.0);
+        assert!(error < 40.0, "{:?} mean error {}", method, error);
+    }
+
+    let grey: Surface<Rgb, u8, Vec<u8>> = Surface::new(4, 1, vec![0, 0, 0, 80, 80, 80, 90, 90, 90, 255, 255, 255]);
+    let palette = generate_palette(&grey, 3, PaletteMethod::MedianCut);
+    let mut levels: Vec<u8> = palette.iter().map(|c| c.r).collect();
+    levels.sort();
+    // The widest box is split at its median each time.
+    assert_eq!(levels, vec![40, 90, 255]);
+}
+
+#[test]
+fn dithering_preserves_average_colour() {
+    use super::Rgb;
+
+    let flat: Surface<Rgb, u8, Vec<u8>> = Surface::new(16, 16, vec![64; 16 * 16 * 3]);
+    let palette = [ColorRGBA::new_rgb(0, 0, 0), ColorRGBA::new_rgb(255, 255, 255)];
+
+    let plain = map_to_palette(&flat, &palette, false);
+    assert!(plain.indices().as_storage().iter().all(|&i| i == 0));
+
+    let dithered = map_to_palette(&flat, &palette, true);
+    let white = dithered.indices().as_storage().iter().filter(|&&i| i == 1).count();
+    // 64 / 255 of 256 pixels is about 64.
+    assert!((60..=68).contains(&white), "{} white pixels", white);
+}
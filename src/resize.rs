@@ -1,6 +1,10 @@
 use std::ops::{Deref, DerefMut};
 
 use super::{Surface, Channel, Format};
+#[cfg(feature = "parallel")]
+use super::PackedFormat;
+#[cfg(feature = "parallel")]
+use super::parallel::{self, Partition};
 
 pub fn resize_nearest<F, C, SI, SO>(input: &Surface<F, C, SI>, output: &mut Surface<F, C, SO>)
     where
@@ -13,14 +17,38 @@ pub fn resize_nearest<F, C, SI, SO>(input: &Surface<F, C, SI>, output: &mut Surf
     let (output_width, output_height) = (output.width(), output.height());
 
     for y in 0..output_height {
-        let sy = ((y * input_height) as f64 / output_height as f64).round() as u32;
+        let sy = nearest_source(y, input_height, output_height);
         for x in 0..output_width {
-            let sx = ((x * input_width) as f64 / output_width as f64).round() as u32;
+            let sx = nearest_source(x, input_width, output_width);
             output.put_pixel(x, y, input.get_pixel(sx, sy));
         }
     }
 }
 
+/// Parallel counterpart of `resize_nearest`, producing identical output.
+#[cfg(feature = "parallel")]
+pub fn resize_nearest_par<F, C, SI, SO>(input: &Surface<F, C, SI>, output: &mut Surface<F, C, SO>, partition: Partition)
+    where
+        F: PackedFormat<C> + Sync,
+        C: Channel + Send + Sync,
+        SI: Deref<Target=[C]> + Sync,
+        SO: Deref<Target=[C]> + DerefMut,
+{
+    let (input_width, input_height) = (input.width(), input.height());
+    let (output_width, output_height) = (output.width(), output.height());
+
+    parallel::fill_with(output, partition, |x, y| {
+        let sx = nearest_source(x, input_width, output_width);
+        let sy = nearest_source(y, input_height, output_height);
+        input.get_pixel(sx, sy)
+    });
+}
+
+#[inline]
+fn nearest_source(pos: u32, input_len: u32, output_len: u32) -> u32 {
+    ((pos * input_len) as f64 / output_len as f64).round() as u32
+}
+
 
 // pub struct Surface<M, C, S>
 //     where
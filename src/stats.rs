@@ -0,0 +1,310 @@
+//! Per-channel histograms and summary statistics.
+//!
+//! Channels are reported in the field order of the format's pixel, e.g.
+//! R, G, B for `Rgb` and Y, U, V for the YUV formats.  `Luma`, `Yuv420p`
+//! and `Yuv422p` are read straight from their planes; for the planar YUV
+//! formats the chroma statistics are therefore over chroma samples rather
+//! than pixels.
+
+use std::any::TypeId;
+use std::ops::Deref;
+
+use super::{Channel, ChannelAccess, Format, PlanarFormat, Surface, Luma, Yuv420p, Yuv422p};
+
+/// Counts of channel values in equal-width bins spanning the channel's
+/// range.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    bins: Vec<u64>,
+}
+
+impl Histogram {
+    /// An empty histogram.
+    pub fn new(bins: usize) -> Histogram {
+        assert!(bins > 0, "histograms need at least one bin");
+        Histogram { bins: vec![0; bins] }
+    }
+
+    /// The number of bins used when none is given: one per value for
+    /// channels of up to 16 bits, otherwise 256.
+    pub fn default_bins<C: Channel>() -> usize {
+        match C::max_depth() {
+            Some(max) if max <= 0xFFFF => max as usize + 1,
+            _ => 256,
+        }
+    }
+
+    /// The bin holding `value` when the range of `C` is split into `bins`.
+    pub fn bin_of<C: Channel>(value: C, bins: usize) -> usize {
+        let (lo, width) = range_of::<C>();
+        let pos = (value.to_f64().unwrap() - lo) * bins as f64 / width;
+        (pos.max(0.0) as usize).min(bins - 1)
+    }
+
+    /// The lower bound of bin `index` as a channel value.
+    pub fn bin_start<C: Channel>(index: usize, bins: usize) -> f64 {
+        let (lo, width) = range_of::<C>();
+        lo + width * index as f64 / bins as f64
+    }
+
+    pub fn bins(&self) -> &[u64] {
+        &self.bins
+    }
+
+    /// Counts `values` into the histogram.
+    pub fn add<C: Channel>(&mut self, values: &[C]) {
+        let bins = self.bins.len();
+        for &value in values {
+            self.bins[Histogram::bin_of(value, bins)] += 1;
+        }
+    }
+
+    /// The number of values counted.
+    pub fn total(&self) -> u64 {
+        self.bins.iter().sum()
+    }
+
+    /// Running totals: entry `i` counts the values in bins `0..=i`.
+    pub fn cumulative(&self) -> Vec<u64> {
+        self.bins.iter()
+            .scan(0, |acc, &count| {
+                *acc += count;
+                Some(*acc)
+            })
+            .collect()
+    }
+}
+
+/// The lowest value of `C` and the width of its range.  Integer ranges
+/// include their top value.
+fn range_of<C: Channel>() -> (f64, f64) {
+    let lo = C::min_value().to_f64().unwrap();
+    let hi = C::max_value().to_f64().unwrap();
+    let inclusive = if C::max_depth().is_some() { 1.0 } else { 0.0 };
+    (lo, hi - lo + inclusive)
+}
+
+/// Summary statistics of one channel.  The standard deviation is that of
+/// the population.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelStats {
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+/// Running statistics, with the mean and variance updated by Welford's
+/// method so that large, nearly constant values keep their precision.
+struct Accumulator {
+    stats: ChannelStats,
+    /// The sum of squared differences from the running mean.
+    m2: f64,
+}
+
+impl Accumulator {
+    fn new() -> Accumulator {
+        Accumulator {
+            stats: ChannelStats { count: 0, min: 0.0, max: 0.0, mean: 0.0, stddev: 0.0 },
+            m2: 0.0,
+        }
+    }
+
+    #[inline]
+    fn add(&mut self, value: f64) {
+        let stats = &mut self.stats;
+        if stats.count == 0 || value < stats.min {
+            stats.min = value;
+        }
+        if stats.count == 0 || value > stats.max {
+            stats.max = value;
+        }
+        stats.count += 1;
+        let delta = value - stats.mean;
+        stats.mean += delta / stats.count as f64;
+        self.m2 += delta * (value - stats.mean);
+    }
+
+    fn finish(self) -> ChannelStats {
+        let mut stats = self.stats;
+        if stats.count > 0 {
+            stats.stddev = (self.m2 / stats.count as f64).sqrt();
+        }
+        stats
+    }
+}
+
+/// The histogram of a run of channel values, e.g. one plane.
+pub fn histogram_of<C: Channel>(values: &[C], bins: usize) -> Histogram {
+    let mut hist = Histogram::new(bins);
+    hist.add(values);
+    hist
+}
+
+/// The statistics of a run of channel values, e.g. one plane.
+pub fn stats_of<C: Channel>(values: &[C]) -> ChannelStats {
+    let mut acc = Accumulator::new();
+    for &value in values {
+        acc.add(value.to_f64().unwrap());
+    }
+    acc.finish()
+}
+
+/// The planes that can be read directly, if `M` has any.
+fn planes<M, C, S>(surface: &Surface<M, C, S>) -> Option<Vec<&[C]>>
+    where
+        M: Format<C> + 'static,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    let size = (surface.width(), surface.height());
+    let storage = &surface.as_storage()[..];
+    let format = TypeId::of::<M>();
+    if format == TypeId::of::<Luma>() {
+        return Some(vec![&storage[..size.0 as usize * size.1 as usize]]);
+    }
+    if format == TypeId::of::<Yuv420p>() {
+        let (y, u, v) = <Yuv420p as PlanarFormat<C>>::get_planes(storage, size);
+        return Some(vec![y, u, v]);
+    }
+    if format == TypeId::of::<Yuv422p>() {
+        let (y, u, v) = <Yuv422p as PlanarFormat<C>>::get_planes(storage, size);
+        return Some(vec![y, u, v]);
+    }
+    None
+}
+
+/// One histogram per channel.  `bins` defaults to
+/// `Histogram::default_bins::<C>()`.
+pub fn histograms<M, C, S>(surface: &Surface<M, C, S>, bins: Option<usize>) -> Vec<Histogram>
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    let bins = bins.unwrap_or_else(Histogram::default_bins::<C>);
+    if let Some(planes) = planes(surface) {
+        return planes.iter().map(|plane| histogram_of(plane, bins)).collect();
+    }
+
+    let channels = <M::Pixel as ChannelAccess>::channel_count();
+    let mut hists = vec![Histogram::new(bins); channels];
+    for px in surface.iter_pixels() {
+        for (index, hist) in hists.iter_mut().enumerate() {
+            hist.bins[Histogram::bin_of(px.channel(index), bins)] += 1;
+        }
+    }
+    hists
+}
+
+/// Minimum, maximum, mean and standard deviation of every channel.
+pub fn channel_stats<M, C, S>(surface: &Surface<M, C, S>) -> Vec<ChannelStats>
+    where
+        M: Format<C> + 'static,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    if let Some(planes) = planes(surface) {
+        return planes.iter().map(|plane| stats_of(plane)).collect();
+    }
+
+    let channels = <M::Pixel as ChannelAccess>::channel_count();
+    let mut accs: Vec<Accumulator> = (0..channels).map(|_| Accumulator::new()).collect();
+    for px in surface.iter_pixels() {
+        for (index, acc) in accs.iter_mut().enumerate() {
+            acc.add(px.channel(index).to_f64().unwrap());
+        }
+    }
+    accs.into_iter().map(Accumulator::finish).collect()
+}
+
+#[test]
+fn rgb_histograms_and_stats() {
+    use super::Rgb;
+
+    let storage = vec![
+        0, 10, 255,
+        0, 20, 255,
+        4, 30, 255,
+        4, 40, 255,
+    ];
+    let surf: Surface<Rgb, u8, Vec<u8>> = Surface::new(2, 2, storage);
+    let hist = histograms(&surf, None);
+    assert_eq!(hist.len(), 3);
+    assert_eq!(hist[0].bins().len(), 256);
+    assert_eq!((hist[0].bins()[0], hist[0].bins()[4]), (2, 2));
+    assert_eq!(hist[2].bins()[255], 4);
+    assert_eq!(hist[0].cumulative()[3], 2);
+    assert_eq!(hist[0].cumulative()[255], 4);
+
+    let coarse = histograms(&surf, Some(4));
+    assert_eq!(coarse[1].bins(), &[4, 0, 0, 0][..]);
+    assert_eq!(coarse[2].bins(), &[0, 0, 0, 4][..]);
+
+    let stats = channel_stats(&surf);
+    assert_eq!((stats[0].min, stats[0].max, stats[0].mean, stats[0].stddev), (0.0, 4.0, 2.0, 2.0));
+    assert_eq!((stats[1].mean, stats[1].count), (25.0, 4));
+    assert_eq!(stats[2].stddev, 0.0);
+}
+
+#[test]
+fn wide_and_float_channels() {
+    use super::Rgba;
+
+    assert_eq!(Histogram::default_bins::<u16>(), 65536);
+    assert_eq!(Histogram::default_bins::<u32>(), 256);
+    assert_eq!(Histogram::default_bins::<f64>(), 256);
+    assert_eq!(Histogram::bin_of(0xFFFFu16, 16), 15);
+    assert_eq!(Histogram::bin_of(0x0FFFu16, 16), 0);
+    assert_eq!(Histogram::bin_of(1.0f64, 10), 9);
+    assert_eq!(Histogram::bin_of(0.25f64, 4), 1);
+    assert_eq!(Histogram::bin_start::<u8>(2, 4), 128.0);
+
+    let surf: Surface<Rgba, f64, Vec<f64>> = Surface::new(2, 1, vec![0.0, 0.5, 1.0, 1.0, 0.5, 0.5, 1.0, 0.0]);
+    let hist = histograms(&surf, Some(2));
+    assert_eq!(hist[0].bins(), &[1, 1][..]);
+    assert_eq!(hist[1].bins(), &[0, 2][..]);
+    assert_eq!(hist[3].bins(), &[1, 1][..]);
+    assert_eq!(channel_stats(&surf)[0].mean, 0.25);
+}
+
+#[test]
+fn plane_paths_match_samples() {
+    let mut surf: Surface<Yuv420p, u8, Vec<u8>> = Surface::new_black(4, 2);
+    {
+        let (y, u, v) = surf.get_planes_mut();
+        for (i, l) in y.iter_mut().enumerate() {
+            *l = i as u8 * 10;
+        }
+        u.copy_from_slice(&[100, 200]);
+        v.copy_from_slice(&[50, 50]);
+    }
+    let hist = histograms(&surf, None);
+    assert_eq!(hist.iter().map(Histogram::total).collect::<Vec<_>>(), vec![8, 2, 2]);
+    assert_eq!(hist[1].bins()[200], 1);
+
+    let stats = channel_stats(&surf);
+    assert_eq!((stats[0].min, stats[0].max, stats[0].mean), (0.0, 70.0, 35.0));
+    assert_eq!(stats[1].mean, 150.0);
+    assert_eq!(stats[2].stddev, 0.0);
+
+    // Each Yuv422p chroma sample is counted once, not once per pixel.
+    let planar: Surface<Yuv422p, u8, Vec<u8>> = Surface::new(3, 1, vec![1, 2, 3, 40, 60, 80, 80]);
+    let stats = channel_stats(&planar);
+    assert_eq!((stats[1].count, stats[1].mean, stats[2].stddev), (2, 50.0, 0.0));
+
+    let luma: Surface<Luma, u8, Vec<u8>> = Surface::new(3, 1, vec![0, 7, 255]);
+    let stats = channel_stats(&luma);
+    assert_eq!((stats.len(), stats[0].min, stats[0].max), (1, 0.0, 255.0));
+}
+
+#[test]
+fn stddev_keeps_precision_for_large_offsets() {
+    let values: Vec<f64> = (0..1000).map(|i| 1e9 + (i % 2) as f64).collect();
+    let stats = stats_of(&values);
+    assert_eq!(stats.mean, 1e9 + 0.5);
+    assert!((stats.stddev - 0.5).abs() < 1e-9, "{}", stats.stddev);
+}
@@ -0,0 +1,39 @@
+//! Helpers shared by the tone operations: level adjustments, curves and
+//! sharpening.  Which channels hold tone is given by `ToneFormat`.
+
+use std::any::TypeId;
+use std::ops::{Deref, DerefMut};
+
+use super::{Channel, ChannelAccess, Surface, ToneFormat};
+use super::{Rgb, RgbPlanar, Rgba, RgbaPlanar};
+
+/// Whether `M` is one of the RGB formats, with or without alpha.
+pub fn is_rgb<M: 'static>() -> bool {
+    let format = TypeId::of::<M>();
+    format == TypeId::of::<Rgb>() || format == TypeId::of::<Rgba>() ||
+    format == TypeId::of::<RgbPlanar>() || format == TypeId::of::<RgbaPlanar>()
+}
+
+/// Maps the tone channels of `surface` through `luts`, one per channel,
+/// indexed by channel value.
+pub fn apply_luts<M, C, S>(surface: &mut Surface<M, C, S>, luts: &[Vec<C>])
+    where
+        M: ToneFormat<C>,
+        M::Pixel: ChannelAccess,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    if M::LUMA_PLANE {
+        let pixels = surface.width() as usize * surface.height() as usize;
+        for value in surface.as_storage_mut()[..pixels].iter_mut() {
+            *value = luts[0][value.to_usize().unwrap()];
+        }
+        return;
+    }
+    surface.map_in_place(|_, _, px| {
+        px.map_channels(|index, value| match luts.get(index) {
+            Some(lut) => lut[value.to_usize().unwrap()],
+            None => value,
+        })
+    });
+}
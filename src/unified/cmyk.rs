@@ -0,0 +1,262 @@
+//! CMYK formats for print output.
+//!
+//! `Cmyk` stores each pixel as `c, m, y, k` with the channel maximum meaning
+//! full ink coverage, which is the sample layout of a PAM `CMYK` tuple and of
+//! a contiguous TIFF with separated photometric interpretation.
+
+use std::io::{self, Write};
+use std::ops::Deref;
+use std::slice;
+
+use super::{Format, InterleavedFormat, PackedFormat, PlanarFormat, Surface, plane_rows, plane_rows_mut};
+use super::super::unsafe_impl::{chunks4_mut, cast_pixels, cast_pixels_mut};
+use super::super::Channel;
+use super::super::colorspace::{ColorCMYK, ColorRGB, Separation};
+
+#[derive(Clone)]
+pub struct Cmyk;
+
+impl<C> Format<C> for Cmyk where C: Channel {
+    type Pixel = ColorCMYK<C>;
+
+    fn channel_data_size(width: u32, height: u32) -> usize {
+        4 * width as usize * height as usize
+    }
+
+    /// Black is full black ink over no colour ink.
+    fn init_black(width: u32, height: u32, storage: &mut [C]) {
+        assert!(storage.len() == <Self as Format<C>>::channel_data_size(width, height));
+
+        for px in storage.chunks_mut(4) {
+            px[0] = C::min_value();
+            px[1] = C::min_value();
+            px[2] = C::min_value();
+            px[3] = C::max_value();
+        }
+    }
+
+    #[inline]
+    fn get_pixel(storage: &[C], width: u32, height: u32, x: u32, y: u32) -> Self::Pixel {
+        assert!(x < width, "x < width : {} < {}", x, width);
+        assert!(y < height, "y < height : {} < {}", y, height);
+        let offset = 4 * (x as usize + width as usize * y as usize);
+        let px = &storage[offset..];
+        ColorCMYK::new_cmyk(px[0], px[1], px[2], px[3])
+    }
+
+    #[inline]
+    fn put_pixel(storage: &mut [C], width: u32, _height: u32, x: u32, y: u32, pixel: Self::Pixel) {
+        let offset = 4 * (x as usize + width as usize * y as usize);
+        let px = &mut storage[offset..];
+        px[0] = pixel.c;
+        px[1] = pixel.m;
+        px[2] = pixel.y;
+        px[3] = pixel.k;
+    }
+
+    fn map_in_place<F>(storage: &mut [C], width: u32, height: u32, mut f: F)
+        where
+            F: FnMut(u32, u32, Self::Pixel) -> Self::Pixel
+    {
+        let row_len = 4 * width as usize;
+        if row_len == 0 {
+            return;
+        }
+        let rows = storage[..row_len * height as usize].chunks_mut(row_len);
+        for (y, row) in rows.enumerate() {
+            for (x, (c, m, ye, k)) in chunks4_mut(row).enumerate() {
+                let px = f(x as u32, y as u32, ColorCMYK::new_cmyk(*c, *m, *ye, *k));
+                *c = px.c;
+                *m = px.m;
+                *ye = px.y;
+                *k = px.k;
+            }
+        }
+    }
+
+    fn packed_pixels(storage: &[C]) -> Option<&[Self::Pixel]> {
+        Some(<Self as PackedFormat<C>>::as_pixels(storage))
+    }
+}
+
+impl<C> InterleavedFormat<C> for Cmyk where C: Channel {}
+
+impl<C> PackedFormat<C> for Cmyk where C: Channel {
+    fn as_pixels(storage: &[C]) -> &[Self::Pixel] {
+        unsafe { cast_pixels(storage) }
+    }
+
+    fn as_pixels_mut(storage: &mut [C]) -> &mut [Self::Pixel] {
+        unsafe { cast_pixels_mut(storage) }
+    }
+}
+
+
+/// One full-size plane per ink, in `c, m, y, k` order, like a TIFF with
+/// separate planar configuration.
+#[derive(Clone)]
+pub struct CmykPlanar;
+
+impl<C> Format<C> for CmykPlanar where C: Channel {
+    type Pixel = ColorCMYK<C>;
+
+    fn channel_data_size(width: u32, height: u32) -> usize {
+        4 * width as usize * height as usize
+    }
+
+    /// Black is full black ink over no colour ink.
+    fn init_black(width: u32, height: u32, storage: &mut [C]) {
+        assert!(storage.len() == <Self as Format<C>>::channel_data_size(width, height));
+
+        let plane = width as usize * height as usize;
+        let (inks, black) = storage.split_at_mut(3 * plane);
+        for ch in inks.iter_mut() {
+            *ch = C::min_value();
+        }
+        for ch in black.iter_mut() {
+            *ch = C::max_value();
+        }
+    }
+
+    #[inline]
+    fn get_pixel(storage: &[C], width: u32, height: u32, x: u32, y: u32) -> Self::Pixel {
+        let plane = width as usize * height as usize;
+        let offset = x as usize + width as usize * y as usize;
+        ColorCMYK::new_cmyk(
+            storage[offset],
+            storage[plane + offset],
+            storage[2 * plane + offset],
+            storage[3 * plane + offset])
+    }
+
+    #[inline]
+    fn put_pixel(storage: &mut [C], width: u32, height: u32, x: u32, y: u32, pixel: Self::Pixel) {
+        let plane = width as usize * height as usize;
+        let offset = x as usize + width as usize * y as usize;
+        storage[offset] = pixel.c;
+        storage[plane + offset] = pixel.m;
+        storage[2 * plane + offset] = pixel.y;
+        storage[3 * plane + offset] = pixel.k;
+    }
+}
+
+impl<'a, C> PlanarFormat<'a, C> for CmykPlanar
+    where
+        C: Channel + 'a
+{
+    type Planes = (&'a [C], &'a [C], &'a [C], &'a [C]);
+    type PlanesMut = (&'a mut [C], &'a mut [C], &'a mut [C], &'a mut [C]);
+    type PlaneRows = (slice::Chunks<'a, C>, slice::Chunks<'a, C>, slice::Chunks<'a, C>, slice::Chunks<'a, C>);
+    type PlaneRowsMut = (slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>);
+
+    fn get_planes(data: &'a [C], (w, h): (u32, u32)) -> Self::Planes {
+        let plane = w as usize * h as usize;
+        let (c_plane, rest) = data.split_at(plane);
+        let (m_plane, rest) = rest.split_at(plane);
+        let (y_plane, rest) = rest.split_at(plane);
+        (c_plane, m_plane, y_plane, &rest[..plane])
+    }
+
+    fn get_planes_mut(data: &'a mut [C], (w, h): (u32, u32)) -> Self::PlanesMut {
+        let plane = w as usize * h as usize;
+        let (c_plane, rest) = data.split_at_mut(plane);
+        let (m_plane, rest) = rest.split_at_mut(plane);
+        let (y_plane, rest) = rest.split_at_mut(plane);
+        (c_plane, m_plane, y_plane, &mut rest[..plane])
+    }
+
+    fn get_plane_rows(data: &'a [C], (w, h): (u32, u32)) -> Self::PlaneRows {
+        let (c, m, y, k) = <Self as PlanarFormat<C>>::get_planes(data, (w, h));
+        let w = w as usize;
+        (plane_rows(c, w), plane_rows(m, w), plane_rows(y, w), plane_rows(k, w))
+    }
+
+    fn get_plane_rows_mut(data: &'a mut [C], (w, h): (u32, u32)) -> Self::PlaneRowsMut {
+        let (c, m, y, k) = <Self as PlanarFormat<C>>::get_planes_mut(data, (w, h));
+        let w = w as usize;
+        (plane_rows_mut(c, w), plane_rows_mut(m, w), plane_rows_mut(y, w), plane_rows_mut(k, w))
+    }
+}
+
+/// Separates an RGB surface into a `Cmyk` or `CmykPlanar` surface.
+pub fn separate_cmyk<M, MO, C, S>(input: &Surface<M, C, S>, separation: Separation) -> Surface<MO, C, Box<[C]>>
+    where
+        M: Format<C, Pixel=ColorRGB<C>>,
+        MO: Format<C, Pixel=ColorCMYK<C>>,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    let mut out: Surface<MO, C, Box<[C]>> = Surface::new_black(input.width(), input.height());
+    out.map_in_place(|x, y, _| ColorCMYK::from_rgb(input.get_pixel(x, y), separation));
+    out
+}
+
+/// Writes `surface` as a PAM image with tuple type `CMYK`.  Channels are
+/// written big-endian at their full depth, so only `u8` and `u16` surfaces
+/// can be written.
+pub fn write_cmyk_pam<W, C, S>(surface: &Surface<Cmyk, C, S>, out: &mut W) -> io::Result<()>
+    where
+        W: Write,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    let maxval = match C::max_depth() {
+        Some(depth) if depth <= 0xFFFF => depth,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "PAM samples are at most 16 bits")),
+    };
+    write!(out, "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL {}\nTUPLTYPE CMYK\nENDHDR\n",
+        surface.width(), surface.height(), maxval)?;
+
+    let len = <Cmyk as Format<C>>::channel_data_size(surface.width(), surface.height());
+    let samples = &surface.as_storage()[..len];
+    if maxval <= 0xFF {
+        let bytes: Vec<u8> = samples.iter().map(|c| c.to_u8().unwrap()).collect();
+        out.write_all(&bytes)
+    } else {
+        let mut bytes = Vec::with_capacity(2 * samples.len());
+        for c in samples.iter() {
+            let sample = c.to_u16().unwrap();
+            bytes.push((sample >> 8) as u8);
+            bytes.push(sample as u8);
+        }
+        out.write_all(&bytes)
+    }
+}
+
+#[test]
+fn cmyk_formats() {
+    use super::Rgb;
+
+    let black: Surface<Cmyk, u8, Vec<u8>> = Surface::new_black(2, 1);
+    assert_eq!(&black.as_storage()[..], &[0, 0, 0, 255, 0, 0, 0, 255]);
+    let black: Surface<CmykPlanar, u8, Vec<u8>> = Surface::new_black(2, 1);
+    assert_eq!(&black.as_storage()[..], &[0, 0, 0, 0, 0, 0, 255, 255]);
+
+    let rgb: Surface<Rgb, u8, Vec<u8>> = Surface::new(2, 1, vec![255, 0, 0, 64, 64, 64]);
+    let naive: Surface<Cmyk, u8, _> = separate_cmyk(&rgb, Separation::Naive);
+    assert_eq!(&naive.as_storage()[..], &[0, 255, 255, 0, 191, 191, 191, 0]);
+    let ucr: Surface<CmykPlanar, u8, _> = separate_cmyk(&rgb, Separation::UnderColorRemoval(1.0));
+    assert_eq!(&ucr.as_storage()[..], &[0, 0, 255, 0, 255, 0, 0, 191]);
+    let back: Surface<Rgb, u8, _> = ucr.convert_pixels();
+    assert_eq!(&back.as_storage()[..], &rgb.as_storage()[..]);
+
+    let mut pam = Vec::new();
+    write_cmyk_pam(&naive, &mut pam).unwrap();
+    let header = "P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE CMYK\nENDHDR\n";
+    assert_eq!(&pam[..header.len()], header.as_bytes());
+    assert_eq!(&pam[header.len()..], &naive.as_storage()[..]);
+
+    let deep: Surface<Cmyk, u16, Vec<u16>> = Surface::new(1, 1, vec![0x1234, 0, 0xFFFF, 1]);
+    let mut pam = Vec::new();
+    write_cmyk_pam(&deep, &mut pam).unwrap();
+    assert!(pam.ends_with(b"MAXVAL 65535\nTUPLTYPE CMYK\nENDHDR\n\x12\x34\x00\x00\xff\xff\x00\x01"));
+
+    // Storage past the image is not written.
+    let spare: Surface<Cmyk, u8, Vec<u8>> = Surface::new(1, 1, vec![1, 2, 3, 4, 5, 6]);
+    let mut pam = Vec::new();
+    write_cmyk_pam(&spare, &mut pam).unwrap();
+    assert!(pam.ends_with(b"ENDHDR\n\x01\x02\x03\x04"));
+
+    let float: Surface<Cmyk, f64, Vec<f64>> = Surface::new_black(1, 1);
+    assert!(write_cmyk_pam(&float, &mut Vec::new()).is_err());
+}
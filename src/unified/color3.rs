@@ -0,0 +1,113 @@
+//! Packed three-channel formats for the HSV, HSL, XYZ and Lab pixel types.
+//! They share `Rgb`'s layout and differ only in the pixel they hold.
+
+use super::{Format, InterleavedFormat, PackedFormat};
+use super::super::unsafe_impl::{chunks3_mut, cast_pixels, cast_pixels_mut};
+use super::super::Channel;
+use super::super::colorspace::{ColorHSV, ColorHSL, ColorXYZ, ColorLab};
+
+macro_rules! packed3_format {
+    ($(#[$attr:meta])* $format:ident, $pixel:ident, $a:ident, $b:ident, $c:ident) => {
+        $(#[$attr])*
+        #[derive(Clone)]
+        pub struct $format;
+
+        impl<C> Format<C> for $format where C: Channel {
+            type Pixel = $pixel<C>;
+
+            fn channel_data_size(width: u32, height: u32) -> usize {
+                3 * width as usize * height as usize
+            }
+
+            fn init_black(width: u32, height: u32, storage: &mut [C]) {
+                assert!(storage.len() == <Self as Format<C>>::channel_data_size(width, height));
+
+                let ch_min = <C as Channel>::min_value();
+                for ch in storage.iter_mut() {
+                    *ch = ch_min;
+                }
+            }
+
+            #[inline]
+            fn get_pixel(storage: &[C], width: u32, height: u32, x: u32, y: u32) -> Self::Pixel {
+                assert!(x < width, "x < width : {} < {}", x, width);
+                assert!(y < height, "y < height : {} < {}", y, height);
+                let offset = 3 * (x as usize + width as usize * y as usize);
+                let px = &storage[offset..];
+                $pixel { $a: px[0], $b: px[1], $c: px[2] }
+            }
+
+            #[inline]
+            fn put_pixel(storage: &mut [C], width: u32, _height: u32, x: u32, y: u32, pixel: Self::Pixel) {
+                let offset = 3 * (x as usize + width as usize * y as usize);
+                let px = &mut storage[offset..];
+                px[0] = pixel.$a;
+                px[1] = pixel.$b;
+                px[2] = pixel.$c;
+            }
+
+            fn map_in_place<F>(storage: &mut [C], width: u32, height: u32, mut f: F)
+                where
+                    F: FnMut(u32, u32, Self::Pixel) -> Self::Pixel
+            {
+                let row_len = 3 * width as usize;
+                if row_len == 0 {
+                    return;
+                }
+                let rows = storage[..row_len * height as usize].chunks_mut(row_len);
+                for (y, row) in rows.enumerate() {
+                    for (x, (a, b, c)) in chunks3_mut(row).enumerate() {
+                        let px = f(x as u32, y as u32, $pixel { $a: *a, $b: *b, $c: *c });
+                        *a = px.$a;
+                        *b = px.$b;
+                        *c = px.$c;
+                    }
+                }
+            }
+
+            fn packed_pixels(storage: &[C]) -> Option<&[Self::Pixel]> {
+                Some(<Self as PackedFormat<C>>::as_pixels(storage))
+            }
+        }
+
+        impl<C> InterleavedFormat<C> for $format where C: Channel {}
+
+        impl<C> PackedFormat<C> for $format where C: Channel {
+            fn as_pixels(storage: &[C]) -> &[Self::Pixel] {
+                unsafe { cast_pixels(storage) }
+            }
+
+            fn as_pixels_mut(storage: &mut [C]) -> &mut [Self::Pixel] {
+                unsafe { cast_pixels_mut(storage) }
+            }
+        }
+    };
+}
+
+packed3_format!(
+    /// Packed `ColorHSV` pixels.
+    Hsv, ColorHSV, h, s, v);
+packed3_format!(
+    /// Packed `ColorHSL` pixels.
+    Hsl, ColorHSL, h, s, l);
+packed3_format!(
+    /// Packed `ColorXYZ` pixels, normally with `f64` channels.
+    Xyz, ColorXYZ, x, y, z);
+packed3_format!(
+    /// Packed `ColorLab` pixels, normally with `f64` channels.
+    Lab, ColorLab, l, a, b);
+
+#[test]
+fn convert_pixels_between_formats() {
+    use super::{Rgb, Surface};
+
+    let rgb: Surface<Rgb, u8, Vec<u8>> = Surface::new(2, 1, vec![255, 0, 0, 12, 200, 99]);
+
+    let lab: Surface<Lab, f64, _> = rgb.convert_pixels();
+    assert!((lab.get_pixel(0, 0).l - 53.24).abs() < 0.01);
+    let back: Surface<Rgb, u8, _> = lab.convert_pixels();
+    assert_eq!(&back.as_storage()[..], &rgb.as_storage()[..]);
+
+    let hsv: Surface<Hsv, u8, _> = rgb.convert_pixels();
+    assert_eq!(&hsv.as_storage()[..3], &[0, 255, 255]);
+}
@@ -0,0 +1,51 @@
+use super::{Format, InterleavedFormat, PackedFormat};
+use super::super::unsafe_impl::{cast_pixels, cast_pixels_mut};
+use super::super::colorspace::ColorL;
+
+/// One palette index per pixel.  The palette lives outside the surface,
+/// see `quantize::IndexedImage`; the pixel's `l` is the index.
+#[derive(Clone)]
+pub struct Indexed8;
+
+impl Format<u8> for Indexed8 {
+    type Pixel = ColorL<u8>;
+
+    fn channel_data_size(width: u32, height: u32) -> usize {
+        width as usize * height as usize
+    }
+
+    /// Every pixel takes palette entry 0.
+    fn init_black(width: u32, height: u32, storage: &mut [u8]) {
+        assert!(storage.len() == <Self as Format<u8>>::channel_data_size(width, height));
+
+        for index in storage.iter_mut() {
+            *index = 0;
+        }
+    }
+
+    #[inline]
+    fn get_pixel(storage: &[u8], width: u32, _height: u32, x: u32, y: u32) -> Self::Pixel {
+        ColorL::new_l(storage[x as usize + width as usize * y as usize])
+    }
+
+    #[inline]
+    fn put_pixel(storage: &mut [u8], width: u32, _height: u32, x: u32, y: u32, pixel: Self::Pixel) {
+        storage[x as usize + width as usize * y as usize] = pixel.l;
+    }
+
+    fn packed_pixels(storage: &[u8]) -> Option<&[Self::Pixel]> {
+        Some(<Self as PackedFormat<u8>>::as_pixels(storage))
+    }
+}
+
+impl InterleavedFormat<u8> for Indexed8 {}
+
+impl PackedFormat<u8> for Indexed8 {
+    fn as_pixels(storage: &[u8]) -> &[Self::Pixel] {
+        unsafe { cast_pixels(storage) }
+    }
+
+    fn as_pixels_mut(storage: &mut [u8]) -> &mut [Self::Pixel] {
+        unsafe { cast_pixels_mut(storage) }
+    }
+}
@@ -1,5 +1,6 @@
 use super::super::{Channel, Pixel};
-use super::Format;
+use super::{Format, InterleavedFormat, PackedFormat, LumaFormat, ToneFormat};
+use super::super::unsafe_impl::{cast_pixels, cast_pixels_mut};
 use super::super::colorspace::ColorL;
 
 #[derive(Clone)]
@@ -34,4 +35,49 @@ impl<C> Format<C> for Luma where C: Channel {
         let offset_y = x + width * y;
         storage[offset_y as usize] = pixel.l;
     }
+
+    fn map_in_place<F>(storage: &mut [C], width: u32, height: u32, mut f: F)
+        where
+            F: FnMut(u32, u32, Self::Pixel) -> Self::Pixel
+    {
+        if width == 0 {
+            return;
+        }
+        let rows = storage[..width as usize * height as usize].chunks_mut(width as usize);
+        for (y, row) in rows.enumerate() {
+            for (x, l) in row.iter_mut().enumerate() {
+                *l = f(x as u32, y as u32, ColorL::new_l(*l)).l;
+            }
+        }
+    }
+
+    fn packed_pixels(storage: &[C]) -> Option<&[Self::Pixel]> {
+        Some(<Self as PackedFormat<C>>::as_pixels(storage))
+    }
+}
+
+impl<C> InterleavedFormat<C> for Luma where C: Channel {}
+
+impl<C> LumaFormat<C> for Luma where C: Channel {
+    fn with_luma_plane_mut<F>(storage: &mut [C], width: u32, height: u32, f: F)
+        where
+            F: FnOnce(&mut [C])
+    {
+        f(&mut storage[..width as usize * height as usize])
+    }
+}
+
+impl<C> ToneFormat<C> for Luma where C: Channel {
+    const TONE_CHANNELS: usize = 1;
+    const LUMA_PLANE: bool = true;
+}
+
+impl<C> PackedFormat<C> for Luma where C: Channel {
+    fn as_pixels(storage: &[C]) -> &[Self::Pixel] {
+        unsafe { cast_pixels(storage) }
+    }
+
+    fn as_pixels_mut(storage: &mut [C]) -> &mut [Self::Pixel] {
+        unsafe { cast_pixels_mut(storage) }
+    }
 }
@@ -1,4 +1,6 @@
+use std::cmp;
 use std::mem;
+use std::slice;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
@@ -11,12 +13,18 @@ use super::colorspace::{
     ColorL,
 };
 use super::Channel;
+use super::error::{Error, checked_channel_count};
+#[cfg(feature = "parallel")]
+use super::parallel::{self, Partition};
 
 mod yuv420;
 mod yuv422;
 mod yuv444;
 mod luma;
 mod rgba;
+mod color3;
+mod cmyk;
+mod indexed;
 
 pub use self::yuv420::{Yuv420p}; // Yuv420
 pub use self::yuv422::{Yuv422, Yuv422p};
@@ -24,6 +32,9 @@ pub use self::yuv444::{Yuv444}; // Yuv444p
 
 pub use self::luma::{Luma};
 pub use self::rgba::{Rgb, RgbPlanar, Rgba, RgbaPlanar};
+pub use self::color3::{Hsv, Hsl, Xyz, Lab};
+pub use self::indexed::Indexed8;
+pub use self::cmyk::{Cmyk, CmykPlanar, separate_cmyk, write_cmyk_pam};
 
 pub trait Format<C>
     where
@@ -35,11 +46,91 @@ pub trait Format<C>
 
     fn channel_data_size(width: u32, height: u32) -> usize;
 
+    /// Like `channel_data_size`, but rejects dimensions the format cannot
+    /// represent instead of producing a bogus size.
+    ///
+    /// The default suits formats without subsampling or padding, which
+    /// store `channel_data_size(1, 1)` channels per pixel; other formats
+    /// override it.
+    fn try_channel_data_size(width: u32, height: u32) -> Result<usize, Error> {
+        checked_channel_count(width, height, <Self as Format<C>>::channel_data_size(1, 1))
+    }
+
     fn init_black(width: u32, height: u32, storage: &mut [C]);
 
     fn get_pixel(storage: &[C], width: u32, height: u32, x: u32, y: u32) -> Self::Pixel;
 
     fn put_pixel(holder: &mut [C], width: u32, height: u32, x: u32, y: u32, pixel: <Self as Format<C>>::Pixel);
+
+    /// Replaces every pixel with `f(x, y, pixel)`, in raster order.
+    ///
+    /// For subsampled formats the shared chroma samples end up holding the
+    /// value written for the last pixel that covers them.
+    fn map_in_place<F>(storage: &mut [C], width: u32, height: u32, mut f: F)
+        where
+            F: FnMut(u32, u32, Self::Pixel) -> Self::Pixel
+    {
+        for y in 0..height {
+            for x in 0..width {
+                let px = <Self as Format<C>>::get_pixel(storage, width, height, x, y);
+                <Self as Format<C>>::put_pixel(storage, width, height, x, y, f(x, y, px));
+            }
+        }
+    }
+
+    /// `storage` as a slice of pixels, for formats that are also
+    /// `PackedFormat`s.  Lets code generic over `Format` skip the
+    /// per-pixel `get_pixel` path.
+    fn packed_pixels(_storage: &[C]) -> Option<&[Self::Pixel]> {
+        None
+    }
+}
+
+/// A format keeping all channels in a single plane, so that each row is a
+/// contiguous run of `channel_data_size(width, 1)` channels.
+pub trait InterleavedFormat<C>: Format<C>
+    where
+        C: Channel
+{
+}
+
+/// A format whose storage is a plain array of pixels, one `Self::Pixel`
+/// per pixel with no subsampling or padding.
+pub trait PackedFormat<C>: InterleavedFormat<C>
+    where
+        C: Channel
+{
+    fn as_pixels(storage: &[C]) -> &[Self::Pixel];
+
+    fn as_pixels_mut(storage: &mut [C]) -> &mut [Self::Pixel];
+}
+
+/// A format with a full-resolution luma channel: the grey of `Luma` or the
+/// Y of the YUV formats.
+pub trait LumaFormat<C>: Format<C>
+    where
+        C: Channel
+{
+    /// Calls `f` with the luma channel as a `width`x`height` plane.  Formats
+    /// interleaving luma with chroma copy it out and back in.
+    fn with_luma_plane_mut<F>(storage: &mut [C], width: u32, height: u32, f: F)
+        where
+            F: FnOnce(&mut [C]);
+}
+
+/// A format with tone channels for level, curve and sharpening operations
+/// to change: luma for `Luma` and YUV, colour for RGB.  Chroma and alpha
+/// are left alone.
+pub trait ToneFormat<C>: Format<C>
+    where
+        C: Channel
+{
+    /// The number of leading channels of each pixel that hold tone.
+    const TONE_CHANNELS: usize;
+
+    /// Whether storage starts with a full-size plane of the only tone
+    /// channel, which can then be changed in place.
+    const LUMA_PLANE: bool = false;
 }
 
 pub trait PlanarFormat<'a, C>: Format<C>
@@ -48,10 +139,28 @@ pub trait PlanarFormat<'a, C>: Format<C>
 {
     type Planes: 'a;
     type PlanesMut: 'a;
+    type PlaneRows: 'a;
+    type PlaneRowsMut: 'a;
 
     fn get_planes(data: &'a [C], wh: (u32, u32)) -> Self::Planes;
 
     fn get_planes_mut(data: &'a mut [C], wh: (u32, u32)) -> Self::PlanesMut;
+
+    /// Row iterators over each plane, in the same order as `get_planes`.
+    fn get_plane_rows(data: &'a [C], wh: (u32, u32)) -> Self::PlaneRows;
+
+    fn get_plane_rows_mut(data: &'a mut [C], wh: (u32, u32)) -> Self::PlaneRowsMut;
+}
+
+#[inline]
+fn plane_rows<C>(plane: &[C], row_len: usize) -> slice::Chunks<'_, C> {
+    // An empty plane has no rows, whatever its nominal width.
+    plane.chunks(cmp::max(row_len, 1))
+}
+
+#[inline]
+fn plane_rows_mut<C>(plane: &mut [C], row_len: usize) -> slice::ChunksMut<'_, C> {
+    plane.chunks_mut(cmp::max(row_len, 1))
 }
 
 #[derive(Clone)]
@@ -93,6 +202,19 @@ impl<M, C, S> Surface<M, C, S>
         }
     }
 
+    /// Wraps `storage`, checking that the dimensions are valid for the
+    /// format and that the storage is exactly the required size.
+    pub fn try_new(width: u32, height: u32, storage: S) -> Result<Surface<M, C, S>, Error> {
+        let expected = <M as Format<C>>::try_channel_data_size(width, height)?;
+        if storage.len() != expected {
+            return Err(Error::SizeMismatch {
+                expected,
+                actual: storage.len(),
+            });
+        }
+        Ok(Surface::new(width, height, storage))
+    }
+
     pub fn width(&self) -> u32 {
         self.width
     }
@@ -105,6 +227,11 @@ impl<M, C, S> Surface<M, C, S>
         Pixels::new(self)
     }
 
+    /// Iterates over `(x, y, pixel)` in raster order.
+    pub fn enumerate_pixels(&self) -> EnumeratePixels<'_, M, C, S> {
+        EnumeratePixels { inner: Pixels::new(self) }
+    }
+
     pub fn get_pixel(&self, x: u32, y: u32) -> M::Pixel {
         <M as Format<C>>::get_pixel(&self.storage, self.width, self.height, x, y)
     }
@@ -113,6 +240,18 @@ impl<M, C, S> Surface<M, C, S>
         Surface::new(self.width, self.height, copy_to_boxed_slice(&self.storage))
     }
 
+    /// Converts every pixel into another format, e.g. `Rgb` into `Lab`.
+    pub fn convert_pixels<MO, CO>(&self) -> Surface<MO, CO, Box<[CO]>>
+        where
+            MO: Format<CO>,
+            CO: Channel,
+            MO::Pixel: From<M::Pixel>,
+    {
+        let mut out: Surface<MO, CO, Box<[CO]>> = Surface::new_black(self.width, self.height);
+        out.map_in_place(|x, y, _| MO::Pixel::from(self.get_pixel(x, y)));
+        out
+    }
+
     pub fn into_storage(self) -> S {
         self.storage
     }
@@ -139,6 +278,20 @@ impl<M, C, S> Surface<M, C, S>
     // }
 }
 
+/// The storage length of a `width`x`height` `M` surface.  Empty
+/// dimensions give an empty surface; sizes that overflow panic.
+pub fn storage_len<M, C>(width: u32, height: u32) -> usize
+    where
+        M: Format<C>,
+        C: Channel,
+{
+    match <M as Format<C>>::try_channel_data_size(width, height) {
+        Ok(length) => length,
+        Err(err @ Error::Overflow { .. }) => panic!("{}", err),
+        Err(_) => <M as Format<C>>::channel_data_size(width, height),
+    }
+}
+
 pub trait StorageAlloc {
     fn alloc(length: usize) -> Self;
 }
@@ -172,33 +325,24 @@ where
     SO: Deref<Target=[C]> + DerefMut + StorageAlloc
 {
     fn from(s: Surface<Yuv422, C, SI>) -> Surface<Yuv422p, C, SO> {
-        let mut into = Surface::new_black(s.width(), s.height());
-        let mut src = s.as_storage().iter();
+        let (width, height) = (s.width(), s.height());
+        let mut into = Surface::new_black(width, height);
+        let width = width as usize;
+        let chroma_width = yuv422::chroma_width(s.width());
 
         let (yp, up, vp): (&mut [C], &mut [C], &mut [C]) = into.get_planes_mut();
-        let mut yp_iter = yp.iter_mut();
-        let mut up_iter = up.iter_mut();
-        let mut vp_iter = vp.iter_mut();
-
-        let mut subpixel_ctr = 0;
-        loop {
-            if let (Some(ys), Some(yd)) = (src.next(), yp_iter.next()) {
-                *yd = *ys;
-                subpixel_ctr += 1;
-            }
-            if let (Some(us), Some(ud)) = (src.next(), up_iter.next()) {
-                *ud = *us;
-                subpixel_ctr += 1;
-            }
-            if let (Some(ys), Some(yd)) = (src.next(), yp_iter.next()) {
-                *yd = *ys;
-                subpixel_ctr += 1;
-            }
-            if let (Some(vs), Some(vd)) = (src.next(), vp_iter.next()) {
-                *vd = *vs;
-                subpixel_ctr += 1;
-            } else {
-                break;
+        let rows = s.as_storage().chunks(4 * chroma_width)
+            .zip(yp.chunks_mut(width))
+            .zip(up.chunks_mut(chroma_width).zip(vp.chunks_mut(chroma_width)));
+
+        for ((src, yd), (ud, vd)) in rows {
+            for (idx, group) in src.chunks(4).enumerate() {
+                yd[2 * idx] = group[0];
+                if 2 * idx + 1 < width {
+                    yd[2 * idx + 1] = group[2];
+                }
+                ud[idx] = group[1];
+                vd[idx] = group[3];
             }
         }
 
@@ -212,9 +356,74 @@ impl<M, C, S> Surface<M, C, S>
         C: Channel,
         S: Deref<Target=[C]> + DerefMut,
 {
+    /// The underlying channels.  Unlike the storage itself, a slice cannot
+    /// be resized, so the surface stays well-formed.
+    pub fn as_storage_mut(&mut self) -> &mut [C] {
+        &mut self.storage
+    }
+
     pub fn put_pixel(&mut self, x: u32, y: u32, val: M::Pixel) {
         <M as Format<C>>::put_pixel(&mut self.storage, self.width, self.height, x, y, val)
     }
+
+    /// Replaces every pixel with `f(x, y, pixel)`.
+    pub fn map_in_place<F>(&mut self, f: F)
+        where
+            F: FnMut(u32, u32, M::Pixel) -> M::Pixel
+    {
+        <M as Format<C>>::map_in_place(&mut self.storage, self.width, self.height, f)
+    }
+}
+
+impl<M, C, S> Surface<M, C, S>
+    where
+        M: InterleavedFormat<C>,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    /// Iterates over the channels of each row.
+    pub fn rows(&self) -> slice::Chunks<'_, C> {
+        let row_len = <M as Format<C>>::channel_data_size(self.width, 1);
+        plane_rows(&self.storage, row_len)
+    }
+}
+
+impl<M, C, S> Surface<M, C, S>
+    where
+        M: InterleavedFormat<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    pub fn rows_mut(&mut self) -> slice::ChunksMut<'_, C> {
+        let row_len = <M as Format<C>>::channel_data_size(self.width, 1);
+        plane_rows_mut(&mut self.storage, row_len)
+    }
+}
+
+impl<M, C, S> Surface<M, C, S>
+    where
+        M: PackedFormat<C>,
+        C: Channel,
+        S: Deref<Target=[C]>,
+{
+    pub fn pixels(&self) -> &[M::Pixel] {
+        <M as PackedFormat<C>>::as_pixels(&self.storage)
+    }
+}
+
+impl<M, C, S> Surface<M, C, S>
+    where
+        M: PackedFormat<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    pub fn pixels_mut(&mut self) -> &mut [M::Pixel] {
+        <M as PackedFormat<C>>::as_pixels_mut(&mut self.storage)
+    }
+
+    pub fn iter_pixels_mut(&mut self) -> slice::IterMut<'_, M::Pixel> {
+        self.pixels_mut().iter_mut()
+    }
 }
 
 impl<M, S> Surface<M, u8, S>
@@ -248,6 +457,11 @@ impl<'a, M, C, S> Surface<M, C, S>
         <M as PlanarFormat<C>>::get_planes(&self.storage, size)
     }
 
+    pub fn plane_rows(&'a self) -> <M as PlanarFormat<C>>::PlaneRows {
+        let size = (self.width, self.height);
+        <M as PlanarFormat<C>>::get_plane_rows(&self.storage, size)
+    }
+
 }
 
 impl<'a, M, C, S> Surface<M, C, S>
@@ -260,6 +474,26 @@ impl<'a, M, C, S> Surface<M, C, S>
         let size = (self.width, self.height);
         <M as PlanarFormat<C>>::get_planes_mut(&mut self.storage, size)
     }
+
+    pub fn plane_rows_mut(&'a mut self) -> <M as PlanarFormat<C>>::PlaneRowsMut {
+        let size = (self.width, self.height);
+        <M as PlanarFormat<C>>::get_plane_rows_mut(&mut self.storage, size)
+    }
+}
+
+impl<M, C, S> Surface<M, C, S>
+    where
+        M: LumaFormat<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + DerefMut,
+{
+    /// Calls `f` with the luma plane, leaving chroma untouched.
+    pub fn with_luma_plane_mut<F>(&mut self, f: F)
+        where
+            F: FnOnce(&mut [C])
+    {
+        <M as LumaFormat<C>>::with_luma_plane_mut(&mut self.storage, self.width, self.height, f)
+    }
 }
 
 impl<M, C, S> Surface<M, C, S>
@@ -268,8 +502,11 @@ impl<M, C, S> Surface<M, C, S>
         C: Channel,
         S: Deref<Target=[C]> + DerefMut + StorageAlloc
 {
+    /// A black surface in newly allocated storage.  Panics if the storage
+    /// size overflows a `usize`; `try_new_black` reports that, and empty
+    /// dimensions, as an `Error` instead.
     pub fn new_black(width: u32, height: u32) -> Surface<M, C, S> {
-        let length = <M as Format<C>>::channel_data_size(width, height);
+        let length = storage_len::<M, C>(width, height);
         let mut storage = <S as StorageAlloc>::alloc(length);
         <M as Format<C>>::init_black(width, height, &mut storage);
 
@@ -281,6 +518,11 @@ impl<M, C, S> Surface<M, C, S>
             _channel_marker: PhantomData,
         }
     }
+
+    pub fn try_new_black(width: u32, height: u32) -> Result<Surface<M, C, S>, Error> {
+        <M as Format<C>>::try_channel_data_size(width, height)?;
+        Ok(Surface::new_black(width, height))
+    }
 }
 
 
@@ -315,6 +557,26 @@ pub fn extract_luma<M, C, S>(input: &Surface<M, C, S>)
     out
 }
 
+/// Parallel counterpart of `extract_luma`, producing identical output.
+#[cfg(feature = "parallel")]
+pub fn extract_luma_par<M, C, S>(input: &Surface<M, C, S>, partition: Partition)
+-> Surface<Luma, C, Box<[C]>>
+    where
+        M: Format<C> + Sync + 'static,
+        C: Channel + Send + Sync,
+        S: Deref<Target=[C]> + Sync,
+{
+    use std::any::TypeId;
+
+    if TypeId::of::<M>() == TypeId::of::<Yuv420p>() {
+        return extract_luma(input);
+    }
+
+    let mut out: Surface<Luma, C, Box<[C]>> = Surface::new_black(input.width, input.height);
+    parallel::fill_with(&mut out, partition, |x, y| input.get_pixel(x, y).luma());
+    out
+}
+
 
 // impl<C, S> Surface<Yuv420p, C, S>
 //     where
@@ -394,7 +656,50 @@ impl<S> Surface<Luma, u8, S>
     }
 }
 
-// TODO: bound-check elision
+#[cfg(feature = "parallel")]
+impl<S> Surface<Luma, u8, S>
+    where
+        S: Deref<Target=[u8]> + Sync,
+{
+    /// Parallel counterpart of `run_luma8_kernel_3x3`, producing identical
+    /// output.  Border pixels of `output` are left untouched.
+    pub fn run_luma8_kernel_3x3_par<S2>(
+        &self,
+        kernel: fn(pixels: &[u8; 9]) -> u8,
+        output: &mut Surface<Luma, u8, S2>,
+        partition: Partition,
+    )
+        where
+            S2: Deref<Target=[u8]> + DerefMut
+    {
+        use std::mem::transmute_copy;
+
+        assert_eq!(self.width, output.width);
+        assert_eq!(self.height, output.height);
+
+        let (width, height) = (self.width, self.height);
+        parallel::for_each_region_mut(output, partition, |region| {
+            let (x0, y0) = region.origin();
+            let mut data_pix: [<Luma as Format<u8>>::Pixel; 9] = [Pixel::black(); 9];
+            for (dy, row) in region.rows_mut().enumerate() {
+                let y = y0 + dy as u32;
+                if y == 0 || height <= y + 1 {
+                    continue;
+                }
+                for (dx, out) in row.iter_mut().enumerate() {
+                    let x = x0 + dx as u32;
+                    if x == 0 || width <= x + 1 {
+                        continue;
+                    }
+                    surf_3x3_get(self, &mut data_pix, x, y);
+                    let data: [u8; 9] = unsafe { transmute_copy(&data_pix) };
+                    *out = kernel(&data);
+                }
+            }
+        });
+    }
+}
+
 pub struct Pixels<'a, M, C, S>
     where
         M: Format<C> + 'a,
@@ -402,6 +707,7 @@ pub struct Pixels<'a, M, C, S>
         S: Deref<Target=[C]> + 'a,
 {
     surface: &'a Surface<M, C, S>,
+    packed: Option<slice::Iter<'a, M::Pixel>>,
     x_pos: u32,
     y_pos: u32,
 }
@@ -413,8 +719,12 @@ impl<'a, M, C, S> Pixels<'a, M, C, S>
         S: Deref<Target=[C]>,
 {
     fn new(surface: &Surface<M, C, S>) -> Pixels<M, C, S> {
+        let pixels = surface.width as usize * surface.height as usize;
+        let packed = <M as Format<C>>::packed_pixels(&surface.storage)
+            .map(|packed| packed[..pixels].iter());
         Pixels {
             surface: surface,
+            packed: packed,
             x_pos: 0,
             y_pos: 0,
         }
@@ -430,11 +740,14 @@ impl<'a, M, C, S> Iterator for Pixels<'a, M, C, S>
     type Item = M::Pixel;
 
     fn next(&mut self) -> Option<M::Pixel> {
-        if self.surface.height <= self.y_pos {
+        if self.surface.height <= self.y_pos || self.surface.width == 0 {
             return None;
         }
     
-        let px = self.surface.get_pixel(self.x_pos, self.y_pos);
+        let px = match self.packed {
+            Some(ref mut packed) => *packed.next().unwrap(),
+            None => self.surface.get_pixel(self.x_pos, self.y_pos),
+        };
         self.x_pos += 1;
 
         if self.surface.width <= self.x_pos {
@@ -446,6 +759,29 @@ impl<'a, M, C, S> Iterator for Pixels<'a, M, C, S>
     }
 }
 
+pub struct EnumeratePixels<'a, M, C, S>
+    where
+        M: Format<C> + 'a,
+        C: Channel + 'a,
+        S: Deref<Target=[C]> + 'a,
+{
+    inner: Pixels<'a, M, C, S>,
+}
+
+impl<'a, M, C, S> Iterator for EnumeratePixels<'a, M, C, S>
+    where
+        M: Format<C>,
+        C: Channel,
+        S: Deref<Target=[C]> + 'a,
+{
+    type Item = (u32, u32, M::Pixel);
+
+    fn next(&mut self) -> Option<(u32, u32, M::Pixel)> {
+        let (x, y) = (self.inner.x_pos, self.inner.y_pos);
+        self.inner.next().map(|px| (x, y, px))
+    }
+}
+
 // impl<C> Surface<RgbHolder<C>, Rgb, C> where C: Channel {
 //     pub fn extract_luma(&self) -> Surface<[Box<[C]>; 1], Luma, C> {
 //         Surface::new(self.width, self.height, self.planes.get_y())
@@ -485,3 +821,119 @@ fn surf_3x3_get<M, C, S>(
 fn copy_to_boxed_slice<C>(data: &[C]) -> Box<[C]> where C: Copy {
     Into::<Vec<C>>::into(data).into_boxed_slice()
 }
+
+#[test]
+fn try_new_checks_storage_size() {
+    let ok: Result<Surface<Rgb, u8, Vec<u8>>, _> = Surface::try_new(4, 2, vec![0; 24]);
+    assert!(ok.is_ok());
+
+    let short: Result<Surface<Rgb, u8, Vec<u8>>, _> = Surface::try_new(4, 2, vec![0; 23]);
+    assert_eq!(short.err(), Some(Error::SizeMismatch { expected: 24, actual: 23 }));
+}
+
+#[test]
+fn try_new_rejects_bad_dimensions() {
+    let empty: Result<Surface<Luma, u8, Vec<u8>>, _> = Surface::try_new(0, 4, vec![]);
+    assert_eq!(empty.err(), Some(Error::ZeroDimensions { width: 0, height: 4 }));
+
+    let odd: Result<Surface<Yuv422, u8, Vec<u8>>, _> = Surface::try_new(3, 2, vec![0; 12]);
+    assert_eq!(odd.err(), Some(Error::SizeMismatch { expected: 16, actual: 12 }));
+
+    let max = u32::max_value();
+    let huge = <Rgba as Format<u8>>::try_channel_data_size(max, max);
+    assert_eq!(huge, Err(Error::Overflow { width: max, height: max }));
+}
+
+#[test]
+fn try_new_black_yuv422() {
+    let surf: Surface<Yuv422, u8, Vec<u8>> = Surface::try_new_black(4, 2).unwrap();
+    assert_eq!(surf.as_storage().len(), 16);
+    assert_eq!(&surf.as_storage()[..4], &[0, 128, 0, 128]);
+}
+
+#[test]
+fn map_in_place_visits_in_raster_order() {
+    use super::ColorRGB;
+
+    let mut surf: Surface<Rgb, u8, Vec<u8>> = Surface::new_black(3, 2);
+    surf.map_in_place(|x, y, _| ColorRGB::new_rgb(x as u8, y as u8, 7));
+    assert_eq!(&surf.as_storage()[..], &[
+        0, 0, 7, 1, 0, 7, 2, 0, 7,
+        0, 1, 7, 1, 1, 7, 2, 1, 7,
+    ][..]);
+
+    let mut planar: Surface<RgbPlanar, u8, Vec<u8>> = Surface::new_black(3, 2);
+    planar.map_in_place(|x, y, _| ColorRGB::new_rgb(x as u8, y as u8, 7));
+    for (x, y, px) in planar.enumerate_pixels() {
+        let packed = surf.get_pixel(x, y);
+        assert_eq!((px.r, px.g, px.b), (packed.r, packed.g, packed.b));
+    }
+}
+
+#[test]
+fn enumerate_pixels_yields_coordinates() {
+    let surf: Surface<Luma, u8, Vec<u8>> = Surface::new(2, 2, vec![1, 2, 3, 4]);
+    let all: Vec<_> = surf.enumerate_pixels().map(|(x, y, px)| (x, y, px.l)).collect();
+    assert_eq!(all, vec![(0, 0, 1), (1, 0, 2), (0, 1, 3), (1, 1, 4)]);
+}
+
+#[test]
+fn iter_pixels_packed_and_planar_agree() {
+    let data: Vec<u8> = (0..15).collect();
+    let mut spare = data.clone();
+    spare.extend_from_slice(&[99; 3]);
+    let packed: Surface<Rgb, u8, Vec<u8>> = Surface::new(5, 1, spare);
+    let mut planar: Surface<RgbPlanar, u8, Vec<u8>> = Surface::new_black(5, 1);
+    for (x, px) in packed.iter_pixels().enumerate() {
+        planar.put_pixel(x as u32, 0, px);
+    }
+    let from_packed: Vec<_> = packed.iter_pixels().map(|px| (px.r, px.g, px.b)).collect();
+    let from_planar: Vec<_> = planar.iter_pixels().map(|px| (px.r, px.g, px.b)).collect();
+    assert_eq!(from_packed.len(), 5);
+    assert_eq!(from_packed, from_planar);
+    assert_eq!(from_packed[4], (12, 13, 14));
+}
+
+#[test]
+fn iter_pixels_mut_packed() {
+    let mut surf: Surface<Rgba, u8, Vec<u8>> = Surface::new_black(2, 2);
+    for px in surf.iter_pixels_mut() {
+        px.g = 9;
+    }
+    assert_eq!(surf.pixels().len(), 4);
+    assert_eq!(&surf.as_storage()[..8], &[0, 9, 0, 0, 0, 9, 0, 0][..]);
+}
+
+#[test]
+fn rows_of_interleaved_formats() {
+    let surf: Surface<Rgb, u8, Vec<u8>> = Surface::new(2, 2, (0..12).collect());
+    let rows: Vec<&[u8]> = surf.rows().collect();
+    assert_eq!(rows, vec![&[0, 1, 2, 3, 4, 5][..], &[6, 7, 8, 9, 10, 11][..]]);
+
+    // Odd-width packed 4:2:2 rows include the padding sample.
+    let mut packed: Surface<Yuv422, u8, Vec<u8>> = Surface::new_black(3, 2);
+    assert_eq!(packed.rows().map(|r| r.len()).collect::<Vec<_>>(), vec![8, 8]);
+    for (y, row) in packed.rows_mut().enumerate() {
+        row[0] = y as u8 + 1;
+    }
+    assert_eq!(packed.get_pixel(0, 1).y, 2);
+}
+
+#[test]
+fn plane_rows_of_planar_formats() {
+    let surf: Surface<Yuv420p, u8, Vec<u8>> = Surface::new_black(5, 3);
+    let (y_rows, u_rows, v_rows) = surf.plane_rows();
+    assert_eq!(y_rows.map(|r| r.len()).collect::<Vec<_>>(), vec![5, 5, 5]);
+    assert_eq!(u_rows.map(|r| r.len()).collect::<Vec<_>>(), vec![3, 3]);
+    assert_eq!(v_rows.map(|r| r.len()).collect::<Vec<_>>(), vec![3, 3]);
+
+    let mut rgb: Surface<RgbPlanar, u8, Vec<u8>> = Surface::new_black(2, 2);
+    {
+        let (_, g_rows, _) = rgb.plane_rows_mut();
+        for row in g_rows {
+            row.copy_from_slice(&[5, 6]);
+        }
+    }
+    assert_eq!(rgb.get_pixel(1, 1).g, 6);
+    assert_eq!(rgb.get_pixel(1, 1).r, 0);
+}
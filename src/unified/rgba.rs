@@ -1,6 +1,8 @@
 use std::ops::{Deref, DerefMut};
+use std::slice;
 
-use super::{Format};
+use super::{Format, InterleavedFormat, PackedFormat, PlanarFormat, ToneFormat, plane_rows, plane_rows_mut};
+use super::super::unsafe_impl::{chunks3_mut, chunks4_mut, cast_pixels, cast_pixels_mut};
 use super::super::Channel;
 use super::super::colorspace::{
     ColorRGBA as ColorRgba,
@@ -60,6 +62,45 @@ impl<C> Format<C> for Rgb where C: Channel {
         px[1] = pixel.g;
         px[2] = pixel.b;
     }
+
+    fn map_in_place<F>(storage: &mut [C], width: u32, height: u32, mut f: F)
+        where
+            F: FnMut(u32, u32, Self::Pixel) -> Self::Pixel
+    {
+        let row_len = 3 * width as usize;
+        if row_len == 0 {
+            return;
+        }
+        let rows = storage[..row_len * height as usize].chunks_mut(row_len);
+        for (y, row) in rows.enumerate() {
+            for (x, (r, g, b)) in chunks3_mut(row).enumerate() {
+                let px = f(x as u32, y as u32, ColorRgb::new_rgb(*r, *g, *b));
+                *r = px.r;
+                *g = px.g;
+                *b = px.b;
+            }
+        }
+    }
+
+    fn packed_pixels(storage: &[C]) -> Option<&[Self::Pixel]> {
+        Some(<Self as PackedFormat<C>>::as_pixels(storage))
+    }
+}
+
+impl<C> InterleavedFormat<C> for Rgb where C: Channel {}
+
+impl<C> PackedFormat<C> for Rgb where C: Channel {
+    fn as_pixels(storage: &[C]) -> &[Self::Pixel] {
+        unsafe { cast_pixels(storage) }
+    }
+
+    fn as_pixels_mut(storage: &mut [C]) -> &mut [Self::Pixel] {
+        unsafe { cast_pixels_mut(storage) }
+    }
+}
+
+impl<C> ToneFormat<C> for Rgb where C: Channel {
+    const TONE_CHANNELS: usize = 3;
 }
 
 
@@ -102,6 +143,46 @@ impl<C> Format<C> for Rgba where C: Channel {
         px[2] = pixel.b;
         px[3] = pixel.a;
     }
+
+    fn map_in_place<F>(storage: &mut [C], width: u32, height: u32, mut f: F)
+        where
+            F: FnMut(u32, u32, Self::Pixel) -> Self::Pixel
+    {
+        let row_len = 4 * width as usize;
+        if row_len == 0 {
+            return;
+        }
+        let rows = storage[..row_len * height as usize].chunks_mut(row_len);
+        for (y, row) in rows.enumerate() {
+            for (x, (r, g, b, a)) in chunks4_mut(row).enumerate() {
+                let px = f(x as u32, y as u32, ColorRgba::new_rgba(*r, *g, *b, *a));
+                *r = px.r;
+                *g = px.g;
+                *b = px.b;
+                *a = px.a;
+            }
+        }
+    }
+
+    fn packed_pixels(storage: &[C]) -> Option<&[Self::Pixel]> {
+        Some(<Self as PackedFormat<C>>::as_pixels(storage))
+    }
+}
+
+impl<C> InterleavedFormat<C> for Rgba where C: Channel {}
+
+impl<C> PackedFormat<C> for Rgba where C: Channel {
+    fn as_pixels(storage: &[C]) -> &[Self::Pixel] {
+        unsafe { cast_pixels(storage) }
+    }
+
+    fn as_pixels_mut(storage: &mut [C]) -> &mut [Self::Pixel] {
+        unsafe { cast_pixels_mut(storage) }
+    }
+}
+
+impl<C> ToneFormat<C> for Rgba where C: Channel {
+    const TONE_CHANNELS: usize = 3;
 }
 
 
@@ -154,6 +235,47 @@ impl<C> Format<C> for RgbPlanar where C: Channel {
 }
 
 
+impl<'a, C> PlanarFormat<'a, C> for RgbPlanar
+    where
+        C: Channel + 'a
+{
+    type Planes = (&'a [C], &'a [C], &'a [C]);
+    type PlanesMut = (&'a mut [C], &'a mut [C], &'a mut [C]);
+    type PlaneRows = (slice::Chunks<'a, C>, slice::Chunks<'a, C>, slice::Chunks<'a, C>);
+    type PlaneRowsMut = (slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>);
+
+    fn get_planes(data: &'a [C], (w, h): (u32, u32)) -> Self::Planes {
+        let plane = get_plane_offset(w, h, 1);
+        let (r_plane, rest) = data.split_at(plane);
+        let (g_plane, rest) = rest.split_at(plane);
+        (r_plane, g_plane, &rest[..plane])
+    }
+
+    fn get_planes_mut(data: &'a mut [C], (w, h): (u32, u32)) -> Self::PlanesMut {
+        let plane = get_plane_offset(w, h, 1);
+        let (r_plane, rest) = data.split_at_mut(plane);
+        let (g_plane, rest) = rest.split_at_mut(plane);
+        (r_plane, g_plane, &mut rest[..plane])
+    }
+
+    fn get_plane_rows(data: &'a [C], (w, h): (u32, u32)) -> Self::PlaneRows {
+        let (r, g, b) = <Self as PlanarFormat<C>>::get_planes(data, (w, h));
+        let w = w as usize;
+        (plane_rows(r, w), plane_rows(g, w), plane_rows(b, w))
+    }
+
+    fn get_plane_rows_mut(data: &'a mut [C], (w, h): (u32, u32)) -> Self::PlaneRowsMut {
+        let (r, g, b) = <Self as PlanarFormat<C>>::get_planes_mut(data, (w, h));
+        let w = w as usize;
+        (plane_rows_mut(r, w), plane_rows_mut(g, w), plane_rows_mut(b, w))
+    }
+}
+
+impl<C> ToneFormat<C> for RgbPlanar where C: Channel {
+    const TONE_CHANNELS: usize = 3;
+}
+
+
 #[derive(Clone)]
 pub struct RgbaPlanar;
 
@@ -205,3 +327,45 @@ impl<C> Format<C> for RgbaPlanar where C: Channel {
         storage[a_off] = pixel.a;
     }
 }
+
+impl<'a, C> PlanarFormat<'a, C> for RgbaPlanar
+    where
+        C: Channel + 'a
+{
+    type Planes = (&'a [C], &'a [C], &'a [C], &'a [C]);
+    type PlanesMut = (&'a mut [C], &'a mut [C], &'a mut [C], &'a mut [C]);
+    type PlaneRows = (slice::Chunks<'a, C>, slice::Chunks<'a, C>, slice::Chunks<'a, C>, slice::Chunks<'a, C>);
+    type PlaneRowsMut = (slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>);
+
+    fn get_planes(data: &'a [C], (w, h): (u32, u32)) -> Self::Planes {
+        let plane = get_plane_offset(w, h, 1);
+        let (r_plane, rest) = data.split_at(plane);
+        let (g_plane, rest) = rest.split_at(plane);
+        let (b_plane, rest) = rest.split_at(plane);
+        (r_plane, g_plane, b_plane, &rest[..plane])
+    }
+
+    fn get_planes_mut(data: &'a mut [C], (w, h): (u32, u32)) -> Self::PlanesMut {
+        let plane = get_plane_offset(w, h, 1);
+        let (r_plane, rest) = data.split_at_mut(plane);
+        let (g_plane, rest) = rest.split_at_mut(plane);
+        let (b_plane, rest) = rest.split_at_mut(plane);
+        (r_plane, g_plane, b_plane, &mut rest[..plane])
+    }
+
+    fn get_plane_rows(data: &'a [C], (w, h): (u32, u32)) -> Self::PlaneRows {
+        let (r, g, b, a) = <Self as PlanarFormat<C>>::get_planes(data, (w, h));
+        let w = w as usize;
+        (plane_rows(r, w), plane_rows(g, w), plane_rows(b, w), plane_rows(a, w))
+    }
+
+    fn get_plane_rows_mut(data: &'a mut [C], (w, h): (u32, u32)) -> Self::PlaneRowsMut {
+        let (r, g, b, a) = <Self as PlanarFormat<C>>::get_planes_mut(data, (w, h));
+        let w = w as usize;
+        (plane_rows_mut(r, w), plane_rows_mut(g, w), plane_rows_mut(b, w), plane_rows_mut(a, w))
+    }
+}
+
+impl<C> ToneFormat<C> for RgbaPlanar where C: Channel {
+    const TONE_CHANNELS: usize = 3;
+}
@@ -1,41 +1,34 @@
 use std::ops::{Deref, DerefMut};
 
-use super::{Format, PlanarFormat};
+use std::slice;
+
+use super::{Format, LumaFormat, PlanarFormat, ToneFormat, plane_rows, plane_rows_mut};
 use super::super::Channel;
+use super::super::error::{Error, checked_channel_count};
 use super::super::colorspace::ColorYUV as ColorYuv;
 
 
 #[derive(Clone)]
 pub struct Yuv420p;
 
+/// Dimensions of each chroma plane.  Odd sizes round up, so the last
+/// chroma sample in a row or column covers a single luma sample.
 #[inline]
-pub fn get_y<C>(data: &[C], pixels: usize) -> &[C] {
-    &data[..pixels]
-}
-
-#[inline]
-pub fn get_u<C>(data: &[C], pixels: usize) -> &[C] {
-    &data[pixels..][..pixels / 4]
-}
-
-#[inline]
-pub fn get_v<C>(data: &[C], pixels: usize) -> &[C] {
-    &data[pixels..][pixels / 4..][..pixels / 4]
+pub fn chroma_size(width: u32, height: u32) -> (usize, usize) {
+    ((width as usize).div_ceil(2), (height as usize).div_ceil(2))
 }
 
 #[inline]
-pub fn get_y_mut<C>(data: &mut [C], pixels: usize) -> &mut [C] {
-    &mut data[..pixels]
-}
-
-#[inline]
-pub fn get_u_mut<C>(data: &mut [C], pixels: usize) -> &mut [C] {
-    &mut data[pixels..][..pixels / 4]
+pub fn get_y<C>(data: &[C], pixels: usize) -> &[C] {
+    &data[..pixels]
 }
 
 #[inline]
-pub fn get_v_mut<C>(data: &mut [C], pixels: usize) -> &mut [C] {
-    &mut data[pixels..][pixels / 4..][..pixels / 4]
+fn chroma_offsets(width: u32, height: u32, x: u32, y: u32) -> (usize, usize) {
+    let (cw, _) = chroma_size(width, height);
+    let offset_y = x as usize + width as usize * y as usize;
+    let offset_c = (x / 2) as usize + cw * (y / 2) as usize;
+    (offset_y, offset_c)
 }
 
 impl<C> Format<C> for Yuv420p where C: Channel {
@@ -43,71 +36,186 @@ impl<C> Format<C> for Yuv420p where C: Channel {
 
     fn channel_data_size(width: u32, height: u32) -> usize
     {
-        3 * width as usize * height as usize / 2
+        let (cw, ch) = chroma_size(width, height);
+        width as usize * height as usize + 2 * cw * ch
+    }
+
+    fn try_channel_data_size(width: u32, height: u32) -> Result<usize, Error>
+    {
+        // The chroma planes are never larger than the luma plane.
+        checked_channel_count(width, height, 3)?;
+        Ok(<Self as Format<C>>::channel_data_size(width, height))
     }
 
     fn init_black(width: u32, height: u32, storage: &mut [C])
     {
-        assert_eq!(width % 2, 0);
-        assert_eq!(height % 2, 0);
-        
         assert!(storage.len() == <Self as Format<C>>::channel_data_size(width, height));
-        let pixels = width as usize * width as usize;
+        let pixels = width as usize * height as usize;
 
         let luma_min = <C as Channel>::from_i32(0, 0, 2);
         let chroma_neutral = <C as Channel>::from_i32(1, 0, 2);
 
-        let mut pxiter = storage.iter_mut();
-        for _ in 0..pixels {
-            if let Some(px) = pxiter.next() {
-                *px = luma_min;
-            }
+        let (luma, chroma) = storage.split_at_mut(pixels);
+        for px in luma.iter_mut() {
+            *px = luma_min;
         }
-        while let Some(px) = pxiter.next() {
+        for px in chroma.iter_mut() {
             *px = chroma_neutral;
         }
     }
 
     #[inline]
     fn get_pixel(holder: &[C], width: u32, height: u32, x: u32, y: u32) -> Self::Pixel {
-        let pixels = width as usize * height as usize;
-        let offset_y = x + width * y;
-        let offset_c = (x / 2) + width * (y / 2);
-        let y = get_y(holder, pixels)[offset_y as usize];
-        let u = get_u(holder, pixels)[offset_c as usize];
-        let v = get_v(holder, pixels)[offset_c as usize];
-        ColorYuv::new_yuv(y, u, v)
+        assert!(x < width && y < height);
+        let (offset_y, offset_c) = chroma_offsets(width, height, x, y);
+        let (y_plane, u_plane, v_plane) = <Self as PlanarFormat<C>>::get_planes(holder, (width, height));
+        ColorYuv::new_yuv(y_plane[offset_y], u_plane[offset_c], v_plane[offset_c])
     }
 
     #[inline]
     fn put_pixel(holder: &mut [C], width: u32, height: u32, x: u32, y: u32, pixel: <Self as Format<C>>::Pixel) {
-        let pixels = width as usize * height as usize;
-        let offset_y = x + width * y;
-        let offset_c = (x / 2) + width * (y / 2);
-        get_y_mut(holder, pixels)[offset_y as usize] = pixel.y;
-        get_u_mut(holder, pixels)[offset_c as usize] = pixel.u;
-        get_v_mut(holder, pixels)[offset_c as usize] = pixel.v;
+        assert!(x < width && y < height);
+        let (offset_y, offset_c) = chroma_offsets(width, height, x, y);
+        let (y_plane, u_plane, v_plane) = <Self as PlanarFormat<C>>::get_planes_mut(holder, (width, height));
+        y_plane[offset_y] = pixel.y;
+        u_plane[offset_c] = pixel.u;
+        v_plane[offset_c] = pixel.v;
     }
 }
 
+impl<C> LumaFormat<C> for Yuv420p where C: Channel {
+    fn with_luma_plane_mut<F>(storage: &mut [C], width: u32, height: u32, f: F)
+        where
+            F: FnOnce(&mut [C])
+    {
+        f(&mut storage[..width as usize * height as usize])
+    }
+}
+
+impl<C> ToneFormat<C> for Yuv420p where C: Channel {
+    const TONE_CHANNELS: usize = 1;
+    const LUMA_PLANE: bool = true;
+}
+
 impl<'a, C> PlanarFormat<'a, C> for Yuv420p
     where
         C: Channel + 'a
 {
     type Planes = (&'a [C], &'a [C], &'a [C]);
     type PlanesMut = (&'a mut [C], &'a mut [C], &'a mut [C]);
+    type PlaneRows = (slice::Chunks<'a, C>, slice::Chunks<'a, C>, slice::Chunks<'a, C>);
+    type PlaneRowsMut = (slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>);
 
     fn get_planes(data: &'a [C], (w, h): (u32, u32)) -> Self::Planes {
-        let (w, h) = (w as usize, h as usize);
-        let (y_plane, rest) = data.split_at(w * h);
-        let (u_plane, v_plane) = rest.split_at(w * h / 4);
-        (y_plane, u_plane, v_plane)
+        let (cw, ch) = chroma_size(w, h);
+        let (y_plane, rest) = data.split_at(w as usize * h as usize);
+        let (u_plane, rest) = rest.split_at(cw * ch);
+        (y_plane, u_plane, &rest[..cw * ch])
     }
 
     fn get_planes_mut(data: &'a mut [C], (w, h): (u32, u32)) -> Self::PlanesMut {
-        let (w, h) = (w as usize, h as usize);
-        let (y_plane, rest) = data.split_at_mut(w * h);
-        let (u_plane, v_plane) = rest.split_at_mut(w * h / 4);
-        (y_plane, u_plane, v_plane)
+        let (cw, ch) = chroma_size(w, h);
+        let (y_plane, rest) = data.split_at_mut(w as usize * h as usize);
+        let (u_plane, rest) = rest.split_at_mut(cw * ch);
+        (y_plane, u_plane, &mut rest[..cw * ch])
     }
+
+    fn get_plane_rows(data: &'a [C], (w, h): (u32, u32)) -> Self::PlaneRows {
+        let (cw, _) = chroma_size(w, h);
+        let (y_plane, u_plane, v_plane) = <Self as PlanarFormat<C>>::get_planes(data, (w, h));
+        (plane_rows(y_plane, w as usize), plane_rows(u_plane, cw), plane_rows(v_plane, cw))
+    }
+
+    fn get_plane_rows_mut(data: &'a mut [C], (w, h): (u32, u32)) -> Self::PlaneRowsMut {
+        let (cw, _) = chroma_size(w, h);
+        let (y_plane, u_plane, v_plane) = <Self as PlanarFormat<C>>::get_planes_mut(data, (w, h));
+        (plane_rows_mut(y_plane, w as usize), plane_rows_mut(u_plane, cw), plane_rows_mut(v_plane, cw))
+    }
+}
+
+#[cfg(test)]
+fn test_pixel(x: u32, y: u32) -> ColorYuv<u8> {
+    let (cx, cy) = (x / 2, y / 2);
+    ColorYuv::new_yuv((x + 16 * y) as u8, (64 + cx + 8 * cy) as u8, (160 + cx + 8 * cy) as u8)
+}
+
+/// Planar 4:2:0 as laid out by ffmpeg's `AV_PIX_FMT_YUV420P` with an
+/// alignment of 1.
+#[cfg(test)]
+fn reference_layout(width: u32, height: u32) -> Vec<u8> {
+    let (cw, ch) = (width.div_ceil(2), height.div_ceil(2));
+    let mut out = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            out.push(test_pixel(x, y).y);
+        }
+    }
+    for cy in 0..ch {
+        for cx in 0..cw {
+            out.push(test_pixel(2 * cx, 2 * cy).u);
+        }
+    }
+    for cy in 0..ch {
+        for cx in 0..cw {
+            out.push(test_pixel(2 * cx, 2 * cy).v);
+        }
+    }
+    out
+}
+
+#[test]
+fn yuv420p_layout_3x3() {
+    use super::Surface;
+
+    let mut surf: Surface<Yuv420p, u8, Vec<u8>> = Surface::new_black(3, 3);
+    for y in 0..3 {
+        for x in 0..3 {
+            surf.put_pixel(x, y, test_pixel(x, y));
+        }
+    }
+    assert_eq!(&surf.as_storage()[..], &[
+        0, 1, 2,
+        16, 17, 18,
+        32, 33, 34,
+        64, 65,
+        72, 73,
+        160, 161,
+        168, 169,
+    ][..]);
+}
+
+#[test]
+fn yuv420p_layout_matches_reference() {
+    use super::Surface;
+
+    for height in 1..10 {
+        for width in 1..10 {
+            let mut surf: Surface<Yuv420p, u8, Vec<u8>> = Surface::try_new_black(width, height).unwrap();
+            for y in 0..height {
+                for x in 0..width {
+                    surf.put_pixel(x, y, test_pixel(x, y));
+                }
+            }
+            assert_eq!(surf.as_storage(), &reference_layout(width, height), "{}x{}", width, height);
+
+            for y in 0..height {
+                for x in 0..width {
+                    let px = surf.get_pixel(x, y);
+                    let expected = test_pixel(x, y);
+                    assert_eq!((px.y, px.u, px.v), (expected.y, expected.u, expected.v));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn yuv420p_black_is_neutral() {
+    use super::Surface;
+
+    let surf: Surface<Yuv420p, u8, Vec<u8>> = Surface::new_black(5, 3);
+    let (y_plane, u_plane, v_plane) = surf.get_planes();
+    assert_eq!(y_plane, &[0; 15][..]);
+    assert_eq!(u_plane, &[128; 6][..]);
+    assert_eq!(v_plane, &[128; 6][..]);
 }
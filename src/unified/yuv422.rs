@@ -1,7 +1,10 @@
 use std::ops::{Deref, DerefMut};
 
-use super::{Format, PlanarFormat};
+use std::slice;
+
+use super::{Format, InterleavedFormat, LumaFormat, PlanarFormat, ToneFormat, plane_rows, plane_rows_mut};
 use super::super::Channel;
+use super::super::error::{Error, checked_channel_count};
 use super::super::colorspace::ColorYUV as ColorYuv;
 
 
@@ -9,34 +12,39 @@ use super::super::colorspace::ColorYUV as ColorYuv;
 #[derive(Clone)]
 pub struct Yuv422;
 
+/// Width of the chroma planes, and the number of Y0 U Y1 V groups in a
+/// packed row.  Odd widths round up; the Y1 of a trailing group is padding.
 #[inline]
-fn get_yuv422_yuv<C>(data: &[C], (w, h): (u32, u32), (x, y): (u32, u32)) -> (&C, &C, &C) {
-    assert!(x < w);
-    assert!(y < h);
-    let data_base = data.as_ptr();
-    let (w, h) = (w as isize, h as isize);
-    let (x, y) = (x as isize, y as isize);
-    unsafe {
-        (
-            &*data_base.offset(2 * (w * y + x)),
-            &*data_base.offset(2 * ((w * y + x) & !1) + 1),
-            &*data_base.offset(2 * ((w * y + x) & !1) + 3),
-        )
-    }
+pub fn chroma_width(width: u32) -> usize {
+    (width as usize).div_ceil(2)
 }
 
 #[inline]
-fn get_yuv422_yuv_mut<C>(data: &mut [C], (w, h): (u32, u32), (x, y): (u32, u32)) -> (&mut C, &mut C, &mut C) {
+fn yuv422_offsets((w, h): (u32, u32), (x, y): (u32, u32)) -> (usize, usize, usize) {
     assert!(x < w);
     assert!(y < h);
+    let row = 4 * chroma_width(w) * y as usize;
+    let group = row + 4 * (x / 2) as usize;
+    (row + 2 * x as usize, group + 1, group + 3)
+}
+
+#[inline]
+fn get_yuv422_yuv<C>(data: &[C], wh: (u32, u32), xy: (u32, u32)) -> (&C, &C, &C) {
+    let (y_off, u_off, v_off) = yuv422_offsets(wh, xy);
+    (&data[y_off], &data[u_off], &data[v_off])
+}
+
+#[inline]
+fn get_yuv422_yuv_mut<C>(data: &mut [C], wh: (u32, u32), xy: (u32, u32)) -> (&mut C, &mut C, &mut C) {
+    let (y_off, u_off, v_off) = yuv422_offsets(wh, xy);
+    assert!(v_off < data.len());
     let data_base = data.as_mut_ptr();
-    let (w, h) = (w as isize, h as isize);
-    let (x, y) = (x as isize, y as isize);
+    // The three offsets are distinct and in bounds.
     unsafe {
         (
-            &mut *data_base.offset(2 * (w * y + x)),
-            &mut *data_base.offset(2 * ((w * y + x) & !1) + 1),
-            &mut *data_base.offset(2 * ((w * y + x) & !1) + 3),
+            &mut *data_base.add(y_off),
+            &mut *data_base.add(u_off),
+            &mut *data_base.add(v_off),
         )
     }
 }
@@ -46,30 +54,25 @@ impl<C> Format<C> for Yuv422 where C: Channel {
 
     fn channel_data_size(width: u32, height: u32) -> usize
     {
-        2 * width as usize * height as usize / 2
+        4 * chroma_width(width) * height as usize
+    }
+
+    fn try_channel_data_size(width: u32, height: u32) -> Result<usize, Error>
+    {
+        // Padding makes a packed row at most one pixel wider.
+        checked_channel_count(width.saturating_add(1), height, 2)?;
+        Ok(<Self as Format<C>>::channel_data_size(width, height))
     }
 
     fn init_black(width: u32, height: u32, storage: &mut [C])
     {
         assert!(storage.len() == <Self as Format<C>>::channel_data_size(width, height));
-        let pixels = width as usize * height as usize;
-
         let luma_min = <C as Channel>::from_i32(0, 0, 2);
         let chroma_neutral = <C as Channel>::from_i32(1, 0, 2);
 
-        let mut iter = storage.iter_mut();
-        for _ in 0..(pixels * 2) {
-            if let Some(y) = iter.next() {
-                *y = luma_min;
-            }
-            if let Some(u) = iter.next() {
-                *u = chroma_neutral;
-            }
-            if let Some(v) = iter.next() {
-                *v = chroma_neutral;
-            } else {
-                panic!("invalid storage");
-            }
+        // Y0 U Y1 V
+        for (idx, ch) in storage.iter_mut().enumerate() {
+            *ch = if idx % 2 == 0 { luma_min } else { chroma_neutral };
         }
     }
 
@@ -90,43 +93,68 @@ impl<C> Format<C> for Yuv422 where C: Channel {
 }
 
 
+impl<C> InterleavedFormat<C> for Yuv422 where C: Channel {}
+
+impl<C> LumaFormat<C> for Yuv422 where C: Channel {
+    fn with_luma_plane_mut<F>(storage: &mut [C], width: u32, height: u32, f: F)
+        where
+            F: FnOnce(&mut [C])
+    {
+        let (w, stride) = (width as usize, 4 * chroma_width(width));
+        let rows = || (0..height as usize).map(|y| y * stride);
+        let mut plane: Vec<C> = Vec::with_capacity(w * height as usize);
+        for row in rows() {
+            plane.extend((0..w).map(|x| storage[row + 2 * x]));
+        }
+        f(&mut plane);
+        for (row, luma) in rows().zip(plane.chunks(w.max(1))) {
+            for (x, &l) in luma.iter().enumerate() {
+                storage[row + 2 * x] = l;
+            }
+        }
+    }
+}
+
+impl<C> ToneFormat<C> for Yuv422 where C: Channel {
+    const TONE_CHANNELS: usize = 1;
+}
+
+
 /// Planar YUV 4:2:2
 #[derive(Clone)]
 pub struct Yuv422p;
 
 #[inline]
-fn get_yuv422p_yuv<C>(data: &[C], (w, h): (u32, u32), (x, y): (u32, u32)) -> (&C, &C, &C) {
+fn yuv422p_offsets((w, h): (u32, u32), (x, y): (u32, u32)) -> (usize, usize, usize) {
     assert!(x < w);
     assert!(y < h);
-    let data_base = data.as_ptr();
-    let (w, h) = (w as isize, h as isize);
-    let (x, y) = (x as isize, y as isize);
+    let (w, h) = (w as usize, h as usize);
+    let (x, y) = (x as usize, y as usize);
+    let cw = chroma_width(w as u32);
     let u_offset = w * h;
-    let v_offset = 3 * (w * h) / 2;
-    unsafe {
-        (
-            &*data_base.offset(w * y + x),
-            &*data_base.offset(u_offset + ((w * y + x) / 2)),
-            &*data_base.offset(v_offset + ((w * y + x) / 2)),
-        )
-    }
+    let v_offset = u_offset + cw * h;
+    let chroma = cw * y + x / 2;
+    (w * y + x, u_offset + chroma, v_offset + chroma)
+}
+
+#[inline]
+fn get_yuv422p_yuv<C>(data: &[C], wh: (u32, u32), xy: (u32, u32)) -> (&C, &C, &C) {
+    let (y_off, u_off, v_off) = yuv422p_offsets(wh, xy);
+    (&data[y_off], &data[u_off], &data[v_off])
 }
 
 
 #[inline]
-fn get_yuv422p_yuv_mut<C>(data: &mut [C], (w, h): (u32, u32), (x, y): (u32, u32)) -> (&mut C, &mut C, &mut C) {
-    assert!(x < w);
-    assert!(y < h);
+fn get_yuv422p_yuv_mut<C>(data: &mut [C], wh: (u32, u32), xy: (u32, u32)) -> (&mut C, &mut C, &mut C) {
+    let (y_off, u_off, v_off) = yuv422p_offsets(wh, xy);
+    assert!(v_off < data.len());
     let data_base = data.as_mut_ptr();
-    let (w, h) = (w as isize, h as isize);
-    let (x, y) = (x as isize, y as isize);
-    let u_offset = w * h;
-    let v_offset = 3 * (w * h) / 2;
+    // The three offsets lie in distinct planes and are in bounds.
     unsafe {
         (
-            &mut *data_base.offset(w * y + x),
-            &mut *data_base.offset(u_offset + ((w * y + x) / 2)),
-            &mut *data_base.offset(v_offset + ((w * y + x) / 2)),
+            &mut *data_base.add(y_off),
+            &mut *data_base.add(u_off),
+            &mut *data_base.add(v_off),
         )
     }
 }
@@ -136,7 +164,13 @@ impl<C> Format<C> for Yuv422p where C: Channel {
 
     fn channel_data_size(width: u32, height: u32) -> usize
     {
-        2 * width as usize * height as usize
+        (width as usize + 2 * chroma_width(width)) * height as usize
+    }
+
+    fn try_channel_data_size(width: u32, height: u32) -> Result<usize, Error>
+    {
+        checked_channel_count(width.saturating_add(1), height, 2)?;
+        Ok(<Self as Format<C>>::channel_data_size(width, height))
     }
 
     fn init_black(width: u32, height: u32, storage: &mut [C])
@@ -147,12 +181,12 @@ impl<C> Format<C> for Yuv422p where C: Channel {
         let luma_min = <C as Channel>::from_i32(0, 0, 2);
         let chroma_neutral = <C as Channel>::from_i32(1, 0, 2);
 
-        let mut iter = storage.iter_mut();
-        for _ in 0..pixels {
-            *iter.next().unwrap() = luma_min;
+        let (luma, chroma) = storage.split_at_mut(pixels);
+        for px in luma.iter_mut() {
+            *px = luma_min;
         }
-        for _ in 0..pixels {
-            *iter.next().unwrap() = chroma_neutral;
+        for px in chroma.iter_mut() {
+            *px = chroma_neutral;
         }
     }
 
@@ -172,24 +206,153 @@ impl<C> Format<C> for Yuv422p where C: Channel {
     }
 }
 
+impl<C> LumaFormat<C> for Yuv422p where C: Channel {
+    fn with_luma_plane_mut<F>(storage: &mut [C], width: u32, height: u32, f: F)
+        where
+            F: FnOnce(&mut [C])
+    {
+        f(&mut storage[..width as usize * height as usize])
+    }
+}
+
+impl<C> ToneFormat<C> for Yuv422p where C: Channel {
+    const TONE_CHANNELS: usize = 1;
+    const LUMA_PLANE: bool = true;
+}
+
 impl<'a, C> PlanarFormat<'a, C> for Yuv422p
     where
         C: Channel + 'a
 {
     type Planes = (&'a [C], &'a [C], &'a [C]);
     type PlanesMut = (&'a mut [C], &'a mut [C], &'a mut [C]);
+    type PlaneRows = (slice::Chunks<'a, C>, slice::Chunks<'a, C>, slice::Chunks<'a, C>);
+    type PlaneRowsMut = (slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>, slice::ChunksMut<'a, C>);
 
     fn get_planes(data: &'a [C], (w, h): (u32, u32)) -> Self::Planes {
-        let (w, h) = (w as usize, h as usize);
-        let (y_plane, rest) = data.split_at(w * h);
-        let (u_plane, v_plane) = rest.split_at(w * h / 2);
-        (y_plane, u_plane, v_plane)
+        let chroma = chroma_width(w) * h as usize;
+        let (y_plane, rest) = data.split_at(w as usize * h as usize);
+        let (u_plane, rest) = rest.split_at(chroma);
+        (y_plane, u_plane, &rest[..chroma])
     }
 
     fn get_planes_mut(data: &'a mut [C], (w, h): (u32, u32)) -> Self::PlanesMut {
-        let (w, h) = (w as usize, h as usize);
-        let (y_plane, rest) = data.split_at_mut(w * h);
-        let (u_plane, v_plane) = rest.split_at_mut(w * h / 2);
-        (y_plane, u_plane, v_plane)
+        let chroma = chroma_width(w) * h as usize;
+        let (y_plane, rest) = data.split_at_mut(w as usize * h as usize);
+        let (u_plane, rest) = rest.split_at_mut(chroma);
+        (y_plane, u_plane, &mut rest[..chroma])
+    }
+
+    fn get_plane_rows(data: &'a [C], (w, h): (u32, u32)) -> Self::PlaneRows {
+        let cw = chroma_width(w);
+        let (y_plane, u_plane, v_plane) = <Self as PlanarFormat<C>>::get_planes(data, (w, h));
+        (plane_rows(y_plane, w as usize), plane_rows(u_plane, cw), plane_rows(v_plane, cw))
+    }
+
+    fn get_plane_rows_mut(data: &'a mut [C], (w, h): (u32, u32)) -> Self::PlaneRowsMut {
+        let cw = chroma_width(w);
+        let (y_plane, u_plane, v_plane) = <Self as PlanarFormat<C>>::get_planes_mut(data, (w, h));
+        (plane_rows_mut(y_plane, w as usize), plane_rows_mut(u_plane, cw), plane_rows_mut(v_plane, cw))
+    }
+}
+
+#[cfg(test)]
+fn test_pixel(x: u32, y: u32) -> ColorYuv<u8> {
+    let cx = x / 2;
+    ColorYuv::new_yuv((x + 16 * y) as u8, (64 + cx + 8 * y) as u8, (160 + cx + 8 * y) as u8)
+}
+
+#[cfg(test)]
+fn fill<M>(width: u32, height: u32) -> super::Surface<M, u8, Vec<u8>>
+    where
+        M: Format<u8, Pixel=ColorYuv<u8>>
+{
+    let mut surf = super::Surface::try_new_black(width, height).unwrap();
+    for y in 0..height {
+        for x in 0..width {
+            surf.put_pixel(x, y, test_pixel(x, y));
+        }
+    }
+    surf
+}
+
+/// Packed 4:2:2 as laid out by ffmpeg's `AV_PIX_FMT_YUYV422` with an
+/// alignment of 1; odd widths carry a padding luma sample at the end of
+/// each row, which `init_black` leaves at black.
+#[cfg(test)]
+fn reference_packed(width: u32, height: u32) -> Vec<u8> {
+    let mut out = Vec::new();
+    for y in 0..height {
+        for cx in 0..width.div_ceil(2) {
+            let px = test_pixel(2 * cx, y);
+            let y1 = if 2 * cx + 1 < width { test_pixel(2 * cx + 1, y).y } else { 0 };
+            out.extend_from_slice(&[px.y, px.u, y1, px.v]);
+        }
+    }
+    out
+}
+
+/// Planar 4:2:2 as laid out by ffmpeg's `AV_PIX_FMT_YUV422P` with an
+/// alignment of 1.
+#[cfg(test)]
+fn reference_planar(width: u32, height: u32) -> Vec<u8> {
+    let cw = width.div_ceil(2);
+    let mut out = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            out.push(test_pixel(x, y).y);
+        }
+    }
+    for y in 0..height {
+        for cx in 0..cw {
+            out.push(test_pixel(2 * cx, y).u);
+        }
+    }
+    for y in 0..height {
+        for cx in 0..cw {
+            out.push(test_pixel(2 * cx, y).v);
+        }
+    }
+    out
+}
+
+#[test]
+fn yuv422_layout_3x2() {
+    let packed = fill::<Yuv422>(3, 2);
+    assert_eq!(&packed.as_storage()[..], &[
+        0, 64, 1, 160, 2, 65, 0, 161,
+        16, 72, 17, 168, 18, 73, 0, 169,
+    ][..]);
+
+    let planar = fill::<Yuv422p>(3, 2);
+    assert_eq!(&planar.as_storage()[..], &[
+        0, 1, 2, 16, 17, 18,
+        64, 65, 72, 73,
+        160, 161, 168, 169,
+    ][..]);
+}
+
+#[test]
+fn yuv422_layouts_match_reference() {
+    for height in 1..10 {
+        for width in 1..10 {
+            let packed = fill::<Yuv422>(width, height);
+            assert_eq!(packed.as_storage(), &reference_packed(width, height), "{}x{}", width, height);
+
+            let planar = fill::<Yuv422p>(width, height);
+            assert_eq!(planar.as_storage(), &reference_planar(width, height), "{}x{}", width, height);
+
+            for y in 0..height {
+                for x in 0..width {
+                    let expected = test_pixel(x, y);
+                    for px in &[packed.get_pixel(x, y), planar.get_pixel(x, y)] {
+                        assert_eq!((px.y, px.u, px.v), (expected.y, expected.u, expected.v));
+                    }
+                }
+            }
+
+            let converted: super::Surface<Yuv422p, u8, Vec<u8>> = packed.into();
+            assert_eq!(converted.as_storage(), planar.as_storage(), "{}x{}", width, height);
+        }
     }
 }
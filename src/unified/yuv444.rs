@@ -1,6 +1,7 @@
 use std::ops::{Deref, DerefMut};
 
-use super::{Format};
+use super::{Format, InterleavedFormat, PackedFormat, LumaFormat, ToneFormat};
+use super::super::unsafe_impl::{chunks3_mut, cast_pixels, cast_pixels_mut};
 use super::super::Channel;
 use super::super::colorspace::ColorYUV as ColorYuv;
 
@@ -12,13 +13,12 @@ impl<C> Format<C> for Yuv444 where C: Channel {
 
     fn channel_data_size(width: u32, height: u32) -> usize
     {
-        3 * width as usize * height as usize / 2
+        3 * width as usize * height as usize
     }
 
     fn init_black(width: u32, height: u32, storage: &mut [C])
     {
         assert!(storage.len() == <Self as Format<C>>::channel_data_size(width, height));
-        let pixels = width as usize * width as usize;
 
         let luma_min = <C as Channel>::from_i32(0, 0, 2);
         let chroma_neutral = <C as Channel>::from_i32(1, 0, 2);
@@ -55,6 +55,59 @@ impl<C> Format<C> for Yuv444 where C: Channel {
 
         holder[start_subpixel..][..3].copy_from_slice(&[pixel.y, pixel.u, pixel.v]);
     }
+
+    fn map_in_place<F>(storage: &mut [C], width: u32, height: u32, mut f: F)
+        where
+            F: FnMut(u32, u32, Self::Pixel) -> Self::Pixel
+    {
+        let row_len = 3 * width as usize;
+        if row_len == 0 {
+            return;
+        }
+        let rows = storage[..row_len * height as usize].chunks_mut(row_len);
+        for (y, row) in rows.enumerate() {
+            for (x, (py, pu, pv)) in chunks3_mut(row).enumerate() {
+                let px = f(x as u32, y as u32, ColorYuv::new_yuv(*py, *pu, *pv));
+                *py = px.y;
+                *pu = px.u;
+                *pv = px.v;
+            }
+        }
+    }
+
+    fn packed_pixels(storage: &[C]) -> Option<&[Self::Pixel]> {
+        Some(<Self as PackedFormat<C>>::as_pixels(storage))
+    }
+}
+
+impl<C> InterleavedFormat<C> for Yuv444 where C: Channel {}
+
+impl<C> LumaFormat<C> for Yuv444 where C: Channel {
+    fn with_luma_plane_mut<F>(storage: &mut [C], width: u32, height: u32, f: F)
+        where
+            F: FnOnce(&mut [C])
+    {
+        let pixels = width as usize * height as usize;
+        let mut plane: Vec<C> = storage.iter().step_by(3).take(pixels).cloned().collect();
+        f(&mut plane);
+        for (px, l) in storage.chunks_mut(3).zip(plane) {
+            px[0] = l;
+        }
+    }
+}
+
+impl<C> ToneFormat<C> for Yuv444 where C: Channel {
+    const TONE_CHANNELS: usize = 1;
+}
+
+impl<C> PackedFormat<C> for Yuv444 where C: Channel {
+    fn as_pixels(storage: &[C]) -> &[Self::Pixel] {
+        unsafe { cast_pixels(storage) }
+    }
+
+    fn as_pixels_mut(storage: &mut [C]) -> &mut [Self::Pixel] {
+        unsafe { cast_pixels_mut(storage) }
+    }
 }
 
 
@@ -72,7 +125,7 @@ impl<C> Format<C> for Yuv444p where C: Channel {
     /// panicks if storage is insufficiently large.
     fn init_black(width: u32, height: u32, storage: &mut [C])
     {
-        let pixels = width as usize * width as usize;
+        let pixels = width as usize * height as usize;
         let luma_min = <C as Channel>::from_i32(0, 0, 2);
         let chroma_neutral = <C as Channel>::from_i32(1, 0, 2);
 
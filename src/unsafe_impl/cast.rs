@@ -0,0 +1,26 @@
+use std::mem;
+use std::slice;
+
+
+/// Reinterprets a run of channels as a slice of pixels.
+///
+/// Callers must ensure `P` is a `#[repr(C)]` struct consisting solely of
+/// `C` fields, so that it has the size and alignment of `[C; N]`.
+pub unsafe fn cast_pixels<C, P>(memory: &[C]) -> &[P] {
+    let channels = mem::size_of::<P>() / mem::size_of::<C>();
+    assert_eq!(mem::size_of::<P>(), channels * mem::size_of::<C>());
+    assert_eq!(mem::align_of::<P>(), mem::align_of::<C>());
+    assert_eq!(memory.len() % channels, 0);
+
+    slice::from_raw_parts(memory.as_ptr() as *const P, memory.len() / channels)
+}
+
+/// Mutable counterpart of `cast_pixels`, with the same requirements.
+pub unsafe fn cast_pixels_mut<C, P>(memory: &mut [C]) -> &mut [P] {
+    let channels = mem::size_of::<P>() / mem::size_of::<C>();
+    assert_eq!(mem::size_of::<P>(), channels * mem::size_of::<C>());
+    assert_eq!(mem::align_of::<P>(), mem::align_of::<C>());
+    assert_eq!(memory.len() % channels, 0);
+
+    slice::from_raw_parts_mut(memory.as_mut_ptr() as *mut P, memory.len() / channels)
+}
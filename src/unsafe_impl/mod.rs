@@ -9,3 +9,6 @@ pub use self::chunks4::{
     chunks4, chunks4_mut,
     Chunks4, Chunks4Mut,
 };
+
+mod cast;
+pub use self::cast::{cast_pixels, cast_pixels_mut};
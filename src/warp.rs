@@ -0,0 +1,480 @@
+//! Affine and perspective warps of packed surfaces.
+//!
+//! Transforms map input coordinates to output coordinates, with pixel
+//! centres at integer positions.  Each output pixel is sampled from the
+//! input at the inverse-mapped position; positions more than half a pixel
+//! outside the input take the fill colour.  Taps of the bilinear and
+//! bicubic filters that fall outside the input repeat the edge pixels.
+
+use std::ops::{Deref, DerefMut};
+
+use super::{Channel, Format, PackedFormat, Surface};
+
+/// How the input is sampled between pixel centres.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Nearest,
+    Bilinear,
+    /// Catmull-Rom cubic over a 4x4 neighbourhood.
+    Bicubic,
+}
+
+/// A 2D affine transform: `x' = a*x + b*y + c`, `y' = d*x + e*y + f`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Affine {
+    m: [f64; 6],
+}
+
+impl Affine {
+    pub fn new(a: f64, b: f64, c: f64, d: f64, e: f64, f: f64) -> Affine {
+        Affine { m: [a, b, c, d, e, f] }
+    }
+
+    pub fn identity() -> Affine {
+        Affine::new(1.0, 0.0, 0.0, 0.0, 1.0, 0.0)
+    }
+
+    pub fn translation(tx: f64, ty: f64) -> Affine {
+        Affine::new(1.0, 0.0, tx, 0.0, 1.0, ty)
+    }
+
+    pub fn scale(sx: f64, sy: f64) -> Affine {
+        Affine::new(sx, 0.0, 0.0, 0.0, sy, 0.0)
+    }
+
+    /// Rotation about the origin.  With y pointing down, positive angles
+    /// turn clockwise on screen.
+    pub fn rotation(radians: f64) -> Affine {
+        let (sin, cos) = radians.sin_cos();
+        Affine::new(cos, -sin, 0.0, sin, cos, 0.0)
+    }
+
+    /// Rotation about `(cx, cy)`.
+    pub fn rotation_about(radians: f64, cx: f64, cy: f64) -> Affine {
+        Affine::translation(-cx, -cy)
+            .then(&Affine::rotation(radians))
+            .then(&Affine::translation(cx, cy))
+    }
+
+    /// `x' = x + kx*y`, `y' = ky*x + y`.
+    pub fn shear(kx: f64, ky: f64) -> Affine {
+        Affine::new(1.0, kx, 0.0, ky, 1.0, 0.0)
+    }
+
+    /// The transform applying `self` first and `next` second.
+    pub fn then(&self, next: &Affine) -> Affine {
+        let [a, b, c, d, e, f] = self.m;
+        let [na, nb, nc, nd, ne, nf] = next.m;
+        Affine::new(
+            na * a + nb * d, na * b + nb * e, na * c + nb * f + nc,
+            nd * a + ne * d, nd * b + ne * e, nd * c + ne * f + nf)
+    }
+
+    /// The inverse transform, or `None` if `self` is singular.
+    pub fn inverse(&self) -> Option<Affine> {
+        let [a, b, c, d, e, f] = self.m;
+        let det = a * e - b * d;
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let (ia, ib, id, ie) = (e / det, -b / det, -d / det, a / det);
+        Some(Affine::new(ia, ib, -(ia * c + ib * f), id, ie, -(id * c + ie * f)))
+    }
+
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let [a, b, c, d, e, f] = self.m;
+        (a * x + b * y + c, d * x + e * y + f)
+    }
+}
+
+/// A 3x3 projective transform acting on homogeneous coordinates
+/// `(x, y, 1)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Homography {
+    m: [[f64; 3]; 3],
+}
+
+impl Homography {
+    pub fn new(m: [[f64; 3]; 3]) -> Homography {
+        Homography { m }
+    }
+
+    pub fn identity() -> Homography {
+        Homography::from(Affine::identity())
+    }
+
+    /// The homography taking each of the four `from` points to the
+    /// corresponding `to` point, e.g. the corners of a photographed page to
+    /// those of an upright rectangle.  `None` if three of the points in
+    /// either set are collinear or a coordinate is not finite.
+    pub fn from_quad(from: [(f64, f64); 4], to: [(f64, f64); 4]) -> Option<Homography> {
+        // Solve for h00..h21 with h22 = 1.
+        let mut sys = [[0.0; 9]; 8];
+        for (i, (&(x, y), &(u, v))) in from.iter().zip(to.iter()).enumerate() {
+            sys[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+            sys[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+        }
+        let h = solve8(sys)?;
+        Some(Homography::new([
+            [h[0], h[1], h[2]],
+            [h[3], h[4], h[5]],
+            [h[6], h[7], 1.0],
+        ]))
+    }
+
+    /// The transform applying `self` first and `next` second.
+    pub fn then(&self, next: &Homography) -> Homography {
+        let mut m = [[0.0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..3).map(|k| next.m[i][k] * self.m[k][j]).sum();
+            }
+        }
+        Homography::new(m)
+    }
+
+    /// The inverse transform, or `None` if `self` is singular.
+    pub fn inverse(&self) -> Option<Homography> {
+        let m = &self.m;
+        let cof = |r0: usize, r1: usize, c0: usize, c1: usize| {
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+        let adj = [
+            [cof(1, 2, 1, 2), -cof(0, 2, 1, 2), cof(0, 1, 1, 2)],
+            [-cof(1, 2, 0, 2), cof(0, 2, 0, 2), -cof(0, 1, 0, 2)],
+            [cof(1, 2, 0, 1), -cof(0, 2, 0, 1), cof(0, 1, 0, 1)],
+        ];
+        let det = m[0][0] * adj[0][0] + m[0][1] * adj[1][0] + m[0][2] * adj[2][0];
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let mut inv = adj;
+        for v in inv.iter_mut().flat_map(|row| row.iter_mut()) {
+            *v /= det;
+        }
+        Some(Homography::new(inv))
+    }
+
+    /// Maps `(x, y)`, or `None` if it maps to infinity.
+    pub fn apply(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let m = &self.m;
+        let w = m[2][0] * x + m[2][1] * y + m[2][2];
+        if w.abs() < 1e-12 {
+            return None;
+        }
+        Some((
+            (m[0][0] * x + m[0][1] * y + m[0][2]) / w,
+            (m[1][0] * x + m[1][1] * y + m[1][2]) / w,
+        ))
+    }
+}
+
+impl From<Affine> for Homography {
+    fn from(affine: Affine) -> Homography {
+        let [a, b, c, d, e, f] = affine.m;
+        Homography::new([[a, b, c], [d, e, f], [0.0, 0.0, 1.0]])
+    }
+}
+
+/// Gauss-Jordan elimination with partial pivoting on an augmented 8x9
+/// system.  `None` if the system is singular or not finite.
+fn solve8(mut sys: [[f64; 9]; 8]) -> Option<[f64; 8]> {
+    for col in 0..8 {
+        let pivot = (col..8)
+            .max_by(|&a, &b| sys[a][col].abs().total_cmp(&sys[b][col].abs()))
+            .unwrap();
+        let lead = sys[pivot][col].abs();
+        if !lead.is_finite() || lead < 1e-12 {
+            return None;
+        }
+        sys.swap(col, pivot);
+        let lead = sys[col];
+        for (r, row) in sys.iter_mut().enumerate() {
+            if r != col {
+                let k = row[col] / lead[col];
+                for (v, l) in row.iter_mut().zip(lead.iter()) {
+                    *v -= k * l;
+                }
+            }
+        }
+    }
+    let mut out = [0.0; 8];
+    for (i, v) in out.iter_mut().enumerate() {
+        *v = sys[i][8] / sys[i][i];
+    }
+    if out.iter().all(|v| v.is_finite()) {
+        Some(out)
+    } else {
+        None
+    }
+}
+
+struct Source<'a, C: 'a> {
+    data: &'a [C],
+    width: u32,
+    height: u32,
+    channels: usize,
+}
+
+impl<'a, C: Channel> Source<'a, C> {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= -0.5 && x <= self.width as f64 - 0.5 && y >= -0.5 && y <= self.height as f64 - 0.5
+    }
+
+    #[inline]
+    fn offset(&self, x: i64, y: i64) -> usize {
+        let x = x.max(0).min(self.width as i64 - 1) as usize;
+        let y = y.max(0).min(self.height as i64 - 1) as usize;
+        (y * self.width as usize + x) * self.channels
+    }
+
+    fn sample(&self, x: f64, y: f64, interpolation: Interpolation, out: &mut [C]) {
+        match interpolation {
+            Interpolation::Nearest => {
+                let at = self.offset(x.round() as i64, y.round() as i64);
+                out.copy_from_slice(&self.data[at..at + self.channels]);
+            }
+            Interpolation::Bilinear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                self.filter(x0 as i64, y0 as i64, &[1.0 - fx, fx], &[1.0 - fy, fy], out);
+            }
+            Interpolation::Bicubic => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (wx, wy) = (catmull_rom(x - x0), catmull_rom(y - y0));
+                self.filter(x0 as i64 - 1, y0 as i64 - 1, &wx, &wy, out);
+            }
+        }
+    }
+
+    /// Separable filter with taps starting at `(x0, y0)`.
+    fn filter(&self, x0: i64, y0: i64, wx: &[f64], wy: &[f64], out: &mut [C]) {
+        for (c, out) in out.iter_mut().enumerate() {
+            let mut acc = 0.0;
+            for (j, &ky) in wy.iter().enumerate() {
+                for (i, &kx) in wx.iter().enumerate() {
+                    let at = self.offset(x0 + i as i64, y0 + j as i64) + c;
+                    acc += kx * ky * self.data[at].to_f64().unwrap();
+                }
+            }
+            *out = clamp_channel(acc);
+        }
+    }
+}
+
+fn catmull_rom(t: f64) -> [f64; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+/// Bicubic filters overshoot, so results are kept within the channel's
+/// nominal range.
+fn clamp_channel<C: Channel>(val: f64) -> C {
+    let lo = C::min_value().to_f64().unwrap();
+    let hi = C::max_value().to_f64().unwrap();
+    C::from_f64(val.max(lo).min(hi))
+}
+
+fn warp_with<M, C, SI, SO, F>(
+    input: &Surface<M, C, SI>,
+    output: &mut Surface<M, C, SO>,
+    interpolation: Interpolation,
+    fill: M::Pixel,
+    inverse: F,
+)
+    where
+        M: PackedFormat<C>,
+        C: Channel,
+        SI: Deref<Target=[C]>,
+        SO: Deref<Target=[C]> + DerefMut,
+        F: Fn(f64, f64) -> Option<(f64, f64)>,
+{
+    if input.width() == 0 || input.height() == 0 {
+        for row in output.rows_mut() {
+            for px in <M as PackedFormat<C>>::as_pixels_mut(row) {
+                *px = fill;
+            }
+        }
+        return;
+    }
+    let source = Source {
+        data: input.as_storage(),
+        width: input.width(),
+        height: input.height(),
+        channels: <M as Format<C>>::channel_data_size(1, 1),
+    };
+    let channels = source.channels;
+    for (y, row) in output.rows_mut().enumerate() {
+        for (x, px) in row.chunks_mut(channels).enumerate() {
+            match inverse(x as f64, y as f64) {
+                Some((sx, sy)) if source.contains(sx, sy) => {
+                    source.sample(sx, sy, interpolation, px);
+                }
+                _ => <M as PackedFormat<C>>::as_pixels_mut(px)[0] = fill,
+            }
+        }
+    }
+}
+
+/// Renders `input` through `transform` into `output`.  A singular
+/// transform collapses the input to zero area, leaving `output` filled.
+pub fn warp_affine<M, C, SI, SO>(
+    input: &Surface<M, C, SI>,
+    output: &mut Surface<M, C, SO>,
+    transform: &Affine,
+    interpolation: Interpolation,
+    fill: M::Pixel,
+)
+    where
+        M: PackedFormat<C>,
+        C: Channel,
+        SI: Deref<Target=[C]>,
+        SO: Deref<Target=[C]> + DerefMut,
+{
+    let inverse = transform.inverse();
+    warp_with(input, output, interpolation, fill, |x, y| inverse.map(|inv| inv.apply(x, y)));
+}
+
+/// Renders `input` through `transform` into `output`.  A singular
+/// transform leaves `output` filled.
+pub fn warp_perspective<M, C, SI, SO>(
+    input: &Surface<M, C, SI>,
+    output: &mut Surface<M, C, SO>,
+    transform: &Homography,
+    interpolation: Interpolation,
+    fill: M::Pixel,
+)
+    where
+        M: PackedFormat<C>,
+        C: Channel,
+        SI: Deref<Target=[C]>,
+        SO: Deref<Target=[C]> + DerefMut,
+{
+    let inverse = transform.inverse();
+    warp_with(input, output, interpolation, fill, |x, y| inverse.and_then(|inv| inv.apply(x, y)));
+}
+
+#[cfg(test)]
+fn test_image(width: u32, height: u32, seed: usize) -> Surface<super::Rgb, u8, Box<[u8]>> {
+    let storage: Vec<u8> = (0..3 * width as usize * height as usize)
+        .map(|i| ((i * 37 + seed * 101) % 251) as u8)
+        .collect();
+    Surface::new(width, height, storage.into_boxed_slice())
+}
+
+#[cfg(test)]
+const INTERPOLATIONS: [Interpolation; 3] =
+    [Interpolation::Nearest, Interpolation::Bilinear, Interpolation::Bicubic];
+
+#[test]
+fn identity_warp_is_exact() {
+    use super::{Rgb, Rgba, Luma, Pixel, ColorRGB, ColorRGBA, ColorL};
+
+    let rgb = test_image(23, 17, 1);
+    let luma: Surface<Luma, u8, Box<[u8]>> = super::experimental::extract_luma(&rgb);
+    let rgba: Surface<Rgba, u8, Box<[u8]>> = Surface::new(6, 17, rgb.as_storage()[..6 * 17 * 4].to_vec().into_boxed_slice());
+    for &interpolation in &INTERPOLATIONS {
+        let mut out: Surface<Rgb, u8, Box<[u8]>> = Surface::new_black(23, 17);
+        warp_affine(&rgb, &mut out, &Affine::identity(), interpolation, ColorRGB::black());
+        assert_eq!(out.as_storage(), rgb.as_storage());
+
+        let mut out: Surface<Luma, u8, Box<[u8]>> = Surface::new_black(23, 17);
+        warp_perspective(&luma, &mut out, &Homography::identity(), interpolation, ColorL::black());
+        assert_eq!(out.as_storage(), luma.as_storage());
+
+        let mut out: Surface<Rgba, u8, Box<[u8]>> = Surface::new_black(6, 17);
+        warp_affine(&rgba, &mut out, &Affine::identity(), interpolation, ColorRGBA::black());
+        assert_eq!(out.as_storage(), rgba.as_storage());
+    }
+}
+
+#[test]
+fn translation_fills_uncovered_pixels() {
+    use super::{Luma, ColorL};
+
+    let storage: Vec<u8> = (1..13).collect();
+    let luma: Surface<Luma, u8, Vec<u8>> = Surface::new(4, 3, storage);
+    let mut out: Surface<Luma, u8, Vec<u8>> = Surface::new_black(4, 3);
+    for &interpolation in &INTERPOLATIONS {
+        warp_affine(&luma, &mut out, &Affine::translation(1.0, -1.0), interpolation, ColorL::new_l(99));
+        assert_eq!(out.as_storage(), &[
+            99, 5, 6, 7,
+            99, 9, 10, 11,
+            99, 99, 99, 99,
+        ][..]);
+    }
+}
+
+#[test]
+fn empty_input_fills_output() {
+    use super::{Luma, ColorL};
+
+    // Output (0, 0) samples the input's corner at (-0.5, -0.5).
+    let shift = Affine::translation(0.5, 0.5);
+    for &(width, height) in &[(0, 3), (3, 0), (0, 0)] {
+        let empty: Surface<Luma, u8, Vec<u8>> = Surface::new(width, height, Vec::new());
+        let mut out: Surface<Luma, u8, Vec<u8>> = Surface::new_black(3, 2);
+        for &interpolation in &INTERPOLATIONS {
+            warp_affine(&empty, &mut out, &shift, interpolation, ColorL::new_l(7));
+            assert_eq!(out.as_storage(), &[7; 6][..]);
+            warp_perspective(&empty, &mut out, &Homography::from(shift), interpolation, ColorL::new_l(8));
+            assert_eq!(out.as_storage(), &[8; 6][..]);
+        }
+    }
+}
+
+#[test]
+fn quarter_turn_matches_geometry() {
+    use std::f64::consts::FRAC_PI_2;
+    use super::{Rgb, Pixel, ColorRGB};
+
+    let rgb = test_image(9, 5, 2);
+    let expected = super::geometry::rotate90(&rgb);
+    let turn = Affine::rotation(FRAC_PI_2).then(&Affine::translation(4.0, 0.0));
+    let mut out: Surface<Rgb, u8, Box<[u8]>> = Surface::new_black(5, 9);
+    for &interpolation in &INTERPOLATIONS {
+        warp_affine(&rgb, &mut out, &turn, interpolation, ColorRGB::white());
+        assert_eq!(out.as_storage(), expected.as_storage());
+    }
+}
+
+#[test]
+fn homography_from_quad() {
+    let from = [(10.0, 12.0), (90.0, 3.0), (95.0, 80.0), (4.0, 70.0)];
+    let to = [(0.0, 0.0), (100.0, 0.0), (100.0, 100.0), (0.0, 100.0)];
+    let h = Homography::from_quad(from, to).unwrap();
+    let inv = h.inverse().unwrap();
+    for (&(x, y), &(u, v)) in from.iter().zip(to.iter()) {
+        let (hx, hy) = h.apply(x, y).unwrap();
+        assert!((hx - u).abs() < 1e-9 && (hy - v).abs() < 1e-9);
+        let (ix, iy) = inv.apply(u, v).unwrap();
+        assert!((ix - x).abs() < 1e-9 && (iy - y).abs() < 1e-9);
+    }
+
+    let collinear = [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (0.0, 5.0)];
+    assert!(Homography::from_quad(collinear, to).is_none());
+    let nan = [(f64::NAN, 0.0), (90.0, 3.0), (95.0, 80.0), (4.0, 70.0)];
+    assert!(Homography::from_quad(nan, to).is_none());
+    let far = [(0.0, 0.0), (f64::INFINITY, 0.0), (100.0, 100.0), (0.0, 100.0)];
+    assert!(Homography::from_quad(from, far).is_none());
+}
+
+#[test]
+fn affine_composition_and_inverse() {
+    let t = Affine::rotation_about(0.3, 5.0, 7.0)
+        .then(&Affine::shear(0.2, -0.1))
+        .then(&Affine::scale(2.0, 0.5));
+    let inv = t.inverse().unwrap();
+    let (x, y) = t.apply(3.0, -4.0);
+    let (bx, by) = inv.apply(x, y);
+    assert!((bx - 3.0).abs() < 1e-9 && (by + 4.0).abs() < 1e-9);
+
+    let (px, py) = Homography::from(t).apply(3.0, -4.0).unwrap();
+    assert!((px - x).abs() < 1e-9 && (py - y).abs() < 1e-9);
+    assert!(Affine::scale(0.0, 1.0).inverse().is_none());
+}